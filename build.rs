@@ -0,0 +1,30 @@
+use std::path::Path;
+use std::process::Command;
+
+// Exposes the commit being built as `GIT_SHA` for the `/version` endpoint.
+// Builds outside a checkout, e.g. in a container, can pass `GIT_SHA` in the
+// environment instead.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+
+    let sha = std::env::var("GIT_SHA")
+        .ok()
+        .or_else(|| {
+            let output = Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        })
+        .unwrap_or_else(|| "unknown".to_owned());
+
+    println!("cargo:rustc-env=GIT_SHA={}", sha);
+}
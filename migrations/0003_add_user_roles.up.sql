@@ -0,0 +1,7 @@
+DO $$ BEGIN
+    CREATE TYPE user_role AS ENUM ('admin', 'member');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role user_role NOT NULL DEFAULT 'member';
@@ -0,0 +1,4 @@
+ALTER TABLE tasks ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE tasks ALTER COLUMN status TYPE TEXT USING status::text;
+ALTER TABLE tasks DROP COLUMN completed_at;
+DROP TYPE task_status;
@@ -0,0 +1,30 @@
+DO $$ BEGIN
+    CREATE TYPE task_status AS ENUM ('todo', 'in_progress', 'blocked', 'done', 'cancelled');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS completed_at TIMESTAMP;
+
+-- Statuses used to be free text; map the spellings seen in the wild and
+-- fall back to 'todo'
+DO $$ BEGIN
+    IF (SELECT data_type FROM information_schema.columns
+        WHERE table_schema = current_schema()
+          AND table_name = 'tasks' AND column_name = 'status') = 'text' THEN
+        ALTER TABLE tasks ALTER COLUMN status TYPE task_status USING (
+            CASE lower(trim(status))
+                WHEN 'in_progress' THEN 'in_progress'
+                WHEN 'in progress' THEN 'in_progress'
+                WHEN 'blocked' THEN 'blocked'
+                WHEN 'done' THEN 'done'
+                WHEN 'completed' THEN 'done'
+                WHEN 'cancelled' THEN 'cancelled'
+                WHEN 'canceled' THEN 'cancelled'
+                ELSE 'todo'
+            END
+        )::task_status;
+        ALTER TABLE tasks ALTER COLUMN status SET DEFAULT 'todo';
+        UPDATE tasks SET completed_at = now() WHERE status = 'done' AND completed_at IS NULL;
+    END IF;
+END $$;
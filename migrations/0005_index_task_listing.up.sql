@@ -0,0 +1,9 @@
+-- Tasks created without a due date used to be stored with the Unix epoch
+UPDATE tasks SET due_date = NULL WHERE due_date = '1970-01-01 00:00:00';
+
+-- Filters and keyset pagination of get_user_tasks_db
+CREATE INDEX IF NOT EXISTS tasks_user_id_id_idx ON tasks (user_id, id);
+CREATE INDEX IF NOT EXISTS tasks_user_id_status_idx ON tasks (user_id, status);
+CREATE INDEX IF NOT EXISTS tasks_user_id_due_date_idx
+    ON tasks (user_id, (COALESCE(due_date, 'infinity'::timestamp)), id);
+CREATE INDEX IF NOT EXISTS tasks_user_id_title_idx ON tasks (user_id, title, id);
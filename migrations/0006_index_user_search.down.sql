@@ -0,0 +1,2 @@
+DROP INDEX users_email_prefix_idx;
+DROP INDEX users_username_prefix_idx;
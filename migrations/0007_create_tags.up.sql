@@ -0,0 +1,17 @@
+-- Labels a user defines once and attaches to any number of their tasks
+CREATE TABLE IF NOT EXISTS tags (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS task_tags (
+    task_id INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (task_id, tag_id)
+);
+
+-- The primary key covers lookups by task; this one serves the tag filter
+-- and tag deletion
+CREATE INDEX IF NOT EXISTS task_tags_tag_id_idx ON task_tags (tag_id);
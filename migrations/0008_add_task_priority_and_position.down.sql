@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS tasks_user_id_priority_idx;
+DROP INDEX IF EXISTS tasks_user_id_status_position_idx;
+ALTER TABLE tasks DROP COLUMN position;
+ALTER TABLE tasks DROP COLUMN priority;
+DROP TYPE task_priority;
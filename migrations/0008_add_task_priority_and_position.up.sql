@@ -0,0 +1,17 @@
+DO $$ BEGIN
+    CREATE TYPE task_priority AS ENUM ('low', 'medium', 'high', 'urgent');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS priority task_priority NOT NULL DEFAULT 'medium';
+
+-- Fractional positions within a status column, compared byte by byte
+-- whatever the database collation. Existing tasks keep their creation order.
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS position TEXT COLLATE "C" NOT NULL DEFAULT '';
+UPDATE tasks SET position = lpad(id::text, 10, '0') || 'V' WHERE position = '';
+ALTER TABLE tasks ALTER COLUMN position DROP DEFAULT;
+
+CREATE INDEX IF NOT EXISTS tasks_user_id_status_position_idx
+    ON tasks (user_id, status, position, id);
+CREATE INDEX IF NOT EXISTS tasks_user_id_priority_idx ON tasks (user_id, priority, id);
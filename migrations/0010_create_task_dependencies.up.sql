@@ -0,0 +1,12 @@
+-- "task_id cannot be completed until blocker_id is". Both tasks belong to
+-- the same user.
+CREATE TABLE IF NOT EXISTS task_dependencies (
+    task_id INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    blocker_id INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    PRIMARY KEY (task_id, blocker_id),
+    CHECK (task_id <> blocker_id)
+);
+
+-- The primary key covers lookups by blocked task; this one serves cycle
+-- checks and the cascade when a blocker is deleted
+CREATE INDEX IF NOT EXISTS task_dependencies_blocker_id_idx ON task_dependencies (blocker_id);
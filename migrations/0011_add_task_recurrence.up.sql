@@ -0,0 +1,2 @@
+-- An RRULE in canonical form, see `model::recurrence`
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS recurrence TEXT;
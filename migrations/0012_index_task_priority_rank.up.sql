@@ -0,0 +1,3 @@
+-- Nothing to do: task_priority is an enum that sorts in rank order, so
+-- tasks_user_id_priority_idx already serves the priority sort. The SQLite
+-- backend stores priorities as text and needs an expression index instead.
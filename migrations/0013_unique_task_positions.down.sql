@@ -0,0 +1,3 @@
+DROP INDEX tasks_user_id_status_position_key;
+CREATE INDEX IF NOT EXISTS tasks_user_id_status_position_idx
+    ON tasks (user_id, status, position, id);
@@ -0,0 +1,3 @@
+-- SQLite has no enums; a CHECK constraint keeps the same values as user_role
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'member'
+    CHECK (role IN ('admin', 'member'));
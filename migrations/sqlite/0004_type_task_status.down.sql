@@ -0,0 +1,14 @@
+CREATE TABLE tasks_untyped (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    due_date TEXT,
+    status TEXT NOT NULL,
+    user_id INTEGER REFERENCES users(id)
+);
+
+INSERT INTO tasks_untyped (id, title, description, due_date, status, user_id)
+SELECT id, title, description, due_date, status, user_id FROM tasks;
+
+DROP TABLE tasks;
+ALTER TABLE tasks_untyped RENAME TO tasks;
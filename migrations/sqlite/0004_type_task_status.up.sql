@@ -0,0 +1,34 @@
+-- Changing a column's constraints needs a table rebuild in SQLite. Statuses
+-- use the values of the task_status enum, with the legacy spellings mapped
+-- as in the Postgres migration.
+CREATE TABLE tasks_typed (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    due_date TEXT,
+    status TEXT NOT NULL DEFAULT 'todo'
+        CHECK (status IN ('todo', 'in_progress', 'blocked', 'done', 'cancelled')),
+    completed_at TEXT,
+    user_id INTEGER REFERENCES users(id)
+);
+
+INSERT INTO tasks_typed (id, title, description, due_date, status, completed_at, user_id)
+SELECT id, title, description, due_date, status, NULL, user_id FROM (
+    SELECT id, title, description, due_date, user_id,
+        CASE lower(trim(status))
+            WHEN 'in_progress' THEN 'in_progress'
+            WHEN 'in progress' THEN 'in_progress'
+            WHEN 'blocked' THEN 'blocked'
+            WHEN 'done' THEN 'done'
+            WHEN 'completed' THEN 'done'
+            WHEN 'cancelled' THEN 'cancelled'
+            WHEN 'canceled' THEN 'cancelled'
+            ELSE 'todo'
+        END AS status
+    FROM tasks
+);
+
+UPDATE tasks_typed SET completed_at = strftime('%Y-%m-%d %H:%M:%f', 'now') WHERE status = 'done';
+
+DROP TABLE tasks;
+ALTER TABLE tasks_typed RENAME TO tasks;
@@ -0,0 +1,4 @@
+DROP INDEX tasks_user_id_title_idx;
+DROP INDEX tasks_user_id_due_date_idx;
+DROP INDEX tasks_user_id_status_idx;
+DROP INDEX tasks_user_id_id_idx;
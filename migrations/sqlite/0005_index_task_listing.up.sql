@@ -0,0 +1,6 @@
+-- Filters and keyset pagination of the task listing. Due dates are stored as
+-- text, so 'infinity' sorts after every date like in Postgres.
+CREATE INDEX tasks_user_id_id_idx ON tasks (user_id, id);
+CREATE INDEX tasks_user_id_status_idx ON tasks (user_id, status);
+CREATE INDEX tasks_user_id_due_date_idx ON tasks (user_id, COALESCE(due_date, 'infinity'), id);
+CREATE INDEX tasks_user_id_title_idx ON tasks (user_id, title, id);
@@ -0,0 +1,3 @@
+-- Prefix search of the user listing
+CREATE INDEX users_username_prefix_idx ON users (lower(username));
+CREATE INDEX users_email_prefix_idx ON users (lower(email));
@@ -0,0 +1,4 @@
+DROP INDEX tasks_user_id_priority_idx;
+DROP INDEX tasks_user_id_status_position_idx;
+ALTER TABLE tasks DROP COLUMN position;
+ALTER TABLE tasks DROP COLUMN priority;
@@ -0,0 +1,10 @@
+ALTER TABLE tasks ADD COLUMN priority TEXT NOT NULL DEFAULT 'medium'
+    CHECK (priority IN ('low', 'medium', 'high', 'urgent'));
+
+-- Text compares byte by byte by default, as positions require. Existing tasks
+-- keep their creation order.
+ALTER TABLE tasks ADD COLUMN position TEXT NOT NULL DEFAULT '';
+UPDATE tasks SET position = printf('%010dV', id);
+
+CREATE INDEX tasks_user_id_status_position_idx ON tasks (user_id, status, position, id);
+CREATE INDEX tasks_user_id_priority_idx ON tasks (user_id, priority, id);
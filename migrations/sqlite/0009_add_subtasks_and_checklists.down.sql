@@ -0,0 +1,39 @@
+DROP TABLE checklist_items;
+DROP INDEX tasks_parent_id_idx;
+
+-- SQLite cannot drop a column used in a foreign key, so the table is rebuilt
+-- without it. Dropping the old table deletes the tags of its tasks, which are
+-- put back afterwards.
+CREATE TEMP TABLE task_tags_saved AS SELECT task_id, tag_id FROM task_tags;
+
+CREATE TABLE tasks_unnested (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    due_date TEXT,
+    status TEXT NOT NULL DEFAULT 'todo'
+        CHECK (status IN ('todo', 'in_progress', 'blocked', 'done', 'cancelled')),
+    completed_at TEXT,
+    user_id INTEGER REFERENCES users(id),
+    priority TEXT NOT NULL DEFAULT 'medium'
+        CHECK (priority IN ('low', 'medium', 'high', 'urgent')),
+    position TEXT NOT NULL DEFAULT ''
+);
+
+INSERT INTO tasks_unnested (id, title, description, due_date, status, completed_at, user_id,
+    priority, position)
+SELECT id, title, description, due_date, status, completed_at, user_id, priority, position
+FROM tasks;
+
+DROP TABLE tasks;
+ALTER TABLE tasks_unnested RENAME TO tasks;
+
+CREATE INDEX tasks_user_id_id_idx ON tasks (user_id, id);
+CREATE INDEX tasks_user_id_status_idx ON tasks (user_id, status);
+CREATE INDEX tasks_user_id_due_date_idx ON tasks (user_id, COALESCE(due_date, 'infinity'), id);
+CREATE INDEX tasks_user_id_title_idx ON tasks (user_id, title, id);
+CREATE INDEX tasks_user_id_status_position_idx ON tasks (user_id, status, position, id);
+CREATE INDEX tasks_user_id_priority_idx ON tasks (user_id, priority, id);
+
+INSERT INTO task_tags (task_id, tag_id) SELECT task_id, tag_id FROM task_tags_saved;
+DROP TABLE task_tags_saved;
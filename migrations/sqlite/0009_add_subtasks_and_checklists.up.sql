@@ -0,0 +1,11 @@
+-- Deleting a task deletes its subtasks, all the way down
+ALTER TABLE tasks ADD COLUMN parent_id INTEGER REFERENCES tasks(id) ON DELETE CASCADE;
+CREATE INDEX tasks_parent_id_idx ON tasks (parent_id);
+
+CREATE TABLE checklist_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id INTEGER NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    done BOOLEAN NOT NULL DEFAULT FALSE
+);
+CREATE INDEX checklist_items_task_id_idx ON checklist_items (task_id, id);
@@ -0,0 +1,2 @@
+DROP INDEX tasks_user_id_priority_rank_idx;
+CREATE INDEX tasks_user_id_priority_idx ON tasks (user_id, priority, id);
@@ -0,0 +1,9 @@
+-- Priorities are stored as text, so the priority sort orders by their rank
+-- rather than by the column. The index has to use the exact expression of
+-- the listing query to serve it.
+DROP INDEX tasks_user_id_priority_idx;
+CREATE INDEX tasks_user_id_priority_rank_idx ON tasks (
+    user_id,
+    (CASE priority WHEN 'low' THEN 0 WHEN 'medium' THEN 1 WHEN 'high' THEN 2 ELSE 3 END),
+    id
+);
@@ -0,0 +1,2 @@
+DROP INDEX tasks_user_id_status_position_key;
+CREATE INDEX tasks_user_id_status_position_idx ON tasks (user_id, status, position, id);
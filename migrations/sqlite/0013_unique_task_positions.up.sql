@@ -0,0 +1,19 @@
+-- A status change used to keep the task's position, so a column may hold
+-- equal positions. Renumber every column that does, in its current order,
+-- before positions become unique within a column.
+UPDATE tasks SET position = renumbered.position
+FROM (
+    SELECT id, printf('%010dV', row_number() OVER (
+        PARTITION BY user_id, status ORDER BY position, id
+    )) AS position
+    FROM tasks
+    WHERE (user_id, status) IN (
+        SELECT user_id, status FROM tasks
+        GROUP BY user_id, status, position
+        HAVING COUNT(*) > 1
+    )
+) AS renumbered
+WHERE tasks.id = renumbered.id;
+
+DROP INDEX tasks_user_id_status_position_idx;
+CREATE UNIQUE INDEX tasks_user_id_status_position_key ON tasks (user_id, status, position);
@@ -0,0 +1,360 @@
+use chrono::{Duration, Utc};
+use clap::{Parser, Subcommand};
+use dotenv::dotenv;
+use rand::distributions::{Alphanumeric, DistString};
+use sqlx::PgPool;
+use std::error::Error;
+use std::process::ExitCode;
+use task_management_actix_web::config::settings::Settings;
+use task_management_actix_web::db::db::{
+    create_task_db, create_user_db, get_user_by_username_db, reset_user_password_db,
+    set_user_role_db,
+};
+use task_management_actix_web::db::migrations::{
+    applied_migrations, check_applied, rollback_migrations, run_migrations, MIGRATIONS,
+};
+use task_management_actix_web::db::server::create_pool;
+use task_management_actix_web::model::models::{
+    Role, TaskInput, TaskPriority, TaskStatus, User, UserInput,
+};
+use validator::Validate;
+
+const DEMO_TASKS: &[(&str, &str, TaskStatus)] = &[
+    (
+        "Write project brief",
+        "Outline goals and scope",
+        TaskStatus::Done,
+    ),
+    (
+        "Review pull requests",
+        "Go through the open reviews",
+        TaskStatus::InProgress,
+    ),
+    (
+        "Plan sprint",
+        "Pick the stories for next sprint",
+        TaskStatus::Todo,
+    ),
+    (
+        "Fix login bug",
+        "Waiting on a reproduction from QA",
+        TaskStatus::Blocked,
+    ),
+    (
+        "Update dependencies",
+        "Bump crates to their latest versions",
+        TaskStatus::Todo,
+    ),
+    (
+        "Prepare demo",
+        "Slides for the Friday demo",
+        TaskStatus::Todo,
+    ),
+    (
+        "Archive old boards",
+        "No longer needed after the migration",
+        TaskStatus::Cancelled,
+    ),
+];
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+/// Administer the task management database
+#[derive(Parser)]
+#[command(name = "taskctl")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply, revert or list schema migrations
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Create and manage user accounts
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+    /// Create demo users, each with a handful of tasks
+    Seed {
+        /// Number of demo users to create
+        #[arg(long, default_value_t = 3)]
+        users: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Apply every pending migration
+    Run,
+    /// Revert migrations, by default only the latest applied one
+    Rollback {
+        /// Version to roll back to; 0 reverts every migration
+        #[arg(long)]
+        to: Option<i64>,
+    },
+    /// List known migrations and whether they are applied
+    Status,
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Create a user; a password is generated when none is given
+    Create {
+        username: String,
+        email: String,
+        #[arg(long)]
+        password: Option<String>,
+        /// Create the user as an admin
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Grant the admin role to a user
+    Promote { username: String },
+    /// Set a new password and sign the user out everywhere
+    ResetPassword {
+        username: String,
+        /// New password; a random one is generated when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+}
+
+fn generate_password() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 16)
+}
+
+fn is_unique_violation(error: &sqlx::Error) -> bool {
+    matches!(error, sqlx::Error::Database(db_error) if db_error.is_unique_violation())
+}
+
+async fn find_user(pool: &PgPool, username: &str) -> Result<User, Box<dyn Error>> {
+    match get_user_by_username_db(pool, username).await {
+        Ok(user) => Ok(user),
+        Err(sqlx::Error::RowNotFound) => Err(format!("No user named {}", username).into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Commands other than `migrate` expect the schema of this build
+async fn ensure_migrated(pool: &PgPool) -> CliResult {
+    let applied = applied_migrations(pool).await?;
+    check_applied(&applied, MIGRATIONS)?;
+
+    if applied.len() < MIGRATIONS.len() {
+        return Err("Database has pending migrations, run `taskctl migrate run` first".into());
+    }
+
+    Ok(())
+}
+
+async fn migrate(pool: &PgPool, command: MigrateCommand) -> CliResult {
+    match command {
+        MigrateCommand::Run => {
+            let ran = run_migrations(pool).await?;
+            if ran.is_empty() {
+                println!("Database is up to date");
+            }
+            for migration in ran {
+                println!("Applied {}", migration.name);
+            }
+        }
+        MigrateCommand::Rollback { to } => {
+            let target = match to {
+                Some(target) => target,
+                None => {
+                    let applied = applied_migrations(pool).await?;
+                    let mut versions = applied.iter().rev().map(|a| a.version);
+                    versions.next();
+                    versions.next().unwrap_or(0)
+                }
+            };
+
+            let reverted = rollback_migrations(pool, target).await?;
+            if reverted.is_empty() {
+                println!("Nothing to roll back");
+            }
+            for migration in reverted {
+                println!("Reverted {}", migration.name);
+            }
+        }
+        MigrateCommand::Status => {
+            let applied = applied_migrations(pool).await?;
+            for migration in MIGRATIONS {
+                let state = match applied.iter().find(|a| a.version == migration.version) {
+                    Some(record) if record.checksum != migration.checksum() => {
+                        format!("applied {} (checksum mismatch)", record.applied_at)
+                    }
+                    Some(record) => format!("applied {}", record.applied_at),
+                    None => "pending".to_owned(),
+                };
+                println!(
+                    "{:>4}  {:<32}  {}",
+                    migration.version, migration.name, state
+                );
+            }
+            for record in applied
+                .iter()
+                .filter(|a| MIGRATIONS.iter().all(|m| m.version != a.version))
+            {
+                println!(
+                    "{:>4}  {:<32}  applied {} (unknown to this build)",
+                    record.version, record.name, record.applied_at
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn user(pool: &PgPool, command: UserCommand) -> CliResult {
+    ensure_migrated(pool).await?;
+
+    match command {
+        UserCommand::Create {
+            username,
+            email,
+            password,
+            admin,
+        } => {
+            let generated = password.is_none();
+            let input = UserInput {
+                username,
+                email,
+                password: password.unwrap_or_else(generate_password),
+            };
+            input.validate()?;
+
+            // The user and its role are created together or not at all
+            let mut tx = pool.begin().await?;
+            let user = match create_user_db(&mut *tx, &input).await {
+                Ok(user) => user,
+                Err(e) if is_unique_violation(&e) => {
+                    return Err("A user with this username or email already exists".into())
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if admin {
+                set_user_role_db(&mut *tx, user.id, Role::Admin).await?;
+            }
+            tx.commit().await?;
+
+            println!("Created user {} with id {}", user.username, user.id);
+            if generated {
+                println!("Password: {}", input.password);
+            }
+        }
+        UserCommand::Promote { username } => {
+            let user = find_user(pool, &username).await?;
+            set_user_role_db(pool, user.id, Role::Admin).await?;
+            println!("{} is now an admin", user.username);
+        }
+        UserCommand::ResetPassword { username, password } => {
+            let user = find_user(pool, &username).await?;
+            let generated = password.is_none();
+            let input = UserInput {
+                username: user.username,
+                email: user.email,
+                password: password.unwrap_or_else(generate_password),
+            };
+            input.validate()?;
+
+            reset_user_password_db(pool, user.id, &input.password).await?;
+            println!("Reset the password of {}", input.username);
+            if generated {
+                println!("Password: {}", input.password);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Existing demo users are left untouched, so seeding can be run repeatedly
+async fn seed(pool: &PgPool, users: u32) -> CliResult {
+    ensure_migrated(pool).await?;
+
+    let password = generate_password();
+    let mut created = 0;
+
+    for n in 1..=users {
+        let input = UserInput {
+            username: format!("demo{}", n),
+            email: format!("demo{}@example.com", n),
+            password: password.clone(),
+        };
+
+        // A failure leaves no half-seeded user behind to be skipped next time
+        let mut tx = pool.begin().await?;
+        let user = match create_user_db(&mut *tx, &input).await {
+            Ok(user) => user,
+            Err(e) if is_unique_violation(&e) => {
+                println!("Skipping {}, it already exists", input.username);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        for (i, (title, description, status)) in DEMO_TASKS.iter().enumerate() {
+            let task = TaskInput {
+                title: title.to_string(),
+                description: description.to_string(),
+                due_date: Some((Utc::now() + Duration::days(i as i64 * 2 - 3)).naive_utc()),
+                status: *status,
+                priority: TaskPriority::default(),
+                parent_id: None,
+                recurrence: None,
+            };
+            create_task_db(&mut *tx, &task, user.id).await?;
+        }
+        tx.commit().await?;
+
+        println!("Created {} with {} tasks", user.username, DEMO_TASKS.len());
+        created += 1;
+    }
+
+    if created > 0 {
+        println!("Demo users sign in with password: {}", password);
+    }
+
+    Ok(())
+}
+
+async fn run(command: Command, settings: &Settings) -> CliResult {
+    if settings.database.url.starts_with("sqlite:") {
+        return Err("taskctl supports Postgres only, DATABASE_URL points at SQLite".into());
+    }
+
+    match create_pool(&settings.database).await {
+        Ok(pool) => match command {
+            Command::Migrate { command } => migrate(&pool, command).await,
+            Command::User { command } => user(&pool, command).await,
+            Command::Seed { users } => seed(&pool, users).await,
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[actix_web::main]
+async fn main() -> ExitCode {
+    dotenv().ok();
+    let cli = Cli::parse();
+
+    let result = match Settings::load() {
+        Ok(settings) => run(cli.command, &settings).await,
+        Err(e) => Err(e.into()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use serde::Deserialize;
+use std::{env, fmt, fs, io};
+
+// Read when `CONFIG_FILE` is unset; a missing default file is not an error
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// Everything the server needs to start, loaded once by `Settings::load`.
+//
+// Values come from the built-in defaults, then the TOML file, then the
+// environment variables named next to each field, each layer overriding
+// the previous one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub database: DatabaseSettings,
+    pub auth: AuthSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    // BIND_ADDRESS
+    pub bind_address: String,
+    // CORS_ORIGINS, comma separated
+    pub cors_origins: Vec<String>,
+    // SHUTDOWN_TIMEOUT_SECS, how long in-flight requests may finish after
+    // a stop signal
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            bind_address: "127.0.0.1:8080".to_owned(),
+            cors_origins: vec!["http://localhost:3000".to_owned()],
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    // DATABASE_URL
+    pub url: String,
+    // DATABASE_MAX_CONNECTIONS
+    pub max_connections: u32,
+    // DATABASE_ACQUIRE_TIMEOUT_SECS, also bounds each connection attempt
+    // at startup
+    pub acquire_timeout_secs: u64,
+    // DATABASE_CONNECT_ATTEMPTS, tries at startup before giving up
+    pub connect_attempts: u32,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        DatabaseSettings {
+            url: String::new(),
+            max_connections: 10,
+            acquire_timeout_secs: 5,
+            connect_attempts: 5,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSettings {
+    // SECRET_KEY, signs the access tokens
+    pub secret_key: String,
+    // ACCESS_TOKEN_TTL_MINUTES; access tokens are short-lived and clients
+    // renew them with a refresh token
+    pub access_token_ttl_minutes: i64,
+    // REFRESH_TOKEN_TTL_DAYS
+    pub refresh_token_ttl_days: i64,
+}
+
+// Upper bounds of the token lifetimes, far beyond any sensible setting but
+// well within what expiry timestamps can represent
+const MAX_ACCESS_TOKEN_TTL_MINUTES: i64 = 7 * 24 * 60;
+const MAX_REFRESH_TOKEN_TTL_DAYS: i64 = 10 * 365;
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        AuthSettings {
+            secret_key: String::new(),
+            access_token_ttl_minutes: 15,
+            refresh_token_ttl_days: 30,
+        }
+    }
+}
+
+// Keeps the secret out of logs and panic messages
+impl fmt::Debug for AuthSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuthSettings")
+            .field("secret_key", &"<redacted>")
+            .field("access_token_ttl_minutes", &self.access_token_ttl_minutes)
+            .field("refresh_token_ttl_days", &self.refresh_token_ttl_days)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Read {
+        path: String,
+        source: io::Error,
+    },
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+    // One message per invalid value, so every problem is reported at once
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Read { path, source } => {
+                write!(f, "cannot read config file {}: {}", path, source)
+            }
+            SettingsError::Parse { path, source } => {
+                write!(f, "invalid config file {}: {}", path, source)
+            }
+            SettingsError::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    // Reads the file named by `CONFIG_FILE`, or `config.toml` when present,
+    // and applies the process environment on top
+    pub fn load() -> Result<Self, SettingsError> {
+        let (path, required) = match env::var("CONFIG_FILE") {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_FILE.to_owned(), false),
+        };
+
+        let file = match fs::read_to_string(&path) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => None,
+            Err(source) => return Err(SettingsError::Read { path, source }),
+        };
+
+        let settings = match file {
+            Some(contents) => {
+                toml::from_str(&contents).map_err(|source| SettingsError::Parse { path, source })?
+            }
+            None => Settings::default(),
+        };
+
+        settings.with_overrides(|key| env::var(key).ok())
+    }
+
+    // Applies the environment overrides returned by `var`, then validates
+    pub fn with_overrides(
+        mut self,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, SettingsError> {
+        let mut problems = Vec::new();
+
+        if let Some(value) = var("BIND_ADDRESS") {
+            self.server.bind_address = value;
+        }
+        if let Some(value) = var("CORS_ORIGINS") {
+            self.server.cors_origins = value
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        if let Some(value) = var("SHUTDOWN_TIMEOUT_SECS") {
+            parse_into(
+                &mut self.server.shutdown_timeout_secs,
+                "SHUTDOWN_TIMEOUT_SECS",
+                &value,
+                &mut problems,
+            );
+        }
+        if let Some(value) = var("DATABASE_URL") {
+            self.database.url = value;
+        }
+        if let Some(value) = var("DATABASE_MAX_CONNECTIONS") {
+            parse_into(
+                &mut self.database.max_connections,
+                "DATABASE_MAX_CONNECTIONS",
+                &value,
+                &mut problems,
+            );
+        }
+        if let Some(value) = var("DATABASE_ACQUIRE_TIMEOUT_SECS") {
+            parse_into(
+                &mut self.database.acquire_timeout_secs,
+                "DATABASE_ACQUIRE_TIMEOUT_SECS",
+                &value,
+                &mut problems,
+            );
+        }
+        if let Some(value) = var("DATABASE_CONNECT_ATTEMPTS") {
+            parse_into(
+                &mut self.database.connect_attempts,
+                "DATABASE_CONNECT_ATTEMPTS",
+                &value,
+                &mut problems,
+            );
+        }
+        if let Some(value) = var("SECRET_KEY") {
+            self.auth.secret_key = value;
+        }
+        if let Some(value) = var("ACCESS_TOKEN_TTL_MINUTES") {
+            parse_into(
+                &mut self.auth.access_token_ttl_minutes,
+                "ACCESS_TOKEN_TTL_MINUTES",
+                &value,
+                &mut problems,
+            );
+        }
+        if let Some(value) = var("REFRESH_TOKEN_TTL_DAYS") {
+            parse_into(
+                &mut self.auth.refresh_token_ttl_days,
+                "REFRESH_TOKEN_TTL_DAYS",
+                &value,
+                &mut problems,
+            );
+        }
+
+        problems.extend(self.problems());
+        if problems.is_empty() {
+            Ok(self)
+        } else {
+            Err(SettingsError::Invalid(problems))
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let port = self
+            .server
+            .bind_address
+            .rsplit_once(':')
+            .and_then(|(host, port)| (!host.is_empty()).then_some(port));
+        if port.and_then(|port| port.parse::<u16>().ok()).is_none() {
+            problems.push(format!(
+                "server.bind_address must look like host:port, got {:?}",
+                self.server.bind_address
+            ));
+        }
+        for origin in &self.server.cors_origins {
+            if !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                problems.push(format!(
+                    "server.cors_origins must be http(s) origins, got {:?}",
+                    origin
+                ));
+            }
+        }
+
+        if self.database.url.is_empty() {
+            problems.push("database.url (DATABASE_URL) must be set".to_owned());
+        } else if !["postgres:", "postgresql:", "sqlite:"]
+            .iter()
+            .any(|scheme| self.database.url.starts_with(scheme))
+        {
+            problems
+                .push("database.url (DATABASE_URL) must be a postgres: or sqlite: URL".to_owned());
+        }
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".to_owned());
+        }
+        if self.database.acquire_timeout_secs == 0 {
+            problems.push("database.acquire_timeout_secs must be at least 1".to_owned());
+        }
+        if self.database.connect_attempts == 0 {
+            problems.push("database.connect_attempts must be at least 1".to_owned());
+        }
+
+        if self.auth.secret_key.is_empty() {
+            problems.push("auth.secret_key (SECRET_KEY) must be set".to_owned());
+        }
+        if !(1..=MAX_ACCESS_TOKEN_TTL_MINUTES).contains(&self.auth.access_token_ttl_minutes) {
+            problems.push(format!(
+                "auth.access_token_ttl_minutes must be between 1 and {}",
+                MAX_ACCESS_TOKEN_TTL_MINUTES
+            ));
+        }
+        if !(1..=MAX_REFRESH_TOKEN_TTL_DAYS).contains(&self.auth.refresh_token_ttl_days) {
+            problems.push(format!(
+                "auth.refresh_token_ttl_days must be between 1 and {}",
+                MAX_REFRESH_TOKEN_TTL_DAYS
+            ));
+        }
+
+        problems
+    }
+}
+
+fn parse_into<T: std::str::FromStr>(
+    field: &mut T,
+    key: &str,
+    value: &str,
+    problems: &mut Vec<String>,
+) {
+    match value.trim().parse() {
+        Ok(parsed) => *field = parsed,
+        Err(_) => problems.push(format!("{} must be a number, got {:?}", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_environment_overrides_file() {
+        let file: Settings = toml::from_str(
+            r#"
+            [server]
+            bind_address = "0.0.0.0:9000"
+
+            [database]
+            url = "postgres://localhost/tasks"
+            max_connections = 4
+
+            [auth]
+            secret_key = "from-file"
+            "#,
+        )
+        .unwrap();
+
+        let settings = file
+            .with_overrides(env(&[
+                ("SECRET_KEY", "from-env"),
+                ("CORS_ORIGINS", "https://a.example, https://b.example"),
+                ("ACCESS_TOKEN_TTL_MINUTES", "5"),
+            ]))
+            .unwrap();
+
+        assert_eq!(settings.server.bind_address, "0.0.0.0:9000");
+        assert_eq!(
+            settings.server.cors_origins,
+            ["https://a.example", "https://b.example"]
+        );
+        assert_eq!(settings.database.max_connections, 4);
+        assert_eq!(settings.auth.secret_key, "from-env");
+        assert_eq!(settings.auth.access_token_ttl_minutes, 5);
+        assert_eq!(settings.auth.refresh_token_ttl_days, 30);
+    }
+
+    #[test]
+    fn test_invalid_settings_report_every_problem() {
+        let error = Settings::default()
+            .with_overrides(env(&[
+                ("BIND_ADDRESS", "8080"),
+                ("DATABASE_MAX_CONNECTIONS", "many"),
+            ]))
+            .unwrap_err();
+
+        let SettingsError::Invalid(problems) = error else {
+            panic!("expected validation problems, got {:?}", error);
+        };
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].starts_with("DATABASE_MAX_CONNECTIONS"));
+        assert!(problems.iter().any(|p| p.contains("DATABASE_URL")));
+        assert!(problems.iter().any(|p| p.contains("SECRET_KEY")));
+    }
+
+    #[test]
+    fn test_token_lifetimes_are_bounded() {
+        let mut settings = Settings::default();
+        settings.database.url = "postgres://localhost/tasks".to_owned();
+        settings.auth.secret_key = "secret".to_owned();
+        assert!(settings.problems().is_empty());
+
+        settings.auth.access_token_ttl_minutes = i64::MAX;
+        settings.auth.refresh_token_ttl_days = 0;
+        let problems = settings.problems();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("auth.access_token_ttl_minutes"));
+        assert!(problems[1].starts_with("auth.refresh_token_ttl_days"));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Settings>("[server]\nbind = \"0.0.0.0:80\"").is_err());
+    }
+
+    #[test]
+    fn test_debug_output_hides_secret() {
+        let auth = AuthSettings {
+            secret_key: "hunter2".to_owned(),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", auth).contains("hunter2"));
+    }
+}
@@ -1,9 +1,19 @@
-use crate::model::models::{Info, Task, TaskInput, TaskUpdate, User, UserInput};
+use crate::model::models::{
+    ChecklistItem, ChecklistItemInfo, ChecklistItemPatch, DependencyInfo, Info, InvalidParent,
+    Placement, RefreshToken, Role, SortOrder, Tag, TagInfo, Task, TaskCursor, TaskInput, TaskPatch,
+    TaskQuery, TaskSort, TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite, User, UserInput,
+    UserQuery,
+};
+use crate::model::position::{position_after, position_between};
 use bcrypt::{hash, verify, DEFAULT_COST};
-use chrono::NaiveDateTime;
-use sqlx::{Error, PgPool};
+use chrono::{DateTime, Utc};
+use sqlx::{Acquire, Error, PgConnection, PgExecutor, PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
 
-pub async fn create_user_db(pool: &PgPool, new_user: &UserInput) -> Result<User, Error> {
+pub async fn create_user_db(
+    executor: impl PgExecutor<'_>,
+    new_user: &UserInput,
+) -> Result<User, Error> {
     let password_hash = hash(&new_user.password, DEFAULT_COST)
         .map_err(|e| Error::protocol(format!("Bcrypt error: {}", e)))?;
 
@@ -11,153 +21,975 @@ pub async fn create_user_db(pool: &PgPool, new_user: &UserInput) -> Result<User,
         r#"
         INSERT INTO users (username, password_hash, email)
         VALUES ($1, $2, $3)
-        RETURNING id, username, password_hash, email
+        RETURNING id, username, password_hash, email, role AS "role: Role"
         "#,
         &new_user.username,
         &password_hash,
         &new_user.email,
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
 
-    println!("Running this function");
-
     Ok(User {
         id: record.id,
         username: record.username,
         password_hash: record.password_hash,
         email: record.email,
+        role: record.role,
     })
 }
 
-pub async fn get_all_users_db(pool: &PgPool) -> Result<Vec<User>, Error> {
-    let records = sqlx::query!(
+// Appends the `q` prefix filter of a user listing to a query ending in WHERE
+fn push_user_filter(query: &mut QueryBuilder<Postgres>, filter: &UserQuery) {
+    match filter.q.as_deref().filter(|q| !q.is_empty()) {
+        Some(q) => {
+            let prefix = format!("{}%", escape_like(&q.to_lowercase()));
+            query
+                .push("(lower(username) LIKE ")
+                .push_bind(prefix.clone())
+                .push(" OR lower(email) LIKE ")
+                .push_bind(prefix)
+                .push(")");
+        }
+        None => {
+            query.push("TRUE");
+        }
+    }
+}
+
+// Returns up to `limit` users matching `filter` in id order, starting after
+// the user with id `after_id` when paginating
+pub async fn get_all_users_db(
+    pool: &PgPool,
+    filter: &UserQuery,
+    after_id: Option<i32>,
+    limit: i64,
+) -> Result<Vec<User>, Error> {
+    let mut query: QueryBuilder<Postgres> =
+        QueryBuilder::new("SELECT id, username, password_hash, email, role FROM users WHERE ");
+    push_user_filter(&mut query, filter);
+    if let Some(after_id) = after_id {
+        query.push(" AND id > ").push_bind(after_id);
+    }
+    query.push(" ORDER BY id LIMIT ").push_bind(limit);
+
+    query.build_query_as::<User>().fetch_all(pool).await
+}
+
+pub async fn count_users_db(pool: &PgPool, filter: &UserQuery) -> Result<i64, Error> {
+    let mut query: QueryBuilder<Postgres> = QueryBuilder::new("SELECT COUNT(*) FROM users WHERE ");
+    push_user_filter(&mut query, filter);
+
+    let (total,): (i64,) = query.build_query_as().fetch_one(pool).await?;
+
+    Ok(total)
+}
+
+pub async fn get_user_role_db(pool: &PgPool, user_id: i32) -> Result<Role, Error> {
+    let record = sqlx::query!(
         r#"
-        SELECT id, username, password_hash, email FROM users
-        "#
+        SELECT role AS "role: Role" FROM users WHERE id = $1
+        "#,
+        user_id,
     )
-    .fetch_all(pool)
+    .fetch_one(pool)
     .await?;
 
-    let users: Vec<User> = records
-        .into_iter()
-        .map(|record| User {
-            id: record.id,
-            username: record.username,
-            password_hash: record.password_hash,
-            email: record.email,
-        })
-        .collect();
+    Ok(record.role)
+}
 
-    Ok(users)
+pub async fn set_user_role_db(
+    executor: impl PgExecutor<'_>,
+    user_id: i32,
+    role: Role,
+) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET role = $1 WHERE id = $2
+        "#,
+        role as Role,
+        user_id,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn get_user_by_username_db(pool: &PgPool, username: &str) -> Result<User, Error> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT id, username, password_hash, email, role AS "role: Role"
+        FROM users
+        WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_one(pool)
+    .await
 }
 
-pub async fn create_task_db(
+// Replaces the password and revokes every refresh token of the user, so
+// existing sessions end once their access token expires
+pub async fn reset_user_password_db(
     pool: &PgPool,
+    user_id: i32,
+    password: &str,
+) -> Result<u64, Error> {
+    let password_hash = hash(password, DEFAULT_COST)
+        .map_err(|e| Error::protocol(format!("Bcrypt error: {}", e)))?;
+
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET password_hash = $1 WHERE id = $2
+        "#,
+        &password_hash,
+        user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE refresh_tokens SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn create_task_db<'c>(
+    conn: impl Acquire<'c, Database = Postgres>,
+    new_task: &TaskInput,
+    user_id: i32,
+) -> Result<TaskWrite, Error> {
+    let mut tx = conn.begin().await?;
+    lock_user_tasks_db(&mut tx, user_id).await?;
+
+    if let Some(parent_id) = new_task.parent_id {
+        if let Some(invalid) = check_parent_db(&mut tx, user_id, None, parent_id).await? {
+            return Ok(TaskWrite::InvalidParent(invalid));
+        }
+    }
+
+    let mut task = insert_task_db(&mut tx, new_task, user_id).await?;
+    load_task_details_db(&mut tx, std::slice::from_mut(&mut task)).await?;
+
+    tx.commit().await?;
+
+    Ok(TaskWrite::Written(task))
+}
+
+// Inserts a task at the end of its column. The caller holds the lock on the
+// user's positions.
+async fn insert_task_db(
+    conn: &mut PgConnection,
     new_task: &TaskInput,
     user_id: i32,
 ) -> Result<Task, Error> {
-    let record = sqlx::query!(
+    // New tasks go to the end of their column
+    let position = end_of_column_db(conn, user_id, new_task.status, 0).await?;
+
+    sqlx::query_as(&format!(
+        "INSERT INTO tasks (title, description, due_date, status, completed_at, user_id, \
+         priority, position, parent_id, recurrence) \
+         VALUES ($1, $2, $3, $4, CASE WHEN $4::task_status = 'done' THEN now() AT TIME ZONE 'utc' END, \
+         $5, $6, $7, $8, $9) RETURNING {}",
+        TASK_COLUMNS
+    ))
+    .bind(&new_task.title)
+    .bind(&new_task.description)
+    .bind(new_task.due_date)
+    .bind(new_task.status)
+    .bind(user_id)
+    .bind(new_task.priority)
+    .bind(position)
+    .bind(new_task.parent_id)
+    .bind(&new_task.recurrence)
+    .fetch_one(conn)
+    .await
+}
+
+const TASK_COLUMNS: &str = "id, title, COALESCE(description, '') AS description, due_date, \
+     status, completed_at, user_id, priority, position, parent_id, recurrence";
+
+// Sort expression for each `TaskSort`; tasks without a due date sort last
+fn task_sort_key(sort: TaskSort) -> &'static str {
+    match sort {
+        TaskSort::Id => "id",
+        TaskSort::DueDate => "COALESCE(due_date, 'infinity'::timestamp)",
+        TaskSort::Title => "title",
+        TaskSort::Priority => "priority",
+    }
+}
+
+fn escape_like(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// Returns up to `limit` tasks of the user matching `filter`, ordered by
+// `filter.sort` and `filter.order` with `id` as tie-breaker, starting after
+// `after` when paginating
+pub async fn get_user_tasks_db(
+    pool: &PgPool,
+    user_id: i32,
+    filter: &TaskQuery,
+    after: Option<&TaskCursor>,
+    limit: i64,
+) -> Result<Vec<Task>, Error> {
+    let mut query: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+        "SELECT {} FROM tasks WHERE user_id = ",
+        TASK_COLUMNS
+    ));
+    query.push_bind(user_id);
+
+    if let Some(status) = filter.status {
+        query.push(" AND status = ").push_bind(status);
+    }
+    if let Some(priority) = filter.priority {
+        query.push(" AND priority = ").push_bind(priority);
+    }
+    if let Some(parent_id) = filter.parent_id {
+        query.push(" AND parent_id = ").push_bind(parent_id);
+    }
+    if let Some(due_before) = filter.due_before {
+        query.push(" AND due_date < ").push_bind(due_before);
+    }
+    if let Some(due_after) = filter.due_after {
+        query.push(" AND due_date > ").push_bind(due_after);
+    }
+    match filter.overdue {
+        Some(true) => {
+            query.push(
+                " AND due_date < now() AT TIME ZONE 'utc' AND status NOT IN ('done', 'cancelled')",
+            );
+        }
+        Some(false) => {
+            query.push(
+                " AND NOT COALESCE(due_date < now() AT TIME ZONE 'utc' \
+                 AND status NOT IN ('done', 'cancelled'), false)",
+            );
+        }
+        None => {}
+    }
+    if let Some(tag) = &filter.tag {
+        query
+            .push(
+                " AND EXISTS (SELECT 1 FROM task_tags JOIN tags ON tags.id = task_tags.tag_id \
+                 WHERE task_tags.task_id = tasks.id AND tags.name = ",
+            )
+            .push_bind(tag.clone())
+            .push(")");
+    }
+    if let Some(text) = filter.q.as_deref().filter(|text| !text.is_empty()) {
+        let pattern = format!("%{}%", escape_like(text));
+        query
+            .push(" AND (title ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR description ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+
+    let key = task_sort_key(filter.sort);
+    let (comparison, direction) = match filter.order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    if let Some(after) = after {
+        match filter.sort {
+            TaskSort::Id => {
+                query.push(format!(" AND id {} ", comparison));
+                query.push_bind(after.id);
+            }
+            TaskSort::DueDate => {
+                query.push(format!(" AND ({}, id) {} (COALESCE(", key, comparison));
+                query
+                    .push_bind(after.due_date)
+                    .push("::timestamp, 'infinity'::timestamp), ")
+                    .push_bind(after.id)
+                    .push(")");
+            }
+            TaskSort::Title => {
+                query.push(format!(" AND ({}, id) {} (", key, comparison));
+                query
+                    .push_bind(after.title.clone().unwrap_or_default())
+                    .push(", ")
+                    .push_bind(after.id)
+                    .push(")");
+            }
+            TaskSort::Priority => {
+                query.push(format!(" AND ({}, id) {} (", key, comparison));
+                query
+                    .push_bind(after.priority.unwrap_or_default())
+                    .push(", ")
+                    .push_bind(after.id)
+                    .push(")");
+            }
+        }
+    }
+
+    if filter.sort == TaskSort::Id {
+        query.push(format!(" ORDER BY id {}", direction));
+    } else {
+        query.push(format!(" ORDER BY {} {}, id {}", key, direction, direction));
+    }
+    query.push(" LIMIT ").push_bind(limit);
+
+    let mut tasks = query.build_query_as::<Task>().fetch_all(pool).await?;
+    load_task_details_db(&mut *pool.acquire().await?, &mut tasks).await?;
+
+    Ok(tasks)
+}
+
+// Fills in the tags, checklist, progress and blockers of every task, with
+// one query for each
+async fn load_task_details_db(conn: &mut PgConnection, tasks: &mut [Task]) -> Result<(), Error> {
+    if tasks.is_empty() {
+        return Ok(());
+    }
+
+    let task_ids: Vec<i32> = tasks.iter().map(|task| task.id).collect();
+    let rows = sqlx::query!(
         r#"
-        INSERT INTO tasks (title, description, due_date, status, user_id) VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, title, description, due_date, status, user_id
+        SELECT task_tags.task_id, tags.id, tags.name
+        FROM task_tags JOIN tags ON tags.id = task_tags.tag_id
+        WHERE task_tags.task_id = ANY($1)
+        ORDER BY tags.name, tags.id
         "#,
-        &new_task.title,
-        &new_task.description,
-        new_task.due_date.unwrap_or_else(|| NaiveDateTime::from_timestamp(0, 0)),
-        &new_task.status,
-        user_id,
+        &task_ids
     )
-    .fetch_one(pool)
+    .fetch_all(&mut *conn)
     .await?;
 
-    Ok(Task {
-        id: record.id,
-        title: record.title,
-        description: record.description.expect("Description is missing"),
-        due_date: record.due_date,
-        status: record.status,
-        user_id: record.user_id.expect("User ID is missing"),
-    })
+    for row in rows {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == row.task_id) {
+            task.tags.push(Tag {
+                id: row.id,
+                name: row.name,
+            });
+        }
+    }
+
+    let items = sqlx::query!(
+        r#"
+        SELECT task_id, id, title, done FROM checklist_items
+        WHERE task_id = ANY($1)
+        ORDER BY id
+        "#,
+        &task_ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for item in items {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == item.task_id) {
+            task.progress.total += 1;
+            task.progress.done += i64::from(item.done);
+            task.checklist.push(ChecklistItem {
+                id: item.id,
+                title: item.title,
+                done: item.done,
+            });
+        }
+    }
+
+    let subtasks = sqlx::query!(
+        r#"
+        SELECT parent_id AS "parent_id!", COUNT(*) AS "total!",
+            COUNT(*) FILTER (WHERE status = 'done') AS "done!"
+        FROM tasks
+        WHERE parent_id = ANY($1) AND status <> 'cancelled'
+        GROUP BY parent_id
+        "#,
+        &task_ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for subtask in subtasks {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == subtask.parent_id) {
+            task.progress.total += subtask.total;
+            task.progress.done += subtask.done;
+        }
+    }
+
+    let blockers = sqlx::query!(
+        r#"
+        SELECT task_dependencies.task_id, task_dependencies.blocker_id,
+            tasks.status NOT IN ('done', 'cancelled') AS "open!"
+        FROM task_dependencies JOIN tasks ON tasks.id = task_dependencies.blocker_id
+        WHERE task_dependencies.task_id = ANY($1)
+        ORDER BY task_dependencies.blocker_id
+        "#,
+        &task_ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for blocker in blockers {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == blocker.task_id) {
+            task.blocked_by.push(blocker.blocker_id);
+            task.blocked |= blocker.open;
+        }
+    }
+
+    Ok(())
 }
 
-pub async fn get_user_tasks_db(pool: &PgPool, user_id: i32) -> Result<Vec<Task>, Error> {
-    let records = sqlx::query!(
+// Ids of the parent, grandparent and so on of a task of the user, nearest
+// first. UNION rather than UNION ALL stops the walk should a cycle exist.
+async fn task_ancestors_db(
+    conn: &mut PgConnection,
+    user_id: i32,
+    task_id: i32,
+) -> Result<Vec<i32>, Error> {
+    let rows = sqlx::query!(
         r#"
-        SELECT id, title, description, due_date, status, user_id FROM tasks WHERE user_id = $1
+        WITH RECURSIVE ancestors (id, depth) AS (
+            SELECT parent_id, 1 FROM tasks
+            WHERE id = $1 AND user_id = $2 AND parent_id IS NOT NULL
+            UNION
+            SELECT tasks.parent_id, ancestors.depth + 1
+            FROM tasks JOIN ancestors ON tasks.id = ancestors.id
+            WHERE tasks.parent_id IS NOT NULL AND ancestors.depth < 100
+        )
+        SELECT id AS "id!", MIN(depth) AS "depth!" FROM ancestors
+        GROUP BY id
+        ORDER BY 2
         "#,
-        user_id,
+        task_id,
+        user_id
     )
-    .fetch_all(pool)
+    .fetch_all(conn)
     .await?;
 
-    let tasks: Vec<Task> = records
-        .into_iter()
-        .map(|record| Task {
-            id: record.id,
-            title: record.title,
-            description: record.description.expect("Description is missing"),
-            due_date: record.due_date,
-            status: record.status,
-            user_id: record.user_id.expect("User ID is missing"),
-        })
-        .collect();
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
 
-    Ok(tasks)
+// Levels of subtasks below a task of the user: 0 without subtasks, 1 with
+// subtasks only, and so on
+async fn subtask_depth_db(
+    conn: &mut PgConnection,
+    user_id: i32,
+    task_id: i32,
+) -> Result<usize, Error> {
+    let depth = sqlx::query_scalar!(
+        r#"
+        WITH RECURSIVE descendants (id, depth) AS (
+            SELECT id, 0 FROM tasks WHERE id = $1 AND user_id = $2
+            UNION
+            SELECT tasks.id, descendants.depth + 1
+            FROM tasks JOIN descendants ON tasks.parent_id = descendants.id
+            WHERE descendants.depth < 100
+        )
+        SELECT MAX(depth) FROM descendants
+        "#,
+        task_id,
+        user_id
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(depth.unwrap_or(0) as usize)
 }
 
-pub async fn get_user_task_db(pool: &PgPool, user_id: i32, task_id: i32) -> Result<Task, Error> {
-    let record = sqlx::query!(
+// Why the task `parent_id` of the user cannot become the parent of the task
+// `task_id`, or of a new task when `task_id` is `None`, if it cannot. Runs in
+// the transaction writing the parent, which holds the lock on the user's
+// tasks.
+async fn check_parent_db(
+    conn: &mut PgConnection,
+    user_id: i32,
+    task_id: Option<i32>,
+    parent_id: i32,
+) -> Result<Option<InvalidParent>, Error> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM tasks WHERE id = $1 AND user_id = $2) AS "exists!""#,
+        parent_id,
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !exists {
+        return Ok(Some(InvalidParent::Missing));
+    }
+
+    let ancestors = task_ancestors_db(conn, user_id, parent_id).await?;
+    let below = match task_id {
+        Some(task_id) => subtask_depth_db(conn, user_id, task_id).await?,
+        None => 0,
+    };
+    Ok(InvalidParent::check(task_id, parent_id, &ancestors, below))
+}
+
+// Key class of the advisory locks taken by writes that place tasks on a
+// user's board, nest them or link them, paired with the user id. Holding it
+// while reading the neighbouring positions and writing the new one keeps
+// concurrent writes from picking the same position; holding it while checking
+// the parents or dependencies and adding one keeps concurrent writes from
+// closing a cycle or nesting subtasks too deep.
+const TASK_POSITIONS_LOCK_CLASS: i32 = 1_952_543_339;
+
+async fn lock_user_tasks_db(conn: &mut PgConnection, user_id: i32) -> Result<(), Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock($1, $2)")
+        .bind(TASK_POSITIONS_LOCK_CLASS)
+        .bind(user_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// A position after every task in a status column of the user, other than
+// the task with id `exclude`
+async fn end_of_column_db(
+    conn: &mut PgConnection,
+    user_id: i32,
+    status: TaskStatus,
+    exclude: i32,
+) -> Result<String, Error> {
+    let last = neighbor_position_db(conn, user_id, status, None, SortOrder::Desc, exclude).await?;
+    Ok(position_after(last.as_deref()))
+}
+
+// Marks the subtasks of the task, at any level, that the workflow lets move
+// to done as done; blocked ones stay blocked. They are appended to the done
+// column in their board order, and recurring ones get their next occurrence.
+// Runs in the transaction completing the task, which holds the lock on the
+// user's positions.
+async fn complete_subtasks_db(conn: &mut PgConnection, info: &Info) -> Result<(), Error> {
+    let ids = sqlx::query_scalar!(
+        r#"
+        WITH RECURSIVE descendants (id) AS (
+            SELECT id FROM tasks WHERE parent_id = $1 AND user_id = $2
+            UNION
+            SELECT tasks.id FROM tasks JOIN descendants ON tasks.parent_id = descendants.id
+        )
+        SELECT id FROM tasks
+        WHERE id IN (SELECT id FROM descendants) AND status IN ('todo', 'in_progress')
+        ORDER BY position, id
+        "#,
+        info.task_id,
+        info.user_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut position = end_of_column_db(conn, info.user_id, TaskStatus::Done, 0).await?;
+    for id in &ids {
+        let mut task: Task = sqlx::query_as(&format!(
+            "UPDATE tasks \
+             SET status = 'done', position = $1, \
+                 completed_at = COALESCE(completed_at, now() AT TIME ZONE 'utc') \
+             WHERE id = $2 RETURNING {}",
+            TASK_COLUMNS
+        ))
+        .bind(&position)
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+        schedule_next_occurrence_db(conn, &mut task).await?;
+        position = position_after(Some(&position));
+    }
+
+    Ok(())
+}
+
+// Completing a recurring task creates its next occurrence: a `todo` copy
+// with the next due date, the same tags and the checklist unticked. The
+// recurrence moves over to the copy, so reopening and completing the task
+// again does not create another one. Runs in the transaction completing the
+// task, which holds the lock on the user's positions.
+async fn schedule_next_occurrence_db(
+    conn: &mut PgConnection,
+    task: &mut Task,
+) -> Result<(), Error> {
+    if task.recurrence.is_none() || task.due_date.is_none() {
+        return Ok(());
+    }
+
+    if let Some(next) = task.next_occurrence().map_err(Error::protocol)? {
+        let copy = insert_task_db(conn, &next, task.user_id).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO task_tags (task_id, tag_id)
+            SELECT $1, tag_id FROM task_tags WHERE task_id = $2
+            "#,
+            copy.id,
+            task.id
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO checklist_items (task_id, title)
+            SELECT $1, title FROM checklist_items WHERE task_id = $2
+            ORDER BY id
+            "#,
+            copy.id,
+            task.id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query!(
         r#"
-        SELECT id, title, description, due_date, status, user_id FROM tasks WHERE user_id = $1 AND id = $2
+        UPDATE tasks SET recurrence = NULL WHERE id = $1
         "#,
-        user_id, task_id
+        task.id
     )
+    .execute(conn)
+    .await?;
+    task.recurrence = None;
+
+    Ok(())
+}
+
+pub async fn get_user_task_db(pool: &PgPool, user_id: i32, task_id: i32) -> Result<Task, Error> {
+    let mut task: Task = sqlx::query_as(&format!(
+        "SELECT {} FROM tasks WHERE user_id = $1 AND id = $2",
+        TASK_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(task_id)
     .fetch_one(pool)
     .await?;
+    load_task_details_db(&mut *pool.acquire().await?, std::slice::from_mut(&mut task)).await?;
 
-    Ok(Task {
-        id: record.id,
-        title: record.title,
-        description: record.description.expect("Description is missing"),
-        due_date: record.due_date,
-        status: record.status,
-        user_id: record.user_id.expect("User ID is missing"),
-    })
+    Ok(task)
 }
 
+// Only applies while the task is still in `current_status`, so a concurrent
+// status change surfaces as `RowNotFound` instead of skipping the workflow.
+// Completing the task completes its subtasks along with it and, should it
+// recur, creates its next occurrence.
 pub async fn update_user_task_db(
     pool: &PgPool,
     info: Info,
     task_update: TaskUpdate,
-) -> Result<Task, Error> {
-    let record = sqlx::query!(
+    current_status: TaskStatus,
+) -> Result<TaskWrite, Error> {
+    let mut tx = pool.begin().await?;
+    lock_user_tasks_db(&mut tx, info.user_id).await?;
+
+    if task_update.status == TaskStatus::Done && current_status != TaskStatus::Done {
+        let blockers = open_blockers_db(&mut tx, info.user_id, info.task_id).await?;
+        if !blockers.is_empty() {
+            return Ok(TaskWrite::Blocked(blockers));
+        }
+    }
+
+    // A task changing status goes to the end of its new column
+    let position = match task_update.status != current_status {
+        true => Some(end_of_column_db(&mut tx, info.user_id, task_update.status, 0).await?),
+        false => None,
+    };
+
+    let mut task: Task = sqlx::query_as(&format!(
+        "UPDATE tasks \
+         SET title = $1, description = $2, due_date = $3, status = $4, \
+             completed_at = CASE WHEN $4::task_status = 'done' \
+                 THEN COALESCE(completed_at, now() AT TIME ZONE 'utc') END, \
+             priority = $8, recurrence = $9, position = COALESCE($10, position) \
+         WHERE id = $5 AND user_id = $6 AND status = $7 \
+         RETURNING {}",
+        TASK_COLUMNS
+    ))
+    .bind(task_update.title)
+    .bind(task_update.description)
+    .bind(task_update.due_date)
+    .bind(task_update.status)
+    .bind(info.task_id)
+    .bind(info.user_id)
+    .bind(current_status)
+    .bind(task_update.priority)
+    .bind(task_update.recurrence)
+    .bind(position)
+    .fetch_one(&mut *tx)
+    .await?;
+    if task.status == TaskStatus::Done && current_status != TaskStatus::Done {
+        complete_subtasks_db(&mut tx, &info).await?;
+        schedule_next_occurrence_db(&mut tx, &mut task).await?;
+    }
+    load_task_details_db(&mut tx, std::slice::from_mut(&mut task)).await?;
+
+    tx.commit().await?;
+
+    Ok(TaskWrite::Written(task))
+}
+
+// Updates only the columns supplied in `patch`. Like `update_user_task_db`,
+// a status change only applies while the task is still in `current_status`,
+// moves the task to the end of its new column and, to done, completes its
+// subtasks and schedules its next occurrence. `current_status` is `None`
+// when the patch leaves the status alone.
+pub async fn patch_user_task_db(
+    pool: &PgPool,
+    info: Info,
+    patch: TaskPatch,
+    current_status: Option<TaskStatus>,
+) -> Result<TaskWrite, Error> {
+    if patch.is_empty() {
+        return get_user_task_db(pool, info.user_id, info.task_id)
+            .await
+            .map(TaskWrite::Written);
+    }
+
+    let mut tx = pool.begin().await?;
+    lock_user_tasks_db(&mut tx, info.user_id).await?;
+
+    if let Some(Some(parent_id)) = patch.parent_id {
+        let invalid = check_parent_db(&mut tx, info.user_id, Some(info.task_id), parent_id).await?;
+        if let Some(invalid) = invalid {
+            return Ok(TaskWrite::InvalidParent(invalid));
+        }
+    }
+    if patch.status == Some(TaskStatus::Done) && current_status != Some(TaskStatus::Done) {
+        let blockers = open_blockers_db(&mut tx, info.user_id, info.task_id).await?;
+        if !blockers.is_empty() {
+            return Ok(TaskWrite::Blocked(blockers));
+        }
+    }
+
+    let position = match patch
+        .status
+        .filter(|status| Some(*status) != current_status)
+    {
+        Some(status) => Some(end_of_column_db(&mut tx, info.user_id, status, 0).await?),
+        None => None,
+    };
+
+    let mut query: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE tasks SET ");
+    let mut columns = query.separated(", ");
+    if let Some(title) = patch.title {
+        columns.push("title = ").push_bind_unseparated(title);
+    }
+    if let Some(description) = patch.description {
+        columns
+            .push("description = ")
+            .push_bind_unseparated(description);
+    }
+    if let Some(due_date) = patch.due_date {
+        columns.push("due_date = ").push_bind_unseparated(due_date);
+    }
+    if let Some(status) = patch.status {
+        columns.push("status = ").push_bind_unseparated(status);
+        columns
+            .push("completed_at = CASE WHEN ")
+            .push_bind_unseparated(status)
+            .push_unseparated(
+                "::task_status = 'done' THEN COALESCE(completed_at, now() AT TIME ZONE 'utc') END",
+            );
+    }
+    if let Some(position) = position {
+        columns.push("position = ").push_bind_unseparated(position);
+    }
+    if let Some(priority) = patch.priority {
+        columns.push("priority = ").push_bind_unseparated(priority);
+    }
+    if let Some(parent_id) = patch.parent_id {
+        columns
+            .push("parent_id = ")
+            .push_bind_unseparated(parent_id);
+    }
+    if let Some(recurrence) = patch.recurrence {
+        columns
+            .push("recurrence = ")
+            .push_bind_unseparated(recurrence);
+    }
+
+    query
+        .push(" WHERE id = ")
+        .push_bind(info.task_id)
+        .push(" AND user_id = ")
+        .push_bind(info.user_id);
+    if let Some(current_status) = current_status {
+        query.push(" AND status = ").push_bind(current_status);
+    }
+    query.push(format!(" RETURNING {}", TASK_COLUMNS));
+
+    let mut task = query.build_query_as::<Task>().fetch_one(&mut *tx).await?;
+    if patch.status == Some(TaskStatus::Done) && current_status != Some(TaskStatus::Done) {
+        complete_subtasks_db(&mut tx, &info).await?;
+        schedule_next_occurrence_db(&mut tx, &mut task).await?;
+    }
+    load_task_details_db(&mut tx, std::slice::from_mut(&mut task)).await?;
+
+    tx.commit().await?;
+
+    Ok(TaskWrite::Written(task))
+}
+
+// The position next to `from` in a status column of the user: the next
+// greater one for `SortOrder::Asc`, the next smaller one for `Desc`. Without
+// `from`, the first or last position of the column. The task with id
+// `exclude` is skipped, so a task being moved is never its own neighbour.
+async fn neighbor_position_db(
+    conn: &mut PgConnection,
+    user_id: i32,
+    status: TaskStatus,
+    from: Option<&str>,
+    order: SortOrder,
+    exclude: i32,
+) -> Result<Option<String>, Error> {
+    let (comparison, direction) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    let mut query: QueryBuilder<Postgres> =
+        QueryBuilder::new("SELECT position FROM tasks WHERE user_id = ");
+    query
+        .push_bind(user_id)
+        .push(" AND status = ")
+        .push_bind(status)
+        .push(" AND id <> ")
+        .push_bind(exclude);
+    if let Some(from) = from {
+        query
+            .push(format!(" AND position {} ", comparison))
+            .push_bind(from.to_owned());
+    }
+    query.push(format!(" ORDER BY position {} LIMIT 1", direction));
+
+    query.build_query_scalar().fetch_optional(conn).await
+}
+
+// The position `placement` stands for in the `status` column, for the task
+// with id `task_id`
+async fn placement_position_db(
+    conn: &mut PgConnection,
+    user_id: i32,
+    task_id: i32,
+    status: TaskStatus,
+    placement: Placement,
+) -> Result<String, Error> {
+    let anchor_id = match placement {
+        Placement::Before(anchor_id) | Placement::After(anchor_id) => anchor_id,
+        Placement::End => return end_of_column_db(conn, user_id, status, task_id).await,
+    };
+    let anchor = sqlx::query_scalar!(
         r#"
-        UPDATE tasks
-        SET title = $1, description = $2, due_date = $3, status = $4
-        WHERE id = $5 AND user_id = $6
-        RETURNING id, title, description, due_date, status, user_id
+        SELECT position FROM tasks WHERE user_id = $1 AND id = $2 AND status = $3
         "#,
-        task_update.title,
-        task_update.description,
-        task_update.due_date,
-        task_update.status,
-        info.task_id,
-        info.user_id,
+        user_id,
+        anchor_id,
+        status as TaskStatus,
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
 
-    Ok(Task {
-        id: record.id,
-        title: record.title,
-        description: record.description.expect("Description is missing"),
-        due_date: record.due_date,
-        status: record.status,
-        user_id: record.user_id.expect("User ID is missing"),
-    })
+    // The neighbours on either side of the new spot; the task itself is
+    // skipped so that moving it next to where it already is still works
+    let (lower, upper) = match placement {
+        Placement::Before(_) => (
+            neighbor_position_db(
+                conn,
+                user_id,
+                status,
+                Some(&anchor),
+                SortOrder::Desc,
+                task_id,
+            )
+            .await?,
+            Some(anchor),
+        ),
+        _ => {
+            let upper = neighbor_position_db(
+                conn,
+                user_id,
+                status,
+                Some(&anchor),
+                SortOrder::Asc,
+                task_id,
+            )
+            .await?;
+            (Some(anchor), upper)
+        }
+    };
+    position_between(lower.as_deref(), upper.as_deref())
+        .ok_or_else(|| Error::protocol("Task positions are out of order"))
 }
 
+// Puts the task in the `status` column as `placement` says. Like
+// `update_user_task_db`, only applies while the task is still in
+// `current_status` and completes a task moved to done the same way.
+pub async fn move_user_task_db(
+    pool: &PgPool,
+    info: Info,
+    status: TaskStatus,
+    placement: Placement,
+    current_status: TaskStatus,
+) -> Result<TaskWrite, Error> {
+    let mut tx = pool.begin().await?;
+    lock_user_tasks_db(&mut tx, info.user_id).await?;
+
+    if status == TaskStatus::Done && current_status != TaskStatus::Done {
+        let blockers = open_blockers_db(&mut tx, info.user_id, info.task_id).await?;
+        if !blockers.is_empty() {
+            return Ok(TaskWrite::Blocked(blockers));
+        }
+    }
+
+    let position =
+        placement_position_db(&mut tx, info.user_id, info.task_id, status, placement).await?;
+    let mut task: Task = sqlx::query_as(&format!(
+        "UPDATE tasks \
+         SET status = $1, position = $2, \
+             completed_at = CASE WHEN $1::task_status = 'done' \
+                 THEN COALESCE(completed_at, now() AT TIME ZONE 'utc') END \
+         WHERE id = $3 AND user_id = $4 AND status = $5 \
+         RETURNING {}",
+        TASK_COLUMNS
+    ))
+    .bind(status)
+    .bind(position)
+    .bind(info.task_id)
+    .bind(info.user_id)
+    .bind(current_status)
+    .fetch_one(&mut *tx)
+    .await?;
+    if status == TaskStatus::Done && current_status != TaskStatus::Done {
+        complete_subtasks_db(&mut tx, &info).await?;
+        schedule_next_occurrence_db(&mut tx, &mut task).await?;
+    }
+    load_task_details_db(&mut tx, std::slice::from_mut(&mut task)).await?;
+
+    tx.commit().await?;
+
+    Ok(TaskWrite::Written(task))
+}
+
+// Every task of the user in position order
+pub async fn get_user_board_db(pool: &PgPool, user_id: i32) -> Result<Vec<Task>, Error> {
+    let mut tasks: Vec<Task> = sqlx::query_as(&format!(
+        "SELECT {} FROM tasks WHERE user_id = $1 ORDER BY position, id",
+        TASK_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    load_task_details_db(&mut *pool.acquire().await?, &mut tasks).await?;
+
+    Ok(tasks)
+}
+
+// Subtasks and checklist items go with the task through the foreign key
+// cascades
 pub async fn delete_user_task_db(pool: &PgPool, info: Info) -> Result<u64, Error> {
     let result = sqlx::query!(
         r#"
@@ -172,33 +1004,426 @@ pub async fn delete_user_task_db(pool: &PgPool, info: Info) -> Result<u64, Error
     Ok(result.rows_affected())
 }
 
+pub async fn create_tag_db(pool: &PgPool, user_id: i32, name: &str) -> Result<Tag, Error> {
+    sqlx::query_as!(
+        Tag,
+        r#"
+        INSERT INTO tags (user_id, name) VALUES ($1, $2)
+        RETURNING id, name
+        "#,
+        user_id,
+        name
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_user_tags_db(pool: &PgPool, user_id: i32) -> Result<Vec<Tag>, Error> {
+    sqlx::query_as!(
+        Tag,
+        r#"
+        SELECT id, name FROM tags WHERE user_id = $1 ORDER BY name, id
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn rename_tag_db(pool: &PgPool, info: TagInfo, name: &str) -> Result<Tag, Error> {
+    sqlx::query_as!(
+        Tag,
+        r#"
+        UPDATE tags SET name = $1 WHERE id = $2 AND user_id = $3
+        RETURNING id, name
+        "#,
+        name,
+        info.tag_id,
+        info.user_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// Detaching from tasks is left to the foreign key cascade
+pub async fn delete_tag_db(pool: &PgPool, info: TagInfo) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM tags WHERE id = $1 AND user_id = $2
+        "#,
+        info.tag_id,
+        info.user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// Inserts nothing, and so returns `RowNotFound`, unless both the task and the
+// tag belong to the user. Attaching a tag twice is not an error.
+pub async fn attach_tag_db(pool: &PgPool, info: TaskTagInfo) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO task_tags (task_id, tag_id)
+        SELECT tasks.id, tags.id FROM tasks, tags
+        WHERE tasks.id = $1 AND tasks.user_id = $3 AND tags.id = $2 AND tags.user_id = $3
+        ON CONFLICT (task_id, tag_id) DO UPDATE SET task_id = EXCLUDED.task_id
+        RETURNING task_id
+        "#,
+        info.task_id,
+        info.tag_id,
+        info.user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn detach_tag_db(pool: &PgPool, info: TaskTagInfo) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM task_tags
+        WHERE task_id = $1 AND tag_id = $2
+          AND tag_id IN (SELECT id FROM tags WHERE user_id = $3)
+        "#,
+        info.task_id,
+        info.tag_id,
+        info.user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// Inserts nothing, and so returns `RowNotFound`, unless the task belongs to
+// the user
+pub async fn create_checklist_item_db(
+    pool: &PgPool,
+    info: Info,
+    title: &str,
+) -> Result<ChecklistItem, Error> {
+    sqlx::query_as!(
+        ChecklistItem,
+        r#"
+        INSERT INTO checklist_items (task_id, title)
+        SELECT id, $1 FROM tasks WHERE id = $2 AND user_id = $3
+        RETURNING id, title, done
+        "#,
+        title,
+        info.task_id,
+        info.user_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn update_checklist_item_db(
+    pool: &PgPool,
+    info: ChecklistItemInfo,
+    patch: ChecklistItemPatch,
+) -> Result<ChecklistItem, Error> {
+    sqlx::query_as!(
+        ChecklistItem,
+        r#"
+        UPDATE checklist_items SET title = COALESCE($1, title), done = COALESCE($2, done)
+        WHERE id = $3 AND task_id = $4
+          AND task_id IN (SELECT id FROM tasks WHERE user_id = $5)
+        RETURNING id, title, done
+        "#,
+        patch.title,
+        patch.done,
+        info.item_id,
+        info.task_id,
+        info.user_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn delete_checklist_item_db(
+    pool: &PgPool,
+    info: ChecklistItemInfo,
+) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM checklist_items
+        WHERE id = $1 AND task_id = $2
+          AND task_id IN (SELECT id FROM tasks WHERE user_id = $3)
+        "#,
+        info.item_id,
+        info.task_id,
+        info.user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// Inserts nothing, and so returns `RowNotFound`, unless both tasks belong to
+// the user. Adding a dependency twice is not an error. Returns `false`,
+// inserting nothing, when the blocker already waits for the task.
+pub async fn add_dependency_db(pool: &PgPool, info: DependencyInfo) -> Result<bool, Error> {
+    let mut tx = pool.begin().await?;
+    lock_user_tasks_db(&mut tx, info.user_id).await?;
+
+    // Whether the blocker waits for the task, directly or through other
+    // blockers. UNION stops the walk should a cycle exist.
+    let cycle = sqlx::query_scalar!(
+        r#"
+        WITH RECURSIVE blockers (id) AS (
+            SELECT blocker_id FROM task_dependencies
+            WHERE task_id = $1 AND task_id IN (SELECT id FROM tasks WHERE user_id = $2)
+            UNION
+            SELECT task_dependencies.blocker_id
+            FROM task_dependencies JOIN blockers ON task_dependencies.task_id = blockers.id
+        )
+        SELECT EXISTS (SELECT 1 FROM blockers WHERE id = $3) AS "cycle!"
+        "#,
+        info.blocker_id,
+        info.user_id,
+        info.task_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if cycle {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO task_dependencies (task_id, blocker_id)
+        SELECT tasks.id, blockers.id FROM tasks, tasks AS blockers
+        WHERE tasks.id = $1 AND tasks.user_id = $3 AND blockers.id = $2 AND blockers.user_id = $3
+        ON CONFLICT (task_id, blocker_id) DO UPDATE SET task_id = EXCLUDED.task_id
+        RETURNING task_id
+        "#,
+        info.task_id,
+        info.blocker_id,
+        info.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn remove_dependency_db(pool: &PgPool, info: DependencyInfo) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM task_dependencies
+        WHERE task_id = $1 AND blocker_id = $2
+          AND task_id IN (SELECT id FROM tasks WHERE user_id = $3)
+        "#,
+        info.task_id,
+        info.blocker_id,
+        info.user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// Ids of the open tasks keeping a task of the user from being completed: the
+// open blockers of the task and of the subtasks at every level that are
+// completed along with it, other than those subtasks themselves. Runs in the
+// transaction completing the task, which holds the lock on the user's tasks,
+// so neither a new dependency nor a reopened blocker can slip in before the
+// write.
+async fn open_blockers_db(
+    conn: &mut PgConnection,
+    user_id: i32,
+    task_id: i32,
+) -> Result<Vec<i32>, Error> {
+    sqlx::query_scalar!(
+        r#"
+        WITH RECURSIVE family (id, status) AS (
+            SELECT id, status FROM tasks WHERE id = $1 AND user_id = $2
+            UNION
+            SELECT tasks.id, tasks.status FROM tasks JOIN family ON tasks.parent_id = family.id
+        ),
+        completing (id) AS (
+            SELECT id FROM family WHERE id = $1 OR status IN ('todo', 'in_progress')
+        )
+        SELECT DISTINCT blockers.id
+        FROM task_dependencies
+        JOIN tasks AS blockers ON blockers.id = task_dependencies.blocker_id
+        WHERE task_dependencies.task_id IN (SELECT id FROM completing)
+          AND blockers.status NOT IN ('done', 'cancelled')
+          AND blockers.id NOT IN (SELECT id FROM completing)
+        ORDER BY blockers.id
+        "#,
+        task_id,
+        user_id
+    )
+    .fetch_all(conn)
+    .await
+}
+
+// Returns `None` when the username is unknown or the password does not match
 pub async fn authenticate_user(
     pool: &PgPool,
     username: &str,
     password: &str,
-) -> Result<User, Error> {
+) -> Result<Option<User>, Error> {
     let user = sqlx::query_as!(
         User,
         r#"
-        SELECT id, username, password_hash, email
+        SELECT id, username, password_hash, email, role AS "role: Role"
         FROM users
         WHERE username = $1
         "#,
         username
     )
-    .fetch_one(pool)
+    .fetch_optional(pool)
     .await?;
 
+    let Some(user) = user else {
+        return Ok(None);
+    };
+
     if verify(password, &user.password_hash)
         .map_err(|_| Error::protocol("Password verification failed"))?
     {
-        Ok(User {
+        Ok(Some(User {
             id: user.id,
             username: user.username,
             password_hash: String::new(),
             email: user.email,
-        })
+            role: user.role,
+        }))
     } else {
-        Err(Error::protocol("Invalid username or password"))
+        Ok(None)
     }
 }
+
+pub async fn create_refresh_token_db(
+    pool: &PgPool,
+    user_id: i32,
+    family_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<RefreshToken, Error> {
+    sqlx::query_as!(
+        RefreshToken,
+        r#"
+        INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, user_id, family_id, expires_at, revoked_at
+        "#,
+        user_id,
+        family_id,
+        token_hash,
+        expires_at,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_refresh_token_db(pool: &PgPool, token_hash: &str) -> Result<RefreshToken, Error> {
+    sqlx::query_as!(
+        RefreshToken,
+        r#"
+        SELECT id, user_id, family_id, expires_at, revoked_at
+        FROM refresh_tokens
+        WHERE token_hash = $1
+        "#,
+        token_hash,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// Revokes `current` and issues its successor in the same family. Returns
+// `None` when `current` was already revoked, i.e. the token is being reused.
+pub async fn rotate_refresh_token_db(
+    pool: &PgPool,
+    current: &RefreshToken,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<Option<RefreshToken>, Error> {
+    let mut tx = pool.begin().await?;
+
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens SET revoked_at = now()
+        WHERE id = $1 AND revoked_at IS NULL
+        "#,
+        current.id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if revoked.rows_affected() == 0 {
+        tx.rollback().await?;
+        return Ok(None);
+    }
+
+    let next = sqlx::query_as!(
+        RefreshToken,
+        r#"
+        INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, user_id, family_id, expires_at, revoked_at
+        "#,
+        current.user_id,
+        current.family_id,
+        token_hash,
+        expires_at,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE refresh_tokens SET replaced_by = $1 WHERE id = $2
+        "#,
+        next.id,
+        current.id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(next))
+}
+
+pub async fn revoke_token_family_db(
+    pool: &PgPool,
+    user_id: i32,
+    family_id: Uuid,
+) -> Result<u64, Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE refresh_tokens SET revoked_at = now()
+        WHERE user_id = $1 AND family_id = $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        family_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn ping_db(pool: &PgPool) -> Result<(), Error> {
+    sqlx::query!("SELECT 1 AS one").fetch_one(pool).await?;
+
+    Ok(())
+}
+
+pub async fn get_schema_version_db(pool: &PgPool) -> Result<i64, Error> {
+    let version = sqlx::query_scalar!("SELECT MAX(version) FROM schema_migrations")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(version.unwrap_or(0))
+}
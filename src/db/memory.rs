@@ -0,0 +1,1318 @@
+use crate::db::migrations::latest_version;
+use crate::db::repository::{
+    ChecklistRepository, DependencyRepository, HealthRepository, RefreshTokenRepository,
+    TagRepository, TaskRepository, UserRepository,
+};
+use crate::metrics::metrics::PoolStatus;
+use crate::model::models::{
+    ChecklistItem, ChecklistItemInfo, ChecklistItemPatch, DependencyInfo, Info, InvalidParent,
+    Placement, Progress, RefreshToken, Role, SortOrder, Tag, TagInfo, Task, TaskCursor, TaskInput,
+    TaskPatch, TaskPriority, TaskQuery, TaskSort, TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite,
+    User, UserInput, UserQuery,
+};
+use crate::model::position::{position_after, position_between};
+use async_trait::async_trait;
+use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::error::{DatabaseError, ErrorKind};
+use sqlx::Error;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+// A backend keeping everything in process memory, so the handlers can be
+// exercised without a database. It mirrors the behaviour of `PgRepository`,
+// including the errors it reports.
+#[derive(Default)]
+pub struct MemoryRepository {
+    store: Mutex<Store>,
+}
+
+#[derive(Default)]
+struct Store {
+    users: Vec<User>,
+    tasks: Vec<Task>,
+    refresh_tokens: Vec<StoredRefreshToken>,
+    tags: Vec<StoredTag>,
+    // (task id, tag id) pairs
+    task_tags: Vec<(i32, i32)>,
+    // In creation order
+    checklist_items: Vec<StoredChecklistItem>,
+    // (task id, blocker id) pairs
+    dependencies: Vec<(i32, i32)>,
+    // Like SERIAL columns, ids are never reused
+    last_user_id: i32,
+    last_task_id: i32,
+    last_refresh_token_id: i32,
+    last_tag_id: i32,
+    last_checklist_item_id: i32,
+}
+
+struct StoredTag {
+    tag: Tag,
+    user_id: i32,
+}
+
+struct StoredChecklistItem {
+    item: ChecklistItem,
+    task_id: i32,
+}
+
+struct StoredRefreshToken {
+    token: RefreshToken,
+    token_hash: String,
+}
+
+// Stands in for the error Postgres reports when a UNIQUE constraint fails
+#[derive(Debug)]
+struct UniqueViolation {
+    message: String,
+}
+
+impl UniqueViolation {
+    fn error(constraint: &str) -> Error {
+        Error::Database(Box::new(UniqueViolation {
+            message: format!(
+                "duplicate key value violates unique constraint \"{}\"",
+                constraint
+            ),
+        }))
+    }
+}
+
+impl fmt::Display for UniqueViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for UniqueViolation {}
+
+impl DatabaseError for UniqueViolation {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed("23505"))
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::UniqueViolation
+    }
+}
+
+fn hash_password(password: &str) -> Result<String, Error> {
+    hash(password, DEFAULT_COST).map_err(|e| Error::protocol(format!("Bcrypt error: {}", e)))
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn matches_user_filter(user: &User, filter: &UserQuery) -> bool {
+    match filter.q.as_deref().filter(|q| !q.is_empty()) {
+        Some(q) => {
+            let prefix = q.to_lowercase();
+            user.username.to_lowercase().starts_with(&prefix)
+                || user.email.to_lowercase().starts_with(&prefix)
+        }
+        None => true,
+    }
+}
+
+fn is_overdue(task: &Task) -> bool {
+    task.due_date.is_some_and(|due_date| due_date < now()) && is_open(task)
+}
+
+fn matches_task_filter(task: &Task, filter: &TaskQuery) -> bool {
+    filter.status.is_none_or(|status| task.status == status)
+        && filter
+            .priority
+            .is_none_or(|priority| task.priority == priority)
+        && filter
+            .parent_id
+            .is_none_or(|parent_id| task.parent_id == Some(parent_id))
+        && filter
+            .due_before
+            .is_none_or(|before| task.due_date.is_some_and(|d| d < before))
+        && filter
+            .due_after
+            .is_none_or(|after| task.due_date.is_some_and(|d| d > after))
+        && filter
+            .overdue
+            .is_none_or(|overdue| is_overdue(task) == overdue)
+        && filter
+            .q
+            .as_deref()
+            .filter(|text| !text.is_empty())
+            .is_none_or(|text| {
+                let text = text.to_lowercase();
+                task.title.to_lowercase().contains(&text)
+                    || task.description.to_lowercase().contains(&text)
+            })
+}
+
+// Position of a task in a listing sorted by `sort`, compared as a tuple the
+// way Postgres compares `(key, id)`. Tasks without a due date sort last.
+type SortKey = (bool, Option<NaiveDateTime>, String, TaskPriority, i32);
+
+fn sort_key(
+    sort: TaskSort,
+    id: i32,
+    due_date: Option<NaiveDateTime>,
+    title: &str,
+    priority: TaskPriority,
+) -> SortKey {
+    let none = TaskPriority::default();
+    match sort {
+        TaskSort::Id => (false, None, String::new(), none, id),
+        TaskSort::DueDate => (due_date.is_none(), due_date, String::new(), none, id),
+        TaskSort::Title => (false, None, title.to_owned(), none, id),
+        TaskSort::Priority => (false, None, String::new(), priority, id),
+    }
+}
+
+fn is_open(task: &Task) -> bool {
+    !matches!(task.status, TaskStatus::Done | TaskStatus::Cancelled)
+}
+
+// Subtasks the workflow lets move to done are completed along with their
+// parent
+fn completes_with_parent(task: &Task) -> bool {
+    task.status != TaskStatus::Done && task.status.can_transition_to(TaskStatus::Done)
+}
+
+fn completed_at(task: &Task, status: TaskStatus) -> Option<NaiveDateTime> {
+    if status == TaskStatus::Done {
+        Some(task.completed_at.unwrap_or_else(now))
+    } else {
+        None
+    }
+}
+
+impl Store {
+    // Stored tasks carry no tags, checklist, progress or blockers; they are
+    // joined in on the way out
+    fn with_details(&self, task: &Task) -> Task {
+        let mut tags: Vec<Tag> = self
+            .task_tags
+            .iter()
+            .filter(|(task_id, _)| *task_id == task.id)
+            .filter_map(|(_, tag_id)| self.tags.iter().find(|stored| stored.tag.id == *tag_id))
+            .map(|stored| stored.tag.clone())
+            .collect();
+        tags.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+
+        let checklist: Vec<ChecklistItem> = self
+            .checklist_items
+            .iter()
+            .filter(|stored| stored.task_id == task.id)
+            .map(|stored| stored.item.clone())
+            .collect();
+        let subtasks: Vec<&Task> = self
+            .tasks
+            .iter()
+            .filter(|subtask| {
+                subtask.parent_id == Some(task.id) && subtask.status != TaskStatus::Cancelled
+            })
+            .collect();
+        let progress = Progress {
+            done: (checklist.iter().filter(|item| item.done).count()
+                + subtasks
+                    .iter()
+                    .filter(|subtask| subtask.status == TaskStatus::Done)
+                    .count()) as i64,
+            total: (checklist.len() + subtasks.len()) as i64,
+        };
+
+        let mut blocked_by: Vec<i32> = self
+            .dependencies
+            .iter()
+            .filter(|(task_id, _)| *task_id == task.id)
+            .map(|(_, blocker_id)| *blocker_id)
+            .collect();
+        blocked_by.sort();
+        let blocked = self
+            .tasks
+            .iter()
+            .any(|blocker| blocked_by.contains(&blocker.id) && is_open(blocker));
+
+        Task {
+            tags,
+            checklist,
+            progress,
+            blocked_by,
+            blocked,
+            ..task.clone()
+        }
+    }
+
+    // Ids of the subtasks of a task at every level, skipping any already
+    // seen should the parents form a cycle
+    fn descendants(&self, task_id: i32) -> Vec<i32> {
+        let mut ids = vec![task_id];
+        let mut next = 0;
+        while next < ids.len() {
+            let parent_id = ids[next];
+            for task in &self.tasks {
+                if task.parent_id == Some(parent_id) && !ids.contains(&task.id) {
+                    ids.push(task.id);
+                }
+            }
+            next += 1;
+        }
+        ids.remove(0);
+        ids
+    }
+
+    fn user_task(&self, user_id: i32, task_id: i32) -> Option<&Task> {
+        self.tasks
+            .iter()
+            .find(|task| task.user_id == user_id && task.id == task_id)
+    }
+
+    // Like `task_ancestors_db`
+    fn ancestors(&self, user_id: i32, task_id: i32) -> Vec<i32> {
+        let mut ancestors = Vec::new();
+        let mut parent_id = self
+            .user_task(user_id, task_id)
+            .and_then(|task| task.parent_id);
+        while let Some(id) = parent_id.filter(|id| !ancestors.contains(id)) {
+            ancestors.push(id);
+            parent_id = self
+                .tasks
+                .iter()
+                .find(|task| task.id == id)
+                .and_then(|task| task.parent_id);
+        }
+        ancestors
+    }
+
+    // Like `subtask_depth_db`
+    fn subtask_depth(&self, user_id: i32, task_id: i32) -> usize {
+        if self.user_task(user_id, task_id).is_none() {
+            return 0;
+        }
+
+        let mut depth = 0;
+        let mut level = vec![task_id];
+        let mut seen = vec![task_id];
+        loop {
+            level = self
+                .tasks
+                .iter()
+                .filter(|task| task.parent_id.is_some_and(|id| level.contains(&id)))
+                .map(|task| task.id)
+                .filter(|id| !seen.contains(id))
+                .collect();
+            if level.is_empty() {
+                return depth;
+            }
+            seen.extend(&level);
+            depth += 1;
+        }
+    }
+
+    // Like `check_parent_db`
+    fn check_parent(
+        &self,
+        user_id: i32,
+        task_id: Option<i32>,
+        parent_id: i32,
+    ) -> Option<InvalidParent> {
+        if self.user_task(user_id, parent_id).is_none() {
+            return Some(InvalidParent::Missing);
+        }
+
+        let ancestors = self.ancestors(user_id, parent_id);
+        let below = task_id.map_or(0, |task_id| self.subtask_depth(user_id, task_id));
+        InvalidParent::check(task_id, parent_id, &ancestors, below)
+    }
+
+    // Like `neighbor_position_db`
+    fn neighbor_position(
+        &self,
+        user_id: i32,
+        status: TaskStatus,
+        from: Option<&str>,
+        order: SortOrder,
+        exclude: i32,
+    ) -> Option<String> {
+        let positions = self
+            .tasks
+            .iter()
+            .filter(|task| task.user_id == user_id && task.status == status && task.id != exclude)
+            .map(|task| task.position.as_str());
+
+        let neighbor = match order {
+            SortOrder::Asc => positions
+                .filter(|position| from.is_none_or(|from| *position > from))
+                .min(),
+            SortOrder::Desc => positions
+                .filter(|position| from.is_none_or(|from| *position < from))
+                .max(),
+        };
+        neighbor.map(str::to_owned)
+    }
+
+    // A position after every task in a status column of the user, other than
+    // the task with id `exclude`
+    fn end_of_column(&self, user_id: i32, status: TaskStatus, exclude: i32) -> String {
+        let last = self.neighbor_position(user_id, status, None, SortOrder::Desc, exclude);
+        position_after(last.as_deref())
+    }
+
+    // Like `placement_position_db`
+    fn placement_position(
+        &self,
+        user_id: i32,
+        task_id: i32,
+        status: TaskStatus,
+        placement: Placement,
+    ) -> Result<String, Error> {
+        let anchor_id = match placement {
+            Placement::Before(anchor_id) | Placement::After(anchor_id) => anchor_id,
+            Placement::End => return Ok(self.end_of_column(user_id, status, task_id)),
+        };
+        let anchor = self
+            .user_task(user_id, anchor_id)
+            .filter(|anchor| anchor.status == status)
+            .map(|anchor| anchor.position.clone())
+            .ok_or(Error::RowNotFound)?;
+
+        let (lower, upper) = match placement {
+            Placement::Before(_) => (
+                self.neighbor_position(user_id, status, Some(&anchor), SortOrder::Desc, task_id),
+                Some(anchor),
+            ),
+            _ => {
+                let upper =
+                    self.neighbor_position(user_id, status, Some(&anchor), SortOrder::Asc, task_id);
+                (Some(anchor), upper)
+            }
+        };
+        position_between(lower.as_deref(), upper.as_deref())
+            .ok_or_else(|| Error::protocol("Task positions are out of order"))
+    }
+
+    // New tasks go to the end of their column
+    fn insert_task(&mut self, new_task: &TaskInput, user_id: i32) -> Task {
+        let position = self.end_of_column(user_id, new_task.status, 0);
+        self.last_task_id += 1;
+        let task = Task {
+            id: self.last_task_id,
+            title: new_task.title.clone(),
+            description: new_task.description.clone(),
+            due_date: new_task.due_date,
+            status: new_task.status,
+            completed_at: (new_task.status == TaskStatus::Done).then(now),
+            user_id,
+            priority: new_task.priority,
+            position,
+            parent_id: new_task.parent_id,
+            recurrence: new_task.recurrence.clone(),
+            tags: Vec::new(),
+            checklist: Vec::new(),
+            progress: Progress::default(),
+            blocked_by: Vec::new(),
+            blocked: false,
+        };
+        self.tasks.push(task.clone());
+        task
+    }
+
+    // Like `schedule_next_occurrence_db`
+    fn schedule_next_occurrence(&mut self, task: &mut Task) -> Result<(), Error> {
+        if task.recurrence.is_none() || task.due_date.is_none() {
+            return Ok(());
+        }
+
+        if let Some(next) = task.next_occurrence().map_err(Error::protocol)? {
+            let copy = self.insert_task(&next, task.user_id);
+            let tag_ids: Vec<i32> = self
+                .task_tags
+                .iter()
+                .filter(|(task_id, _)| *task_id == task.id)
+                .map(|(_, tag_id)| *tag_id)
+                .collect();
+            for tag_id in tag_ids {
+                self.task_tags.push((copy.id, tag_id));
+            }
+            let titles: Vec<String> = self
+                .checklist_items
+                .iter()
+                .filter(|stored| stored.task_id == task.id)
+                .map(|stored| stored.item.title.clone())
+                .collect();
+            for title in titles {
+                self.last_checklist_item_id += 1;
+                self.checklist_items.push(StoredChecklistItem {
+                    item: ChecklistItem {
+                        id: self.last_checklist_item_id,
+                        title,
+                        done: false,
+                    },
+                    task_id: copy.id,
+                });
+            }
+        }
+
+        if let Some(stored) = self.tasks.iter_mut().find(|stored| stored.id == task.id) {
+            stored.recurrence = None;
+        }
+        task.recurrence = None;
+
+        Ok(())
+    }
+
+    // Like `open_blockers_db`
+    fn open_blockers(&self, user_id: i32, task_id: i32) -> Vec<i32> {
+        if self.user_task(user_id, task_id).is_none() {
+            return Vec::new();
+        }
+
+        let descendants = self.descendants(task_id);
+        let mut completing: Vec<i32> = self
+            .tasks
+            .iter()
+            .filter(|task| descendants.contains(&task.id) && completes_with_parent(task))
+            .map(|task| task.id)
+            .collect();
+        completing.push(task_id);
+
+        let mut blockers: Vec<i32> = self
+            .dependencies
+            .iter()
+            .filter(|(blocked_id, blocker_id)| {
+                completing.contains(blocked_id) && !completing.contains(blocker_id)
+            })
+            .map(|(_, blocker_id)| *blocker_id)
+            .filter(|blocker_id| {
+                self.tasks
+                    .iter()
+                    .any(|task| task.id == *blocker_id && is_open(task))
+            })
+            .collect();
+        blockers.sort();
+        blockers.dedup();
+        blockers
+    }
+
+    // Like `complete_subtasks_db`
+    fn complete_subtasks(&mut self, info: &Info) -> Result<(), Error> {
+        let descendants = self.descendants(info.task_id);
+        let mut completing: Vec<(String, i32)> = self
+            .tasks
+            .iter()
+            .filter(|task| descendants.contains(&task.id) && completes_with_parent(task))
+            .map(|task| (task.position.clone(), task.id))
+            .collect();
+        completing.sort();
+
+        let mut position = self.end_of_column(info.user_id, TaskStatus::Done, 0);
+        for (_, id) in &completing {
+            let task = self
+                .tasks
+                .iter_mut()
+                .find(|task| task.id == *id)
+                .expect("subtask exists");
+            task.completed_at = completed_at(task, TaskStatus::Done);
+            task.status = TaskStatus::Done;
+            task.position = position.clone();
+            position = position_after(Some(&position));
+
+            let mut task = task.clone();
+            self.schedule_next_occurrence(&mut task)?;
+        }
+
+        Ok(())
+    }
+
+    fn has_tag(&self, task: &Task, name: &str) -> bool {
+        self.task_tags.iter().any(|(task_id, tag_id)| {
+            *task_id == task.id
+                && self
+                    .tags
+                    .iter()
+                    .any(|stored| stored.tag.id == *tag_id && stored.tag.name == name)
+        })
+    }
+
+    fn user_tag_mut(&mut self, info: &TagInfo) -> Option<&mut StoredTag> {
+        self.tags
+            .iter_mut()
+            .find(|stored| stored.tag.id == info.tag_id && stored.user_id == info.user_id)
+    }
+
+    fn check_unique_tag(&self, user_id: i32, name: &str) -> Result<(), Error> {
+        if self
+            .tags
+            .iter()
+            .any(|stored| stored.user_id == user_id && stored.tag.name == name)
+        {
+            return Err(UniqueViolation::error("tags_user_id_name_key"));
+        }
+
+        Ok(())
+    }
+
+    fn insert_refresh_token(
+        &mut self,
+        user_id: i32,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, Error> {
+        if self
+            .refresh_tokens
+            .iter()
+            .any(|stored| stored.token_hash == token_hash)
+        {
+            return Err(UniqueViolation::error("refresh_tokens_token_hash_key"));
+        }
+
+        self.last_refresh_token_id += 1;
+        let token = RefreshToken {
+            id: self.last_refresh_token_id,
+            user_id,
+            family_id,
+            expires_at,
+            revoked_at: None,
+        };
+        self.refresh_tokens.push(StoredRefreshToken {
+            token: token.clone(),
+            token_hash: token_hash.to_owned(),
+        });
+
+        Ok(token)
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        MemoryRepository::default()
+    }
+
+    fn store(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().expect("memory store poisoned")
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn create_user(&self, new_user: &UserInput) -> Result<User, Error> {
+        let password_hash = hash_password(&new_user.password)?;
+        let mut store = self.store();
+
+        if store.users.iter().any(|u| u.username == new_user.username) {
+            return Err(UniqueViolation::error("users_username_key"));
+        }
+        if store.users.iter().any(|u| u.email == new_user.email) {
+            return Err(UniqueViolation::error("users_email_key"));
+        }
+
+        store.last_user_id += 1;
+        let user = User {
+            id: store.last_user_id,
+            username: new_user.username.clone(),
+            password_hash,
+            email: new_user.email.clone(),
+            role: Role::Member,
+        };
+        store.users.push(user.clone());
+
+        Ok(user)
+    }
+
+    async fn get_users(
+        &self,
+        filter: &UserQuery,
+        after_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<User>, Error> {
+        let store = self.store();
+
+        Ok(store
+            .users
+            .iter()
+            .filter(|user| matches_user_filter(user, filter))
+            .filter(|user| after_id.is_none_or(|after_id| user.id > after_id))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn count_users(&self, filter: &UserQuery) -> Result<i64, Error> {
+        let store = self.store();
+
+        Ok(store
+            .users
+            .iter()
+            .filter(|user| matches_user_filter(user, filter))
+            .count() as i64)
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<User, Error> {
+        let store = self.store();
+
+        store
+            .users
+            .iter()
+            .find(|user| user.username == username)
+            .cloned()
+            .ok_or(Error::RowNotFound)
+    }
+
+    async fn get_user_role(&self, user_id: i32) -> Result<Role, Error> {
+        let store = self.store();
+
+        store
+            .users
+            .iter()
+            .find(|user| user.id == user_id)
+            .map(|user| user.role)
+            .ok_or(Error::RowNotFound)
+    }
+
+    async fn set_user_role(&self, user_id: i32, role: Role) -> Result<u64, Error> {
+        let mut store = self.store();
+
+        match store.users.iter_mut().find(|user| user.id == user_id) {
+            Some(user) => {
+                user.role = role;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    async fn reset_user_password(&self, user_id: i32, password: &str) -> Result<u64, Error> {
+        let password_hash = hash_password(password)?;
+        let mut store = self.store();
+
+        let Some(user) = store.users.iter_mut().find(|user| user.id == user_id) else {
+            return Ok(0);
+        };
+        user.password_hash = password_hash;
+
+        for stored in store.refresh_tokens.iter_mut() {
+            if stored.token.user_id == user_id && stored.token.revoked_at.is_none() {
+                stored.token.revoked_at = Some(Utc::now());
+            }
+        }
+
+        Ok(1)
+    }
+
+    async fn authenticate_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<User>, Error> {
+        let user = match self.get_user_by_username(username).await {
+            Ok(user) => user,
+            Err(Error::RowNotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        if verify(password, &user.password_hash)
+            .map_err(|_| Error::protocol("Password verification failed"))?
+        {
+            Ok(Some(User {
+                password_hash: String::new(),
+                ..user
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[async_trait]
+impl TaskRepository for MemoryRepository {
+    async fn create_task(&self, new_task: &TaskInput, user_id: i32) -> Result<TaskWrite, Error> {
+        let mut store = self.store();
+        if let Some(parent_id) = new_task.parent_id {
+            if let Some(invalid) = store.check_parent(user_id, None, parent_id) {
+                return Ok(TaskWrite::InvalidParent(invalid));
+            }
+        }
+
+        Ok(TaskWrite::Written(store.insert_task(new_task, user_id)))
+    }
+
+    async fn get_user_tasks(
+        &self,
+        user_id: i32,
+        filter: &TaskQuery,
+        after: Option<&TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<Task>, Error> {
+        let store = self.store();
+        let after = after.map(|after| {
+            sort_key(
+                filter.sort,
+                after.id,
+                after.due_date,
+                after.title.as_deref().unwrap_or_default(),
+                after.priority.unwrap_or_default(),
+            )
+        });
+
+        let mut tasks: Vec<(SortKey, &Task)> = store
+            .tasks
+            .iter()
+            .filter(|task| task.user_id == user_id && matches_task_filter(task, filter))
+            .filter(|task| {
+                filter
+                    .tag
+                    .as_deref()
+                    .is_none_or(|name| store.has_tag(task, name))
+            })
+            .map(|task| {
+                let key = sort_key(
+                    filter.sort,
+                    task.id,
+                    task.due_date,
+                    &task.title,
+                    task.priority,
+                );
+                (key, task)
+            })
+            .filter(|(key, _)| match (&after, filter.order) {
+                (None, _) => true,
+                (Some(after), SortOrder::Asc) => key > after,
+                (Some(after), SortOrder::Desc) => key < after,
+            })
+            .collect();
+
+        tasks.sort_by(|(a, _), (b, _)| a.cmp(b));
+        if filter.order == SortOrder::Desc {
+            tasks.reverse();
+        }
+
+        Ok(tasks
+            .into_iter()
+            .take(limit as usize)
+            .map(|(_, task)| store.with_details(task))
+            .collect())
+    }
+
+    async fn get_user_task(&self, user_id: i32, task_id: i32) -> Result<Task, Error> {
+        let store = self.store();
+
+        store
+            .tasks
+            .iter()
+            .find(|task| task.user_id == user_id && task.id == task_id)
+            .map(|task| store.with_details(task))
+            .ok_or(Error::RowNotFound)
+    }
+
+    async fn update_user_task(
+        &self,
+        info: Info,
+        task_update: TaskUpdate,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error> {
+        let mut store = self.store();
+        if task_update.status == TaskStatus::Done && current_status != TaskStatus::Done {
+            let blockers = store.open_blockers(info.user_id, info.task_id);
+            if !blockers.is_empty() {
+                return Ok(TaskWrite::Blocked(blockers));
+            }
+        }
+
+        let position = (task_update.status != current_status)
+            .then(|| store.end_of_column(info.user_id, task_update.status, 0));
+
+        let task = store
+            .tasks
+            .iter_mut()
+            .find(|task| {
+                task.user_id == info.user_id
+                    && task.id == info.task_id
+                    && task.status == current_status
+            })
+            .ok_or(Error::RowNotFound)?;
+
+        if let Some(position) = position {
+            task.position = position;
+        }
+        task.completed_at = completed_at(task, task_update.status);
+        task.title = task_update.title;
+        task.description = task_update.description;
+        task.due_date = task_update.due_date;
+        task.status = task_update.status;
+        task.priority = task_update.priority;
+        task.recurrence = task_update.recurrence;
+
+        let mut task = task.clone();
+        if task.status == TaskStatus::Done && current_status != TaskStatus::Done {
+            store.complete_subtasks(&info)?;
+            store.schedule_next_occurrence(&mut task)?;
+        }
+        Ok(TaskWrite::Written(store.with_details(&task)))
+    }
+
+    async fn patch_user_task(
+        &self,
+        info: Info,
+        patch: TaskPatch,
+        current_status: Option<TaskStatus>,
+    ) -> Result<TaskWrite, Error> {
+        let mut store = self.store();
+        if let Some(Some(parent_id)) = patch.parent_id {
+            if let Some(invalid) = store.check_parent(info.user_id, Some(info.task_id), parent_id) {
+                return Ok(TaskWrite::InvalidParent(invalid));
+            }
+        }
+        if patch.status == Some(TaskStatus::Done) && current_status != Some(TaskStatus::Done) {
+            let blockers = store.open_blockers(info.user_id, info.task_id);
+            if !blockers.is_empty() {
+                return Ok(TaskWrite::Blocked(blockers));
+            }
+        }
+
+        let position = patch
+            .status
+            .filter(|status| Some(*status) != current_status)
+            .map(|status| store.end_of_column(info.user_id, status, 0));
+
+        let task = store
+            .tasks
+            .iter_mut()
+            .find(|task| {
+                task.user_id == info.user_id
+                    && task.id == info.task_id
+                    && current_status.is_none_or(|current_status| task.status == current_status)
+            })
+            .ok_or(Error::RowNotFound)?;
+
+        if let Some(position) = position {
+            task.position = position;
+        }
+
+        if let Some(title) = patch.title {
+            task.title = title;
+        }
+        if let Some(description) = patch.description {
+            task.description = description;
+        }
+        if let Some(due_date) = patch.due_date {
+            task.due_date = due_date;
+        }
+        if let Some(status) = patch.status {
+            task.completed_at = completed_at(task, status);
+            task.status = status;
+        }
+        if let Some(priority) = patch.priority {
+            task.priority = priority;
+        }
+        if let Some(parent_id) = patch.parent_id {
+            task.parent_id = parent_id;
+        }
+        if let Some(recurrence) = patch.recurrence {
+            task.recurrence = recurrence;
+        }
+
+        let mut task = task.clone();
+        if patch.status == Some(TaskStatus::Done) && current_status != Some(TaskStatus::Done) {
+            store.complete_subtasks(&info)?;
+            store.schedule_next_occurrence(&mut task)?;
+        }
+        Ok(TaskWrite::Written(store.with_details(&task)))
+    }
+
+    async fn delete_user_task(&self, info: Info) -> Result<u64, Error> {
+        let mut store = self.store();
+
+        if store.user_task(info.user_id, info.task_id).is_none() {
+            return Ok(0);
+        }
+
+        // Like the foreign key cascades in Postgres
+        let mut ids = store.descendants(info.task_id);
+        ids.push(info.task_id);
+        store.tasks.retain(|task| !ids.contains(&task.id));
+        store
+            .task_tags
+            .retain(|(task_id, _)| !ids.contains(task_id));
+        store
+            .checklist_items
+            .retain(|stored| !ids.contains(&stored.task_id));
+        store
+            .dependencies
+            .retain(|(task_id, blocker_id)| !ids.contains(task_id) && !ids.contains(blocker_id));
+
+        Ok(1)
+    }
+
+    async fn move_user_task(
+        &self,
+        info: Info,
+        status: TaskStatus,
+        placement: Placement,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error> {
+        let mut store = self.store();
+        if store
+            .user_task(info.user_id, info.task_id)
+            .is_none_or(|task| task.status != current_status)
+        {
+            return Err(Error::RowNotFound);
+        }
+        if status == TaskStatus::Done && current_status != TaskStatus::Done {
+            let blockers = store.open_blockers(info.user_id, info.task_id);
+            if !blockers.is_empty() {
+                return Ok(TaskWrite::Blocked(blockers));
+            }
+        }
+        let position = store.placement_position(info.user_id, info.task_id, status, placement)?;
+
+        let task = store
+            .tasks
+            .iter_mut()
+            .find(|task| {
+                task.user_id == info.user_id
+                    && task.id == info.task_id
+                    && task.status == current_status
+            })
+            .ok_or(Error::RowNotFound)?;
+
+        task.completed_at = completed_at(task, status);
+        task.status = status;
+        task.position = position;
+
+        let mut task = task.clone();
+        if status == TaskStatus::Done && current_status != TaskStatus::Done {
+            store.complete_subtasks(&info)?;
+            store.schedule_next_occurrence(&mut task)?;
+        }
+        Ok(TaskWrite::Written(store.with_details(&task)))
+    }
+
+    async fn get_user_board(&self, user_id: i32) -> Result<Vec<Task>, Error> {
+        let store = self.store();
+
+        let mut tasks: Vec<&Task> = store
+            .tasks
+            .iter()
+            .filter(|task| task.user_id == user_id)
+            .collect();
+        tasks.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+
+        Ok(tasks
+            .into_iter()
+            .map(|task| store.with_details(task))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TagRepository for MemoryRepository {
+    async fn create_tag(&self, user_id: i32, name: &str) -> Result<Tag, Error> {
+        let mut store = self.store();
+        store.check_unique_tag(user_id, name)?;
+
+        store.last_tag_id += 1;
+        let tag = Tag {
+            id: store.last_tag_id,
+            name: name.to_owned(),
+        };
+        store.tags.push(StoredTag {
+            tag: tag.clone(),
+            user_id,
+        });
+
+        Ok(tag)
+    }
+
+    async fn get_user_tags(&self, user_id: i32) -> Result<Vec<Tag>, Error> {
+        let store = self.store();
+
+        let mut tags: Vec<Tag> = store
+            .tags
+            .iter()
+            .filter(|stored| stored.user_id == user_id)
+            .map(|stored| stored.tag.clone())
+            .collect();
+        tags.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+
+        Ok(tags)
+    }
+
+    async fn rename_tag(&self, info: TagInfo, name: &str) -> Result<Tag, Error> {
+        let mut store = self.store();
+        let current = store.user_tag_mut(&info).ok_or(Error::RowNotFound)?;
+        if current.tag.name != name {
+            store.check_unique_tag(info.user_id, name)?;
+        }
+
+        let stored = store.user_tag_mut(&info).ok_or(Error::RowNotFound)?;
+        stored.tag.name = name.to_owned();
+
+        Ok(stored.tag.clone())
+    }
+
+    async fn delete_tag(&self, info: TagInfo) -> Result<u64, Error> {
+        let mut store = self.store();
+        if store.user_tag_mut(&info).is_none() {
+            return Ok(0);
+        }
+
+        store.tags.retain(|stored| stored.tag.id != info.tag_id);
+        store.task_tags.retain(|(_, tag_id)| *tag_id != info.tag_id);
+
+        Ok(1)
+    }
+
+    async fn attach_tag(&self, info: TaskTagInfo) -> Result<(), Error> {
+        let mut store = self.store();
+
+        let owns_task = store
+            .tasks
+            .iter()
+            .any(|task| task.id == info.task_id && task.user_id == info.user_id);
+        let owns_tag = store
+            .tags
+            .iter()
+            .any(|stored| stored.tag.id == info.tag_id && stored.user_id == info.user_id);
+        if !(owns_task && owns_tag) {
+            return Err(Error::RowNotFound);
+        }
+
+        let pair = (info.task_id, info.tag_id);
+        if !store.task_tags.contains(&pair) {
+            store.task_tags.push(pair);
+        }
+
+        Ok(())
+    }
+
+    async fn detach_tag(&self, info: TaskTagInfo) -> Result<u64, Error> {
+        let mut store = self.store();
+
+        let owns_tag = store
+            .tags
+            .iter()
+            .any(|stored| stored.tag.id == info.tag_id && stored.user_id == info.user_id);
+        if !owns_tag {
+            return Ok(0);
+        }
+
+        let before = store.task_tags.len();
+        store
+            .task_tags
+            .retain(|pair| *pair != (info.task_id, info.tag_id));
+
+        Ok((before - store.task_tags.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl ChecklistRepository for MemoryRepository {
+    async fn create_checklist_item(&self, info: Info, title: &str) -> Result<ChecklistItem, Error> {
+        let mut store = self.store();
+        if store.user_task(info.user_id, info.task_id).is_none() {
+            return Err(Error::RowNotFound);
+        }
+
+        store.last_checklist_item_id += 1;
+        let item = ChecklistItem {
+            id: store.last_checklist_item_id,
+            title: title.to_owned(),
+            done: false,
+        };
+        store.checklist_items.push(StoredChecklistItem {
+            item: item.clone(),
+            task_id: info.task_id,
+        });
+
+        Ok(item)
+    }
+
+    async fn update_checklist_item(
+        &self,
+        info: ChecklistItemInfo,
+        patch: ChecklistItemPatch,
+    ) -> Result<ChecklistItem, Error> {
+        let mut store = self.store();
+        if store.user_task(info.user_id, info.task_id).is_none() {
+            return Err(Error::RowNotFound);
+        }
+
+        let stored = store
+            .checklist_items
+            .iter_mut()
+            .find(|stored| stored.item.id == info.item_id && stored.task_id == info.task_id)
+            .ok_or(Error::RowNotFound)?;
+        if let Some(title) = patch.title {
+            stored.item.title = title;
+        }
+        if let Some(done) = patch.done {
+            stored.item.done = done;
+        }
+
+        Ok(stored.item.clone())
+    }
+
+    async fn delete_checklist_item(&self, info: ChecklistItemInfo) -> Result<u64, Error> {
+        let mut store = self.store();
+        if store.user_task(info.user_id, info.task_id).is_none() {
+            return Ok(0);
+        }
+
+        let before = store.checklist_items.len();
+        store
+            .checklist_items
+            .retain(|stored| !(stored.item.id == info.item_id && stored.task_id == info.task_id));
+
+        Ok((before - store.checklist_items.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl DependencyRepository for MemoryRepository {
+    async fn add_dependency(&self, info: DependencyInfo) -> Result<bool, Error> {
+        let mut store = self.store();
+        if store.user_task(info.user_id, info.task_id).is_none()
+            || store.user_task(info.user_id, info.blocker_id).is_none()
+        {
+            return Err(Error::RowNotFound);
+        }
+
+        // Walks the tasks the blocker waits for, directly or through other
+        // blockers, looking for the task
+        let mut seen = vec![info.blocker_id];
+        let mut next = vec![info.blocker_id];
+        while let Some(id) = next.pop() {
+            for (blocked_id, blocker_id) in &store.dependencies {
+                if *blocked_id == id && !seen.contains(blocker_id) {
+                    if *blocker_id == info.task_id {
+                        return Ok(false);
+                    }
+                    seen.push(*blocker_id);
+                    next.push(*blocker_id);
+                }
+            }
+        }
+
+        let pair = (info.task_id, info.blocker_id);
+        if !store.dependencies.contains(&pair) {
+            store.dependencies.push(pair);
+        }
+
+        Ok(true)
+    }
+
+    async fn remove_dependency(&self, info: DependencyInfo) -> Result<u64, Error> {
+        let mut store = self.store();
+        if store.user_task(info.user_id, info.task_id).is_none() {
+            return Ok(0);
+        }
+
+        let before = store.dependencies.len();
+        store
+            .dependencies
+            .retain(|pair| *pair != (info.task_id, info.blocker_id));
+
+        Ok((before - store.dependencies.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for MemoryRepository {
+    async fn create_refresh_token(
+        &self,
+        user_id: i32,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, Error> {
+        self.store()
+            .insert_refresh_token(user_id, family_id, token_hash, expires_at)
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error> {
+        let store = self.store();
+
+        store
+            .refresh_tokens
+            .iter()
+            .find(|stored| stored.token_hash == token_hash)
+            .map(|stored| stored.token.clone())
+            .ok_or(Error::RowNotFound)
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        current: &RefreshToken,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<RefreshToken>, Error> {
+        let mut store = self.store();
+
+        let Some(stored) = store
+            .refresh_tokens
+            .iter_mut()
+            .find(|stored| stored.token.id == current.id && stored.token.revoked_at.is_none())
+        else {
+            return Ok(None);
+        };
+        stored.token.revoked_at = Some(Utc::now());
+
+        store
+            .insert_refresh_token(current.user_id, current.family_id, token_hash, expires_at)
+            .map(Some)
+    }
+
+    async fn revoke_token_family(&self, user_id: i32, family_id: Uuid) -> Result<u64, Error> {
+        let mut store = self.store();
+
+        let mut revoked = 0;
+        for stored in store.refresh_tokens.iter_mut() {
+            if stored.token.user_id == user_id
+                && stored.token.family_id == family_id
+                && stored.token.revoked_at.is_none()
+            {
+                stored.token.revoked_at = Some(Utc::now());
+                revoked += 1;
+            }
+        }
+
+        Ok(revoked)
+    }
+}
+
+// There is no schema to migrate, so the store always counts as current
+#[async_trait]
+impl HealthRepository for MemoryRepository {
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> Result<i64, Error> {
+        Ok(latest_version())
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        None
+    }
+
+    async fn close(&self) {}
+}
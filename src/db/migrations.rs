@@ -1,46 +1,476 @@
-use sqlx::Error;
-use sqlx::PgPool;
+use sha2::{Digest, Sha256};
+use sqlx::{Acquire, FromRow, PgConnection, PgPool};
+#[cfg(feature = "sqlite")]
+use sqlx::{SqliteConnection, SqlitePool};
+use std::fmt;
 
-pub async fn run_migrations(pool: &PgPool) -> Result<(), Error> {
+// Arbitrary key for pg_advisory_lock so that concurrent boots apply
+// migrations one at a time
+const MIGRATION_LOCK_ID: i64 = 4_242_717_301;
+
+// A schema change shipped with the binary. `up` is checksummed when applied,
+// so migrations must never be edited once released; add a new one instead.
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+macro_rules! migration {
+    ($version:expr, $name:literal) => {
+        migration!($version, "", $name)
+    };
+    ($version:expr, $dir:literal, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../../migrations/", $dir, $name, ".up.sql")),
+            down: include_str!(concat!("../../migrations/", $dir, $name, ".down.sql")),
+        }
+    };
+}
+
+// Ordered by version. The early up scripts are idempotent so that databases
+// created before migrations were tracked are adopted without errors.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_create_users_and_tasks"),
+    migration!(2, "0002_create_refresh_tokens"),
+    migration!(3, "0003_add_user_roles"),
+    migration!(4, "0004_type_task_status"),
+    migration!(5, "0005_index_task_listing"),
+    migration!(6, "0006_index_user_search"),
+    migration!(7, "0007_create_tags"),
+    migration!(8, "0008_add_task_priority_and_position"),
+    migration!(9, "0009_add_subtasks_and_checklists"),
+    migration!(10, "0010_create_task_dependencies"),
+    migration!(11, "0011_add_task_recurrence"),
+    migration!(12, "0012_index_task_priority_rank"),
+    migration!(13, "0013_unique_task_positions"),
+];
+
+// The same versions for the SQLite backend, written in its dialect
+#[cfg(feature = "sqlite")]
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    migration!(1, "sqlite/", "0001_create_users_and_tasks"),
+    migration!(2, "sqlite/", "0002_create_refresh_tokens"),
+    migration!(3, "sqlite/", "0003_add_user_roles"),
+    migration!(4, "sqlite/", "0004_type_task_status"),
+    migration!(5, "sqlite/", "0005_index_task_listing"),
+    migration!(6, "sqlite/", "0006_index_user_search"),
+    migration!(7, "sqlite/", "0007_create_tags"),
+    migration!(8, "sqlite/", "0008_add_task_priority_and_position"),
+    migration!(9, "sqlite/", "0009_add_subtasks_and_checklists"),
+    migration!(10, "sqlite/", "0010_create_task_dependencies"),
+    migration!(11, "sqlite/", "0011_add_task_recurrence"),
+    migration!(12, "sqlite/", "0012_index_task_priority_rank"),
+    migration!(13, "sqlite/", "0013_unique_task_positions"),
+];
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.up.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Database(sqlx::Error),
+    DatabaseAhead { version: i64, latest: i64 },
+    UnknownMigration { version: i64 },
+    ChecksumMismatch { version: i64, name: String },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrationError::Database(e) => write!(f, "{}", e),
+            MigrationError::DatabaseAhead { version, latest } => write!(
+                f,
+                "Database has migration {} applied but this build only knows up to {}",
+                version, latest
+            ),
+            MigrationError::UnknownMigration { version } => write!(
+                f,
+                "Database has migration {} applied which this build does not know",
+                version
+            ),
+            MigrationError::ChecksumMismatch { version, name } => write!(
+                f,
+                "Migration {} ({}) has changed since it was applied",
+                version, name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(error: sqlx::Error) -> Self {
+        MigrationError::Database(error)
+    }
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+// Refuses a database that is ahead of this build or whose history differs
+// from the migrations shipped with it
+pub fn check_applied(
+    applied: &[AppliedMigration],
+    known: &[Migration],
+) -> Result<(), MigrationError> {
+    let latest = known.last().map_or(0, |migration| migration.version);
+
+    for record in applied {
+        let migration = match known.iter().find(|m| m.version == record.version) {
+            Some(migration) => migration,
+            None if record.version > latest => {
+                return Err(MigrationError::DatabaseAhead {
+                    version: record.version,
+                    latest,
+                })
+            }
+            None => {
+                return Err(MigrationError::UnknownMigration {
+                    version: record.version,
+                })
+            }
+        };
+
+        if migration.checksum() != record.checksum {
+            return Err(MigrationError::ChecksumMismatch {
+                version: migration.version,
+                name: migration.name.to_owned(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+async fn create_migrations_table(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-        CREATE TABLE IF NOT EXISTS users (
-        id SERIAL PRIMARY KEY,
-        username TEXT NOT NULL UNIQUE,
-        password_hash TEXT NOT NULL,
-        email TEXT NOT NULL UNIQUE
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
         );
         "#,
     )
-    .execute(pool)
+    .execute(conn)
     .await?;
 
+    Ok(())
+}
+
+async fn fetch_applied(conn: &mut PgConnection) -> Result<Vec<AppliedMigration>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version",
+    )
+    .fetch_all(conn)
+    .await
+}
+
+// Lists the migrations recorded in `schema_migrations`, oldest first
+pub async fn applied_migrations(pool: &PgPool) -> Result<Vec<AppliedMigration>, MigrationError> {
+    let mut conn = pool.acquire().await?;
+    create_migrations_table(&mut conn).await?;
+
+    Ok(fetch_applied(&mut conn).await?)
+}
+
+async fn lock(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_ID)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn unlock(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_ID)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// Applies every pending migration, each in its own transaction, and returns
+// the ones that were applied
+pub async fn run_migrations(pool: &PgPool) -> Result<Vec<&'static Migration>, MigrationError> {
+    let mut conn = pool.acquire().await?;
+
+    lock(&mut conn).await?;
+    let result = apply_pending(&mut conn).await;
+    unlock(&mut conn).await?;
+
+    result
+}
+
+async fn apply_pending(conn: &mut PgConnection) -> Result<Vec<&'static Migration>, MigrationError> {
+    create_migrations_table(conn).await?;
+    let applied = fetch_applied(conn).await?;
+    check_applied(&applied, MIGRATIONS)?;
+
+    let mut ran = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.iter().any(|a| a.version == migration.version) {
+            continue;
+        }
+
+        let mut tx = conn.begin().await?;
+        sqlx::raw_sql(migration.up).execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        log::info!(
+            "Applied migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        ran.push(migration);
+    }
+
+    Ok(ran)
+}
+
+// Reverts applied migrations newer than `target`, newest first, and returns
+// the ones that were reverted. A target of 0 empties the schema. Rollbacks
+// are an operator action and never run by the server itself, see `taskctl`.
+pub async fn rollback_migrations(
+    pool: &PgPool,
+    target: i64,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    let mut conn = pool.acquire().await?;
+
+    lock(&mut conn).await?;
+    let result = revert_to(&mut conn, target).await;
+    unlock(&mut conn).await?;
+
+    result
+}
+
+async fn revert_to(
+    conn: &mut PgConnection,
+    target: i64,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    create_migrations_table(conn).await?;
+    let applied = fetch_applied(conn).await?;
+    check_applied(&applied, MIGRATIONS)?;
+
+    let mut reverted = Vec::new();
+    for migration in MIGRATIONS.iter().rev() {
+        if migration.version <= target || !applied.iter().any(|a| a.version == migration.version) {
+            continue;
+        }
+
+        let mut tx = conn.begin().await?;
+        sqlx::raw_sql(migration.down).execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM schema_migrations WHERE version = $1")
+            .bind(migration.version)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        log::info!(
+            "Reverted migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        reverted.push(migration);
+    }
+
+    Ok(reverted)
+}
+
+// SQLite serializes writers on its own, so unlike the Postgres runner this
+// needs no advisory lock
+#[cfg(feature = "sqlite")]
+async fn create_sqlite_migrations_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-        CREATE TABLE IF NOT EXISTS tasks (
-            id SERIAL PRIMARY KEY,
-            title TEXT NOT NULL,
-            description TEXT,
-            due_date TIMESTAMP,
-            status TEXT NOT NULL,
-            user_id INTEGER REFERENCES users(id)
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at TEXT NOT NULL
         );
         "#,
     )
-    .execute(pool)
+    .execute(conn)
     .await?;
 
     Ok(())
 }
 
+#[cfg(feature = "sqlite")]
+async fn fetch_sqlite_applied(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<AppliedMigration>, sqlx::Error> {
+    create_sqlite_migrations_table(conn).await?;
+
+    sqlx::query_as(
+        "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version",
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[cfg(feature = "sqlite")]
+pub async fn applied_sqlite_migrations(
+    pool: &SqlitePool,
+) -> Result<Vec<AppliedMigration>, MigrationError> {
+    let mut conn = pool.acquire().await?;
+
+    Ok(fetch_sqlite_applied(&mut conn).await?)
+}
+
+#[cfg(feature = "sqlite")]
+pub async fn run_sqlite_migrations(
+    pool: &SqlitePool,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    let mut conn = pool.acquire().await?;
+    let applied = fetch_sqlite_applied(&mut conn).await?;
+    check_applied(&applied, SQLITE_MIGRATIONS)?;
+
+    let mut ran = Vec::new();
+    for migration in SQLITE_MIGRATIONS {
+        if applied.iter().any(|a| a.version == migration.version) {
+            continue;
+        }
+
+        let mut tx = conn.begin().await?;
+        sqlx::raw_sql(migration.up).execute(&mut *tx).await?;
+        sqlx::query(
+            "INSERT INTO schema_migrations (version, name, checksum, applied_at) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum())
+        .bind(chrono::Utc::now())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        log::info!(
+            "Applied migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        ran.push(migration);
+    }
+
+    Ok(ran)
+}
+
+#[cfg(feature = "sqlite")]
+pub async fn rollback_sqlite_migrations(
+    pool: &SqlitePool,
+    target: i64,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    let mut conn = pool.acquire().await?;
+    let applied = fetch_sqlite_applied(&mut conn).await?;
+    check_applied(&applied, SQLITE_MIGRATIONS)?;
+
+    let mut reverted = Vec::new();
+    for migration in SQLITE_MIGRATIONS.iter().rev() {
+        if migration.version <= target || !applied.iter().any(|a| a.version == migration.version) {
+            continue;
+        }
+
+        let mut tx = conn.begin().await?;
+        sqlx::raw_sql(migration.down).execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM schema_migrations WHERE version = $1")
+            .bind(migration.version)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        log::info!(
+            "Reverted migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        reverted.push(migration);
+    }
+
+    Ok(reverted)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use actix_rt::test;
     use dotenv::dotenv;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
     use sqlx::Error;
     use sqlx::PgPool;
     use std::env;
+    use std::str::FromStr;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    async fn table_exists(pool: &PgPool, table: &str) -> Result<bool, Error> {
+        let row: (bool,) = sqlx::query_as(
+            "SELECT EXISTS (SELECT FROM pg_tables WHERE schemaname = current_schema() AND tablename = $1)",
+        )
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+        Ok(row.0)
+    }
+
+    // A pool whose search_path points at a new, empty schema
+    async fn scratch_pool() -> Result<(PgPool, String), Error> {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let schema = format!(
+            "migrations_test_{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+
+        let admin = PgPool::connect(&database_url).await?;
+        sqlx::query(&format!("CREATE SCHEMA {}", schema))
+            .execute(&admin)
+            .await?;
+
+        let options =
+            PgConnectOptions::from_str(&database_url)?.options([("search_path", schema.as_str())]);
+        let pool = PgPoolOptions::new().connect_with(options).await?;
+        Ok((pool, schema))
+    }
+
+    async fn drop_schema(pool: &PgPool, schema: &str) -> Result<(), Error> {
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", schema))
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
 
     #[test]
     async fn test_run_migrations() -> Result<(), Error> {
@@ -49,17 +479,122 @@ mod tests {
 
         let pool = PgPool::connect(&database_url).await?;
 
-        run_migrations(&pool).await?;
-        let row: (bool,) =
-            sqlx::query_as("SELECT EXISTS (SELECT FROM pg_tables WHERE tablename = 'users')")
-                .fetch_one(&pool)
-                .await?;
-        assert!(row.0, "users table does not exist");
-        let row: (bool,) =
-            sqlx::query_as("SELECT EXISTS (SELECT FROM pg_tables WHERE tablename = 'tasks')")
-                .fetch_one(&pool)
-                .await?;
-        assert!(row.0, "tasks table does not exist");
+        run_migrations(&pool).await.unwrap();
+        assert!(
+            table_exists(&pool, "users").await?,
+            "users table does not exist"
+        );
+        assert!(
+            table_exists(&pool, "tasks").await?,
+            "tasks table does not exist"
+        );
+        assert!(
+            table_exists(&pool, "refresh_tokens").await?,
+            "refresh_tokens table does not exist"
+        );
+
+        let applied = applied_migrations(&pool).await.unwrap();
+        assert_eq!(applied.last().map(|a| a.version), Some(latest_version()));
+
+        Ok(())
+    }
+
+    #[test]
+    async fn test_migrations_roll_back_and_reapply() -> Result<(), Error> {
+        let (pool, schema) = scratch_pool().await?;
+
+        let ran = run_migrations(&pool).await.unwrap();
+        assert_eq!(ran.len(), MIGRATIONS.len());
+        assert!(run_migrations(&pool).await.unwrap().is_empty());
+
+        let reverted = rollback_migrations(&pool, 2).await.unwrap();
+        assert_eq!(reverted.first().map(|m| m.version), Some(latest_version()));
+        assert_eq!(reverted.last().map(|m| m.version), Some(3));
+        assert!(table_exists(&pool, "refresh_tokens").await?);
+
+        rollback_migrations(&pool, 0).await.unwrap();
+        assert!(!table_exists(&pool, "users").await?);
+        assert!(!table_exists(&pool, "tasks").await?);
+        assert!(applied_migrations(&pool).await.unwrap().is_empty());
+
+        let ran = run_migrations(&pool).await.unwrap();
+        assert_eq!(ran.len(), MIGRATIONS.len());
+        assert!(table_exists(&pool, "tasks").await?);
+
+        drop_schema(&pool, &schema).await
+    }
+
+    #[test]
+    async fn test_run_migrations_refuses_unknown_history() -> Result<(), Error> {
+        let (pool, schema) = scratch_pool().await?;
+        run_migrations(&pool).await.unwrap();
+
+        sqlx::query("UPDATE schema_migrations SET checksum = 'edited' WHERE version = 1")
+            .execute(&pool)
+            .await?;
+        let error = run_migrations(&pool).await.unwrap_err();
+        assert!(matches!(
+            error,
+            MigrationError::ChecksumMismatch { version: 1, .. }
+        ));
+
+        sqlx::query("DELETE FROM schema_migrations WHERE version = 1")
+            .execute(&pool)
+            .await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, 'from_the_future', '')")
+            .bind(latest_version() + 1)
+            .execute(&pool)
+            .await?;
+        let error = run_migrations(&pool).await.unwrap_err();
+        assert!(matches!(error, MigrationError::DatabaseAhead { .. }));
+
+        drop_schema(&pool, &schema).await
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    async fn test_sqlite_migrations_roll_back_and_reapply() -> Result<(), Error> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        let table_count = |table: &'static str| {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = $1",
+            )
+            .bind(table)
+            .fetch_one(&pool)
+        };
+
+        let ran = run_sqlite_migrations(&pool).await.unwrap();
+        assert_eq!(ran.len(), SQLITE_MIGRATIONS.len());
+        assert!(run_sqlite_migrations(&pool).await.unwrap().is_empty());
+        assert_eq!(table_count("tasks").await?, 1);
+
+        // Dropping subtasks rebuilds the tasks table, keeping their tags
+        sqlx::raw_sql(
+            "INSERT INTO users (id, username, password_hash, email) VALUES (1, 'a', '', 'a@a'); \
+             INSERT INTO tasks (id, title, status, user_id, position) VALUES (1, 'a', 'todo', 1, 'a'); \
+             INSERT INTO tasks (id, title, status, user_id, position, parent_id) \
+             VALUES (2, 'b', 'todo', 1, 'b', 1); \
+             INSERT INTO tags (id, user_id, name) VALUES (1, 1, 'a'); \
+             INSERT INTO task_tags (task_id, tag_id) VALUES (1, 1);",
+        )
+        .execute(&pool)
+        .await?;
+        rollback_sqlite_migrations(&pool, 8).await.unwrap();
+        let tags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM task_tags")
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(tags, 1);
+
+        rollback_sqlite_migrations(&pool, 0).await.unwrap();
+        assert_eq!(table_count("users").await?, 0);
+        assert!(applied_sqlite_migrations(&pool).await.unwrap().is_empty());
+
+        let ran = run_sqlite_migrations(&pool).await.unwrap();
+        assert_eq!(ran.len(), SQLITE_MIGRATIONS.len());
+        assert_eq!(table_count("refresh_tokens").await?, 1);
 
         Ok(())
     }
@@ -1,3 +1,9 @@
+#[allow(clippy::module_inception)]
 pub mod db;
+pub mod memory;
 pub mod migrations;
+pub mod postgres;
+pub mod repository;
 pub mod server;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
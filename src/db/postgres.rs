@@ -0,0 +1,245 @@
+use crate::db::db::{
+    add_dependency_db, attach_tag_db, authenticate_user, count_users_db, create_checklist_item_db,
+    create_refresh_token_db, create_tag_db, create_task_db, create_user_db,
+    delete_checklist_item_db, delete_tag_db, delete_user_task_db, detach_tag_db, get_all_users_db,
+    get_refresh_token_db, get_schema_version_db, get_user_board_db, get_user_by_username_db,
+    get_user_role_db, get_user_tags_db, get_user_task_db, get_user_tasks_db, move_user_task_db,
+    patch_user_task_db, ping_db, remove_dependency_db, rename_tag_db, reset_user_password_db,
+    revoke_token_family_db, rotate_refresh_token_db, set_user_role_db, update_checklist_item_db,
+    update_user_task_db,
+};
+use crate::db::repository::{
+    ChecklistRepository, DependencyRepository, HealthRepository, RefreshTokenRepository,
+    TagRepository, TaskRepository, UserRepository,
+};
+use crate::metrics::metrics::PoolStatus;
+use crate::model::models::{
+    ChecklistItem, ChecklistItemInfo, ChecklistItemPatch, DependencyInfo, Info, Placement,
+    RefreshToken, Role, Tag, TagInfo, Task, TaskCursor, TaskInput, TaskPatch, TaskQuery,
+    TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite, User, UserInput, UserQuery,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+// The production backend, a thin wrapper around the queries in `db::db`
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> Self {
+        PgRepository { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgRepository {
+    async fn create_user(&self, new_user: &UserInput) -> Result<User, Error> {
+        create_user_db(&self.pool, new_user).await
+    }
+
+    async fn get_users(
+        &self,
+        filter: &UserQuery,
+        after_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<User>, Error> {
+        get_all_users_db(&self.pool, filter, after_id, limit).await
+    }
+
+    async fn count_users(&self, filter: &UserQuery) -> Result<i64, Error> {
+        count_users_db(&self.pool, filter).await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<User, Error> {
+        get_user_by_username_db(&self.pool, username).await
+    }
+
+    async fn get_user_role(&self, user_id: i32) -> Result<Role, Error> {
+        get_user_role_db(&self.pool, user_id).await
+    }
+
+    async fn set_user_role(&self, user_id: i32, role: Role) -> Result<u64, Error> {
+        set_user_role_db(&self.pool, user_id, role).await
+    }
+
+    async fn reset_user_password(&self, user_id: i32, password: &str) -> Result<u64, Error> {
+        reset_user_password_db(&self.pool, user_id, password).await
+    }
+
+    async fn authenticate_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<User>, Error> {
+        authenticate_user(&self.pool, username, password).await
+    }
+}
+
+#[async_trait]
+impl TaskRepository for PgRepository {
+    async fn create_task(&self, new_task: &TaskInput, user_id: i32) -> Result<TaskWrite, Error> {
+        create_task_db(&self.pool, new_task, user_id).await
+    }
+
+    async fn get_user_tasks(
+        &self,
+        user_id: i32,
+        filter: &TaskQuery,
+        after: Option<&TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<Task>, Error> {
+        get_user_tasks_db(&self.pool, user_id, filter, after, limit).await
+    }
+
+    async fn get_user_task(&self, user_id: i32, task_id: i32) -> Result<Task, Error> {
+        get_user_task_db(&self.pool, user_id, task_id).await
+    }
+
+    async fn update_user_task(
+        &self,
+        info: Info,
+        task_update: TaskUpdate,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error> {
+        update_user_task_db(&self.pool, info, task_update, current_status).await
+    }
+
+    async fn patch_user_task(
+        &self,
+        info: Info,
+        patch: TaskPatch,
+        current_status: Option<TaskStatus>,
+    ) -> Result<TaskWrite, Error> {
+        patch_user_task_db(&self.pool, info, patch, current_status).await
+    }
+
+    async fn delete_user_task(&self, info: Info) -> Result<u64, Error> {
+        delete_user_task_db(&self.pool, info).await
+    }
+
+    async fn move_user_task(
+        &self,
+        info: Info,
+        status: TaskStatus,
+        placement: Placement,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error> {
+        move_user_task_db(&self.pool, info, status, placement, current_status).await
+    }
+
+    async fn get_user_board(&self, user_id: i32) -> Result<Vec<Task>, Error> {
+        get_user_board_db(&self.pool, user_id).await
+    }
+}
+
+#[async_trait]
+impl TagRepository for PgRepository {
+    async fn create_tag(&self, user_id: i32, name: &str) -> Result<Tag, Error> {
+        create_tag_db(&self.pool, user_id, name).await
+    }
+
+    async fn get_user_tags(&self, user_id: i32) -> Result<Vec<Tag>, Error> {
+        get_user_tags_db(&self.pool, user_id).await
+    }
+
+    async fn rename_tag(&self, info: TagInfo, name: &str) -> Result<Tag, Error> {
+        rename_tag_db(&self.pool, info, name).await
+    }
+
+    async fn delete_tag(&self, info: TagInfo) -> Result<u64, Error> {
+        delete_tag_db(&self.pool, info).await
+    }
+
+    async fn attach_tag(&self, info: TaskTagInfo) -> Result<(), Error> {
+        attach_tag_db(&self.pool, info).await
+    }
+
+    async fn detach_tag(&self, info: TaskTagInfo) -> Result<u64, Error> {
+        detach_tag_db(&self.pool, info).await
+    }
+}
+
+#[async_trait]
+impl ChecklistRepository for PgRepository {
+    async fn create_checklist_item(&self, info: Info, title: &str) -> Result<ChecklistItem, Error> {
+        create_checklist_item_db(&self.pool, info, title).await
+    }
+
+    async fn update_checklist_item(
+        &self,
+        info: ChecklistItemInfo,
+        patch: ChecklistItemPatch,
+    ) -> Result<ChecklistItem, Error> {
+        update_checklist_item_db(&self.pool, info, patch).await
+    }
+
+    async fn delete_checklist_item(&self, info: ChecklistItemInfo) -> Result<u64, Error> {
+        delete_checklist_item_db(&self.pool, info).await
+    }
+}
+
+#[async_trait]
+impl DependencyRepository for PgRepository {
+    async fn add_dependency(&self, info: DependencyInfo) -> Result<bool, Error> {
+        add_dependency_db(&self.pool, info).await
+    }
+
+    async fn remove_dependency(&self, info: DependencyInfo) -> Result<u64, Error> {
+        remove_dependency_db(&self.pool, info).await
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for PgRepository {
+    async fn create_refresh_token(
+        &self,
+        user_id: i32,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, Error> {
+        create_refresh_token_db(&self.pool, user_id, family_id, token_hash, expires_at).await
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error> {
+        get_refresh_token_db(&self.pool, token_hash).await
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        current: &RefreshToken,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<RefreshToken>, Error> {
+        rotate_refresh_token_db(&self.pool, current, token_hash, expires_at).await
+    }
+
+    async fn revoke_token_family(&self, user_id: i32, family_id: Uuid) -> Result<u64, Error> {
+        revoke_token_family_db(&self.pool, user_id, family_id).await
+    }
+}
+
+#[async_trait]
+impl HealthRepository for PgRepository {
+    async fn ping(&self) -> Result<(), Error> {
+        ping_db(&self.pool).await
+    }
+
+    async fn schema_version(&self) -> Result<i64, Error> {
+        get_schema_version_db(&self.pool).await
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        Some(PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+        })
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
@@ -0,0 +1,1150 @@
+use crate::metrics::metrics::PoolStatus;
+use crate::model::models::{
+    ChecklistItem, ChecklistItemInfo, ChecklistItemPatch, DependencyInfo, Info, Placement,
+    RefreshToken, Role, Tag, TagInfo, Task, TaskCursor, TaskInput, TaskPatch, TaskQuery,
+    TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite, User, UserInput, UserQuery,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+// Storage used by the handlers, held in `AppState`. Implementations report
+// failures as `sqlx::Error` so the mapping to `ApiError` stays the same for
+// every backend: `RowNotFound` for missing rows and a unique violation for
+// duplicates.
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create_user(&self, new_user: &UserInput) -> Result<User, Error>;
+
+    // Up to `limit` users matching `filter` in id order, after `after_id`
+    async fn get_users(
+        &self,
+        filter: &UserQuery,
+        after_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<User>, Error>;
+
+    async fn count_users(&self, filter: &UserQuery) -> Result<i64, Error>;
+
+    async fn get_user_by_username(&self, username: &str) -> Result<User, Error>;
+
+    async fn get_user_role(&self, user_id: i32) -> Result<Role, Error>;
+
+    async fn set_user_role(&self, user_id: i32, role: Role) -> Result<u64, Error>;
+
+    // Also revokes every refresh token of the user
+    async fn reset_user_password(&self, user_id: i32, password: &str) -> Result<u64, Error>;
+
+    // `None` when the username is unknown or the password does not match
+    async fn authenticate_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<User>, Error>;
+}
+
+#[async_trait]
+pub trait TaskRepository: Send + Sync {
+    // A task given a parent is only written while the parent is a task of
+    // the user that it fits under, checked in the same write; otherwise the
+    // reason comes back as `TaskWrite::InvalidParent`
+    async fn create_task(&self, new_task: &TaskInput, user_id: i32) -> Result<TaskWrite, Error>;
+
+    // Up to `limit` tasks of the user matching `filter`, ordered by
+    // `filter.sort` and `filter.order` with `id` as tie-breaker, after `after`
+    async fn get_user_tasks(
+        &self,
+        user_id: i32,
+        filter: &TaskQuery,
+        after: Option<&TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<Task>, Error>;
+
+    async fn get_user_task(&self, user_id: i32, task_id: i32) -> Result<Task, Error>;
+
+    // Updates apply only while the task is still in `current_status`, so a
+    // concurrent status change surfaces as `RowNotFound`. A task changing
+    // status goes to the end of its new column. Completing a task also, in
+    // the same write, marks its subtasks at every level that may move to done
+    // as done, appending them to the done column, and creates the next
+    // occurrence of a recurring task; blocked subtasks stay blocked. A task
+    // is only completed while neither it nor those subtasks wait for an open
+    // task outside them, checked in the same write; otherwise nothing is
+    // written and the open tasks come back as `TaskWrite::Blocked`.
+    async fn update_user_task(
+        &self,
+        info: Info,
+        task_update: TaskUpdate,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error>;
+
+    // Like `update_user_task` when the patch sets a status, which is then
+    // checked against `current_status`. `None` when the patch leaves the
+    // status alone, so the write is not guarded by it. A new parent is
+    // checked like in `create_task`, and must not be one of the task's
+    // subtasks.
+    async fn patch_user_task(
+        &self,
+        info: Info,
+        patch: TaskPatch,
+        current_status: Option<TaskStatus>,
+    ) -> Result<TaskWrite, Error>;
+
+    // Also deletes the subtasks of the task, at every level
+    async fn delete_user_task(&self, info: Info) -> Result<u64, Error>;
+
+    // Puts the task in the `status` column as `placement` says, under the
+    // same `current_status` guard as updates. Also `RowNotFound` when the
+    // task to place it next to is not in that column.
+    async fn move_user_task(
+        &self,
+        info: Info,
+        status: TaskStatus,
+        placement: Placement,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error>;
+
+    // Every task of the user in position order
+    async fn get_user_board(&self, user_id: i32) -> Result<Vec<Task>, Error>;
+}
+
+// Tags are per user; every call is scoped to the user owning them
+#[async_trait]
+pub trait TagRepository: Send + Sync {
+    async fn create_tag(&self, user_id: i32, name: &str) -> Result<Tag, Error>;
+
+    // In name order
+    async fn get_user_tags(&self, user_id: i32) -> Result<Vec<Tag>, Error>;
+
+    async fn rename_tag(&self, info: TagInfo, name: &str) -> Result<Tag, Error>;
+
+    // Also detaches the tag from every task
+    async fn delete_tag(&self, info: TagInfo) -> Result<u64, Error>;
+
+    // `RowNotFound` unless both the task and the tag belong to the user.
+    // Attaching a tag twice is not an error.
+    async fn attach_tag(&self, info: TaskTagInfo) -> Result<(), Error>;
+
+    async fn detach_tag(&self, info: TaskTagInfo) -> Result<u64, Error>;
+}
+
+// Checklist items are reached through their task, so every call is scoped to
+// a task of the user
+#[async_trait]
+pub trait ChecklistRepository: Send + Sync {
+    // `RowNotFound` unless the task belongs to the user
+    async fn create_checklist_item(&self, info: Info, title: &str) -> Result<ChecklistItem, Error>;
+
+    // Changes only the fields supplied in `patch`
+    async fn update_checklist_item(
+        &self,
+        info: ChecklistItemInfo,
+        patch: ChecklistItemPatch,
+    ) -> Result<ChecklistItem, Error>;
+
+    async fn delete_checklist_item(&self, info: ChecklistItemInfo) -> Result<u64, Error>;
+}
+
+// Dependencies link two tasks of the same user: `task_id` waits for
+// `blocker_id`
+#[async_trait]
+pub trait DependencyRepository: Send + Sync {
+    // `RowNotFound` unless both tasks belong to the user. Adding a dependency
+    // twice is not an error. Returns `false`, adding nothing, when the blocker
+    // already waits for the task, directly or through other blockers. The
+    // check and the insert are one write, so two requests linking a pair of
+    // tasks both ways cannot both succeed.
+    async fn add_dependency(&self, info: DependencyInfo) -> Result<bool, Error>;
+
+    async fn remove_dependency(&self, info: DependencyInfo) -> Result<u64, Error>;
+}
+
+#[async_trait]
+pub trait RefreshTokenRepository: Send + Sync {
+    async fn create_refresh_token(
+        &self,
+        user_id: i32,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, Error>;
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error>;
+
+    // Revokes `current` and issues its successor in the same family. `None`
+    // when `current` was already revoked, i.e. the token is being reused.
+    async fn rotate_refresh_token(
+        &self,
+        current: &RefreshToken,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<RefreshToken>, Error>;
+
+    async fn revoke_token_family(&self, user_id: i32, family_id: Uuid) -> Result<u64, Error>;
+}
+
+// Operational access to the backing store, for probes and shutdown
+#[async_trait]
+pub trait HealthRepository: Send + Sync {
+    // Round-trips a trivial query
+    async fn ping(&self) -> Result<(), Error>;
+
+    // Latest applied migration, 0 before the first one
+    async fn schema_version(&self) -> Result<i64, Error>;
+
+    // `None` for backends without a connection pool
+    fn pool_status(&self) -> Option<PoolStatus>;
+
+    // Waits for checked out connections to be returned, then closes them
+    async fn close(&self);
+}
+
+// Every backend must behave the same, so the same checks run against each
+#[cfg(test)]
+mod tests {
+    use crate::config::settings::Settings;
+    use crate::db::migrations::{latest_version, run_migrations};
+    use crate::model::models::{
+        ChecklistItemInfo, ChecklistItemPatch, DependencyInfo, Info, InvalidParent, Placement,
+        Progress, Role, SortOrder, TagInfo, Task, TaskCursor, TaskInput, TaskPatch, TaskPriority,
+        TaskQuery, TaskSort, TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite, User, UserInput,
+    };
+    use crate::model::state::AppState;
+    use chrono::{Duration, NaiveDateTime, Utc};
+    use dotenv::dotenv;
+    use sqlx::PgPool;
+    use std::env;
+    use uuid::Uuid;
+
+    fn days_from_now(days: i64) -> NaiveDateTime {
+        (Utc::now() + Duration::days(days)).naive_utc()
+    }
+
+    fn written(write: TaskWrite) -> Task {
+        match write {
+            TaskWrite::Written(task) => task,
+            other => panic!("expected the task to be written, got {:?}", other),
+        }
+    }
+
+    async fn board_ids(state: &AppState, user_id: i32) -> Vec<i32> {
+        let tasks = state.tasks.get_user_board(user_id).await.unwrap();
+        tasks.into_iter().map(|task| task.id).collect()
+    }
+
+    // Each check signs up a user of its own, so they never see each other's
+    // tasks, tags or tokens
+    async fn contract_user(state: &AppState) -> User {
+        let name = format!("contract{}", Uuid::new_v4().simple());
+        state
+            .users
+            .create_user(&UserInput {
+                username: name.clone(),
+                password: "securepassword123".into(),
+                email: format!("{}@example.com", name),
+            })
+            .await
+            .unwrap()
+    }
+
+    async fn contract_task(state: &AppState, user_id: i32, title: &str) -> Task {
+        let input = TaskInput {
+            title: title.into(),
+            description: "Contract".into(),
+            due_date: None,
+            status: TaskStatus::Todo,
+            priority: TaskPriority::default(),
+            parent_id: None,
+            recurrence: None,
+        };
+        written(state.tasks.create_task(&input, user_id).await.unwrap())
+    }
+
+    async fn check_health_repository(state: &AppState) {
+        state.health.ping().await.unwrap();
+        assert_eq!(
+            state.health.schema_version().await.unwrap(),
+            latest_version()
+        );
+    }
+
+    async fn check_user_repository(state: &AppState) {
+        let name = format!("contract{}", Uuid::new_v4().simple());
+        let input = UserInput {
+            username: name.clone(),
+            password: "securepassword123".into(),
+            email: format!("{}@example.com", name),
+        };
+        let user = state.users.create_user(&input).await.unwrap();
+        assert_eq!(user.role, Role::Member);
+        match state.users.create_user(&input).await {
+            Err(sqlx::Error::Database(e)) => assert!(e.is_unique_violation()),
+            other => panic!("expected a unique violation, got {:?}", other),
+        }
+        assert!(state
+            .users
+            .authenticate_user(&input.username, &input.password)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(state
+            .users
+            .authenticate_user(&input.username, "wrongpassword")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    async fn check_task_repository(state: &AppState) {
+        let user = contract_user(state).await;
+        let mut ids = Vec::new();
+        for (title, due_in, priority) in [
+            ("b", Some(2), TaskPriority::High),
+            ("a", None, TaskPriority::Low),
+            ("c", Some(1), TaskPriority::Urgent),
+        ] {
+            let task = written(
+                state
+                    .tasks
+                    .create_task(
+                        &TaskInput {
+                            title: title.into(),
+                            description: "Contract".into(),
+                            due_date: due_in.map(days_from_now),
+                            status: TaskStatus::Todo,
+                            priority,
+                            parent_id: None,
+                            recurrence: None,
+                        },
+                        user.id,
+                    )
+                    .await
+                    .unwrap(),
+            );
+            ids.push(task.id);
+        }
+
+        // Walks every page of one task per page
+        let filter = TaskQuery {
+            sort: TaskSort::DueDate,
+            ..Default::default()
+        };
+        let mut after = None;
+        let mut seen = Vec::new();
+        loop {
+            let page = state
+                .tasks
+                .get_user_tasks(user.id, &filter, after.as_ref(), 1)
+                .await
+                .unwrap();
+            let Some(task) = page.first() else { break };
+            seen.push(task.id);
+            after = Some(TaskCursor::after(task, filter.sort, filter.order));
+        }
+        assert_eq!(seen, vec![ids[2], ids[0], ids[1]]);
+
+        let filter = TaskQuery {
+            sort: TaskSort::Title,
+            order: SortOrder::Desc,
+            ..Default::default()
+        };
+        let titles: Vec<String> = state
+            .tasks
+            .get_user_tasks(user.id, &filter, None, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|task| task.title)
+            .collect();
+        assert_eq!(titles, ["c", "b", "a"]);
+
+        let filter = TaskQuery {
+            sort: TaskSort::Priority,
+            order: SortOrder::Desc,
+            ..Default::default()
+        };
+        let first = state
+            .tasks
+            .get_user_tasks(user.id, &filter, None, 1)
+            .await
+            .unwrap();
+        let after = TaskCursor::after(&first[0], filter.sort, filter.order);
+        let rest: Vec<i32> = state
+            .tasks
+            .get_user_tasks(user.id, &filter, Some(&after), 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(first[0].id, ids[2]);
+        assert_eq!(rest, vec![ids[0], ids[1]]);
+        let urgent = TaskQuery {
+            priority: Some(TaskPriority::Urgent),
+            ..Default::default()
+        };
+        let urgent = state
+            .tasks
+            .get_user_tasks(user.id, &urgent, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(urgent.len(), 1);
+
+        // New tasks were appended to the column; move the last one around
+        assert_eq!(board_ids(state, user.id).await, ids);
+        let last = Info {
+            user_id: user.id,
+            task_id: ids[2],
+        };
+        let moved = written(
+            state
+                .tasks
+                .move_user_task(
+                    last.clone(),
+                    TaskStatus::Todo,
+                    Placement::Before(ids[0]),
+                    TaskStatus::Todo,
+                )
+                .await
+                .unwrap(),
+        );
+        assert!(moved.completed_at.is_none());
+        assert_eq!(
+            board_ids(state, user.id).await,
+            vec![ids[2], ids[0], ids[1]]
+        );
+        for (placement, board) in [
+            (Placement::After(ids[0]), vec![ids[0], ids[2], ids[1]]),
+            (Placement::End, vec![ids[0], ids[1], ids[2]]),
+        ] {
+            state
+                .tasks
+                .move_user_task(last.clone(), TaskStatus::Todo, placement, TaskStatus::Todo)
+                .await
+                .unwrap();
+            assert_eq!(board_ids(state, user.id).await, board);
+        }
+        let elsewhere = state
+            .tasks
+            .move_user_task(
+                last.clone(),
+                TaskStatus::Blocked,
+                Placement::Before(ids[0]),
+                TaskStatus::Todo,
+            )
+            .await;
+        assert!(matches!(elsewhere, Err(sqlx::Error::RowNotFound)));
+
+        let info = Info {
+            user_id: user.id,
+            task_id: ids[0],
+        };
+        let update = || TaskUpdate {
+            title: "b".into(),
+            description: "Contract".into(),
+            due_date: None,
+            status: TaskStatus::Done,
+            priority: TaskPriority::default(),
+            recurrence: Some("FREQ=DAILY".into()),
+        };
+        let stale = state
+            .tasks
+            .update_user_task(info.clone(), update(), TaskStatus::Blocked)
+            .await;
+        assert!(matches!(stale, Err(sqlx::Error::RowNotFound)));
+        let done = written(
+            state
+                .tasks
+                .update_user_task(info.clone(), update(), TaskStatus::Todo)
+                .await
+                .unwrap(),
+        );
+        assert!(done.completed_at.is_some());
+        assert_eq!(done.recurrence.as_deref(), Some("FREQ=DAILY"));
+
+        let reopened = written(
+            state
+                .tasks
+                .patch_user_task(
+                    info.clone(),
+                    TaskPatch {
+                        status: Some(TaskStatus::Todo),
+                        due_date: Some(Some(days_from_now(3))),
+                        ..Default::default()
+                    },
+                    Some(TaskStatus::Done),
+                )
+                .await
+                .unwrap(),
+        );
+        assert!(reopened.completed_at.is_none());
+        assert!(reopened.due_date.is_some());
+        assert_eq!(reopened.title, "b");
+        assert_eq!(reopened.recurrence.as_deref(), Some("FREQ=DAILY"));
+        // Changing status went to the end of the done, then of the todo column
+        assert_eq!(
+            board_ids(state, user.id).await,
+            vec![ids[1], ids[2], ids[0]]
+        );
+        let one_off = written(
+            state
+                .tasks
+                .patch_user_task(
+                    info.clone(),
+                    TaskPatch {
+                        recurrence: Some(None),
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .await
+                .unwrap(),
+        );
+        assert_eq!(one_off.recurrence, None);
+
+        // A task with a subtask and a sub-subtask
+        let parent = Info {
+            user_id: user.id,
+            task_id: ids[1],
+        };
+        let nested = |parent_id| TaskInput {
+            title: "sub".into(),
+            description: "Contract".into(),
+            due_date: None,
+            status: TaskStatus::Todo,
+            priority: TaskPriority::default(),
+            parent_id: Some(parent_id),
+            recurrence: None,
+        };
+        let mut subtask_ids = Vec::new();
+        for _ in 0..2 {
+            let parent_id = subtask_ids.last().copied().unwrap_or(ids[1]);
+            let subtask = written(
+                state
+                    .tasks
+                    .create_task(&nested(parent_id), user.id)
+                    .await
+                    .unwrap(),
+            );
+            assert_eq!(subtask.parent_id, Some(parent_id));
+            subtask_ids.push(subtask.id);
+        }
+        let invalid_parent = |write| match write {
+            TaskWrite::InvalidParent(invalid) => invalid,
+            other => panic!("expected the parent to be refused, got {:?}", other),
+        };
+        assert_eq!(
+            invalid_parent(state.tasks.create_task(&nested(0), user.id).await.unwrap()),
+            InvalidParent::Missing
+        );
+        // Under the sub-subtask, a task is as deep as subtasks go
+        let deepest = written(
+            state
+                .tasks
+                .create_task(&nested(subtask_ids[1]), user.id)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(
+            invalid_parent(
+                state
+                    .tasks
+                    .create_task(&nested(deepest.id), user.id)
+                    .await
+                    .unwrap()
+            ),
+            InvalidParent::TooDeep
+        );
+        state
+            .tasks
+            .delete_user_task(Info {
+                user_id: user.id,
+                task_id: deepest.id,
+            })
+            .await
+            .unwrap();
+        let under_own_subtask = TaskPatch {
+            parent_id: Some(Some(subtask_ids[1])),
+            ..Default::default()
+        };
+        assert_eq!(
+            invalid_parent(
+                state
+                    .tasks
+                    .patch_user_task(parent.clone(), under_own_subtask, None)
+                    .await
+                    .unwrap()
+            ),
+            InvalidParent::Cycle
+        );
+        let task = state.tasks.get_user_task(user.id, ids[1]).await.unwrap();
+        assert_eq!(task.parent_id, None);
+        assert_eq!(task.progress, Progress { done: 0, total: 1 });
+        let children = TaskQuery {
+            parent_id: Some(ids[1]),
+            ..Default::default()
+        };
+        let children = state
+            .tasks
+            .get_user_tasks(user.id, &children, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].id, subtask_ids[0]);
+
+        // Completing the task completes its subtasks, other than a blocked one
+        // the workflow keeps from moving to done
+        let blocked = written(
+            state
+                .tasks
+                .create_task(
+                    &TaskInput {
+                        title: "stuck".into(),
+                        status: TaskStatus::Blocked,
+                        ..nested(ids[1])
+                    },
+                    user.id,
+                )
+                .await
+                .unwrap(),
+        );
+        let completion = TaskPatch {
+            status: Some(TaskStatus::Done),
+            ..Default::default()
+        };
+        let task = written(
+            state
+                .tasks
+                .patch_user_task(parent.clone(), completion, Some(TaskStatus::Todo))
+                .await
+                .unwrap(),
+        );
+        assert_eq!(task.progress, Progress { done: 1, total: 2 });
+        let blocked = state
+            .tasks
+            .get_user_task(user.id, blocked.id)
+            .await
+            .unwrap();
+        assert_eq!(blocked.status, TaskStatus::Blocked);
+        assert!(blocked.completed_at.is_none());
+        let deepest = state
+            .tasks
+            .get_user_task(user.id, subtask_ids[1])
+            .await
+            .unwrap();
+        assert_eq!(deepest.status, TaskStatus::Done);
+        assert!(deepest.completed_at.is_some());
+
+        // Deleting a task deletes its subtasks at every level
+        assert_eq!(state.tasks.delete_user_task(parent).await.unwrap(), 1);
+        for subtask_id in subtask_ids {
+            assert!(matches!(
+                state.tasks.get_user_task(user.id, subtask_id).await,
+                Err(sqlx::Error::RowNotFound)
+            ));
+        }
+
+        assert_eq!(state.tasks.delete_user_task(info.clone()).await.unwrap(), 1);
+        assert_eq!(state.tasks.delete_user_task(info).await.unwrap(), 0);
+
+        // Completing a recurring task creates its next occurrence in the same
+        // write, with the tags and an unticked checklist
+        let recurring = written(
+            state
+                .tasks
+                .create_task(
+                    &TaskInput {
+                        title: "recurring".into(),
+                        description: "Contract".into(),
+                        due_date: Some(days_from_now(1)),
+                        status: TaskStatus::Todo,
+                        priority: TaskPriority::default(),
+                        parent_id: None,
+                        recurrence: Some("FREQ=DAILY;COUNT=2".into()),
+                    },
+                    user.id,
+                )
+                .await
+                .unwrap(),
+        );
+        let recurring_info = Info {
+            user_id: user.id,
+            task_id: recurring.id,
+        };
+        let tag = state.tags.create_tag(user.id, "daily").await.unwrap();
+        state
+            .tags
+            .attach_tag(TaskTagInfo {
+                user_id: user.id,
+                task_id: recurring.id,
+                tag_id: tag.id,
+            })
+            .await
+            .unwrap();
+        let item = state
+            .checklists
+            .create_checklist_item(recurring_info.clone(), "step")
+            .await
+            .unwrap();
+        state
+            .checklists
+            .update_checklist_item(
+                ChecklistItemInfo {
+                    user_id: user.id,
+                    task_id: recurring.id,
+                    item_id: item.id,
+                },
+                ChecklistItemPatch {
+                    done: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        // A recurring subtask completed with it recurs too
+        let subtask = written(
+            state
+                .tasks
+                .create_task(
+                    &TaskInput {
+                        title: "recurring subtask".into(),
+                        description: "Contract".into(),
+                        due_date: Some(days_from_now(1)),
+                        status: TaskStatus::Todo,
+                        priority: TaskPriority::default(),
+                        parent_id: Some(recurring.id),
+                        recurrence: Some("FREQ=WEEKLY".into()),
+                    },
+                    user.id,
+                )
+                .await
+                .unwrap(),
+        );
+        let done = written(
+            state
+                .tasks
+                .move_user_task(
+                    recurring_info,
+                    TaskStatus::Done,
+                    Placement::End,
+                    TaskStatus::Todo,
+                )
+                .await
+                .unwrap(),
+        );
+        assert!(done.recurrence.is_none());
+        let board = state.tasks.get_user_board(user.id).await.unwrap();
+        let next = board
+            .iter()
+            .find(|task| task.title == "recurring" && task.id != recurring.id)
+            .expect("the next occurrence is created");
+        assert_eq!(next.status, TaskStatus::Todo);
+        assert_eq!(
+            next.due_date,
+            recurring
+                .due_date
+                .map(|due_date| due_date + Duration::days(1))
+        );
+        assert_eq!(next.recurrence.as_deref(), Some("FREQ=DAILY;COUNT=1"));
+        assert_eq!(next.tags, vec![tag]);
+        assert_eq!(next.checklist.len(), 1);
+        assert_eq!(next.checklist[0].title, "step");
+        assert!(!next.checklist[0].done);
+        let next_subtask = board
+            .iter()
+            .find(|task| task.title == "recurring subtask" && task.id != subtask.id)
+            .expect("the next occurrence of the subtask is created");
+        assert_eq!(next_subtask.status, TaskStatus::Todo);
+        assert_eq!(next_subtask.parent_id, Some(recurring.id));
+        assert_eq!(
+            next_subtask.due_date,
+            subtask
+                .due_date
+                .map(|due_date| due_date + Duration::days(7))
+        );
+        assert_eq!(next_subtask.recurrence.as_deref(), Some("FREQ=WEEKLY"));
+
+        // Concurrent writes never pick the same position in a column
+        let creates: Vec<_> = (0..8)
+            .map(|n| {
+                let state = state.clone();
+                actix_rt::spawn(async move {
+                    contract_task(&state, user.id, &format!("concurrent {}", n)).await
+                })
+            })
+            .collect();
+        for create in creates {
+            create.await.unwrap();
+        }
+        let mut positions: Vec<(String, String)> = state
+            .tasks
+            .get_user_board(user.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|task| (task.status.to_string(), task.position))
+            .collect();
+        let count = positions.len();
+        positions.sort();
+        positions.dedup();
+        assert_eq!(positions.len(), count);
+    }
+
+    async fn check_tag_repository(state: &AppState) {
+        let user = contract_user(state).await;
+        let task = contract_task(state, user.id, "tagged").await;
+        contract_task(state, user.id, "untagged").await;
+
+        let work = state.tags.create_tag(user.id, "work").await.unwrap();
+        let home = state.tags.create_tag(user.id, "home").await.unwrap();
+        match state.tags.create_tag(user.id, "work").await {
+            Err(sqlx::Error::Database(e)) => assert!(e.is_unique_violation()),
+            other => panic!("expected a unique violation, got {:?}", other),
+        }
+        let on_task = |tag_id| TaskTagInfo {
+            user_id: user.id,
+            task_id: task.id,
+            tag_id,
+        };
+        for tag_id in [work.id, work.id, home.id] {
+            state.tags.attach_tag(on_task(tag_id)).await.unwrap();
+        }
+        let missing_task = TaskTagInfo {
+            task_id: 0,
+            ..on_task(work.id)
+        };
+        assert!(matches!(
+            state.tags.attach_tag(missing_task).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+
+        let tagged = TaskQuery {
+            tag: Some("work".into()),
+            ..Default::default()
+        };
+        let tasks = state
+            .tasks
+            .get_user_tasks(user.id, &tagged, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].id, task.id);
+        assert_eq!(tasks[0].tags, vec![home.clone(), work.clone()]);
+
+        let home_info = TagInfo {
+            user_id: user.id,
+            tag_id: home.id,
+        };
+        match state.tags.rename_tag(home_info.clone(), "work").await {
+            Err(sqlx::Error::Database(e)) => assert!(e.is_unique_violation()),
+            other => panic!("expected a unique violation, got {:?}", other),
+        }
+        let errands = state.tags.rename_tag(home_info, "errands").await.unwrap();
+        assert_eq!(errands.name, "errands");
+        let names: Vec<String> = state
+            .tags
+            .get_user_tags(user.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(names, ["errands", "work"]);
+
+        assert_eq!(state.tags.detach_tag(on_task(home.id)).await.unwrap(), 1);
+        assert_eq!(state.tags.detach_tag(on_task(home.id)).await.unwrap(), 0);
+        let work_info = TagInfo {
+            user_id: user.id,
+            tag_id: work.id,
+        };
+        assert_eq!(state.tags.delete_tag(work_info).await.unwrap(), 1);
+        let task = state.tasks.get_user_task(user.id, task.id).await.unwrap();
+        assert!(task.tags.is_empty());
+    }
+
+    async fn check_checklist_repository(state: &AppState) {
+        let user = contract_user(state).await;
+        let task = contract_task(state, user.id, "with steps").await;
+        let info = Info {
+            user_id: user.id,
+            task_id: task.id,
+        };
+
+        let item = state
+            .checklists
+            .create_checklist_item(info, "step")
+            .await
+            .unwrap();
+        assert!(!item.done);
+        let item_info = ChecklistItemInfo {
+            user_id: user.id,
+            task_id: task.id,
+            item_id: item.id,
+        };
+        let checked = state
+            .checklists
+            .update_checklist_item(
+                item_info.clone(),
+                ChecklistItemPatch {
+                    done: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(checked.done);
+        assert_eq!(checked.title, "step");
+        let foreign = ChecklistItemInfo {
+            user_id: 0,
+            ..item_info.clone()
+        };
+        assert!(matches!(
+            state
+                .checklists
+                .update_checklist_item(foreign.clone(), ChecklistItemPatch::default())
+                .await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+        assert_eq!(
+            state
+                .checklists
+                .delete_checklist_item(foreign)
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(matches!(
+            state
+                .checklists
+                .create_checklist_item(
+                    Info {
+                        user_id: user.id,
+                        task_id: 0,
+                    },
+                    "step",
+                )
+                .await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+
+        let task = state.tasks.get_user_task(user.id, task.id).await.unwrap();
+        assert_eq!(task.checklist, vec![checked]);
+        assert_eq!(task.progress, Progress { done: 1, total: 1 });
+
+        assert_eq!(
+            state
+                .checklists
+                .delete_checklist_item(item_info.clone())
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            state
+                .checklists
+                .delete_checklist_item(item_info)
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    async fn check_dependency_repository(state: &AppState) {
+        let user = contract_user(state).await;
+        let mut ids = Vec::new();
+        for title in ["a", "b", "c"] {
+            ids.push(contract_task(state, user.id, title).await.id);
+        }
+
+        let waits = DependencyInfo {
+            user_id: user.id,
+            task_id: ids[0],
+            blocker_id: ids[2],
+        };
+        for _ in 0..2 {
+            assert!(state
+                .dependencies
+                .add_dependency(waits.clone())
+                .await
+                .unwrap());
+        }
+        let chained = DependencyInfo {
+            user_id: user.id,
+            task_id: ids[2],
+            blocker_id: ids[1],
+        };
+        assert!(state
+            .dependencies
+            .add_dependency(chained.clone())
+            .await
+            .unwrap());
+        // ids[1] is waited for by ids[0] through ids[2]
+        let cycle = DependencyInfo {
+            user_id: user.id,
+            task_id: ids[1],
+            blocker_id: ids[0],
+        };
+        assert!(!state.dependencies.add_dependency(cycle).await.unwrap());
+        let missing_blocker = DependencyInfo {
+            blocker_id: 0,
+            ..waits.clone()
+        };
+        assert!(matches!(
+            state.dependencies.add_dependency(missing_blocker).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+
+        // Completing ids[0] is refused while ids[2] is open, and nothing changes
+        let completion = TaskPatch {
+            status: Some(TaskStatus::Done),
+            ..Default::default()
+        };
+        let info = Info {
+            user_id: user.id,
+            task_id: ids[0],
+        };
+        match state
+            .tasks
+            .patch_user_task(info, completion, Some(TaskStatus::Todo))
+            .await
+            .unwrap()
+        {
+            TaskWrite::Blocked(blockers) => assert_eq!(blockers, vec![ids[2]]),
+            other => panic!("expected the completion to be blocked, got {:?}", other),
+        }
+        let task = state.tasks.get_user_task(user.id, ids[0]).await.unwrap();
+        assert_eq!(task.status, TaskStatus::Todo);
+        assert_eq!(task.blocked_by, vec![ids[2]]);
+        assert!(task.blocked);
+        for expected in [1, 0] {
+            assert_eq!(
+                state
+                    .dependencies
+                    .remove_dependency(waits.clone())
+                    .await
+                    .unwrap(),
+                expected
+            );
+        }
+        let task = state.tasks.get_user_task(user.id, ids[0]).await.unwrap();
+        assert!(task.blocked_by.is_empty());
+        assert!(!task.blocked);
+
+        // Deleting a task deletes the dependencies on it
+        let blocker = Info {
+            user_id: user.id,
+            task_id: ids[1],
+        };
+        assert_eq!(state.tasks.delete_user_task(blocker).await.unwrap(), 1);
+        let task = state.tasks.get_user_task(user.id, ids[2]).await.unwrap();
+        assert!(task.blocked_by.is_empty());
+
+        // Of two concurrent links between the same tasks in opposite
+        // directions, only one gets in
+        let links: Vec<_> = [(ids[0], ids[2]), (ids[2], ids[0])]
+            .into_iter()
+            .map(|(task_id, blocker_id)| {
+                let state = state.clone();
+                let info = DependencyInfo {
+                    user_id: user.id,
+                    task_id,
+                    blocker_id,
+                };
+                actix_rt::spawn(async move { state.dependencies.add_dependency(info).await })
+            })
+            .collect();
+        let mut added = Vec::new();
+        for link in links {
+            added.push(link.await.unwrap().unwrap());
+        }
+        added.sort();
+        assert_eq!(added, vec![false, true]);
+    }
+
+    async fn check_refresh_token_repository(state: &AppState) {
+        let user = contract_user(state).await;
+        let suffix = Uuid::new_v4().simple();
+        let family_id = Uuid::new_v4();
+        let expires_at = Utc::now() + Duration::days(1);
+        let first = state
+            .refresh_tokens
+            .create_refresh_token(user.id, family_id, &format!("first{}", suffix), expires_at)
+            .await
+            .unwrap();
+        let second = state
+            .refresh_tokens
+            .rotate_refresh_token(&first, &format!("second{}", suffix), expires_at)
+            .await
+            .unwrap()
+            .expect("first rotation succeeds");
+        assert_eq!(second.family_id, family_id);
+        assert!(state
+            .refresh_tokens
+            .rotate_refresh_token(&first, &format!("third{}", suffix), expires_at)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            state
+                .refresh_tokens
+                .revoke_token_family(user.id, family_id)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    async fn check_repositories(state: &AppState) {
+        check_health_repository(state).await;
+        check_user_repository(state).await;
+        check_task_repository(state).await;
+        check_tag_repository(state).await;
+        check_checklist_repository(state).await;
+        check_dependency_repository(state).await;
+        check_refresh_token_repository(state).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_memory_repository() {
+        check_repositories(&AppState::in_memory(Settings::default())).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_postgres_repository() {
+        dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        run_migrations(&pool).await.unwrap();
+
+        check_repositories(&AppState::postgres(pool, Settings::default())).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[actix_rt::test]
+    async fn test_sqlite_repository() {
+        use crate::db::migrations::run_sqlite_migrations;
+        use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+
+        // A file rather than `:memory:`, where every connection opens its own
+        // database, so that connections see each other's writes and contend
+        // for the write lock as they do in production
+        let path = env::temp_dir().join(format!("contract-{}.db", Uuid::new_v4()));
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .foreign_keys(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await
+            .unwrap();
+        run_sqlite_migrations(&pool).await.unwrap();
+
+        check_repositories(&AppState::sqlite(pool.clone(), Settings::default())).await;
+
+        pool.close().await;
+        std::fs::remove_file(&path).unwrap();
+    }
+}
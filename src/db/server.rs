@@ -1,31 +1,218 @@
-use crate::db::migrations::run_migrations;
+use crate::config::settings::{DatabaseSettings, Settings};
+use crate::db::migrations::{latest_version, run_migrations};
+use crate::model::state::AppState;
 use sqlx::postgres::PgPoolOptions;
-use std::env;
+use sqlx::PgPool;
+use std::future::Future;
+use std::time::Duration;
 
-pub async fn create_pool_and_run_migrations() -> Result<sqlx::PgPool, std::io::Error> {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+// Backoff between connection attempts doubles from the first delay up to
+// the cap
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
 
-    let pool = match PgPoolOptions::new()
-        .max_connections(10)
-        .connect(&database_url)
-        .await
-    {
-        Ok(pool) => {
-            println!("Connected to the database successfully");
-            match run_migrations(&pool).await {
-                Ok(_) => println!("Migrations ran successfully"),
-                Err(e) => {
-                    println!("Failed to run migrations: {}", e);
-                    return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
-                }
+// Errors that can clear up on their own, e.g. while the database container
+// is still starting. Anything else, such as bad credentials, fails at once.
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // cannot_connect_now, sent while Postgres starts up or recovers
+        sqlx::Error::Database(db_error) => db_error.code().as_deref() == Some("57P03"),
+        _ => false,
+    }
+}
+
+// Calls `connect` up to `settings.connect_attempts` times, backing off
+// exponentially between transient failures
+async fn connect_with_retry<T, F, Fut>(
+    settings: &DatabaseSettings,
+    connect: F,
+) -> Result<T, sqlx::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        match connect().await {
+            Ok(pool) => return Ok(pool),
+            Err(e) if attempt < settings.connect_attempts && is_transient(&e) => {
+                log::warn!(
+                    "Database connection attempt {} of {} failed, retrying in {:?}: {}",
+                    attempt,
+                    settings.connect_attempts,
+                    delay,
+                    e
+                );
+                actix_rt::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+                attempt += 1;
             }
-            pool
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+pub async fn create_pool(settings: &DatabaseSettings) -> Result<PgPool, std::io::Error> {
+    let connect = || {
+        PgPoolOptions::new()
+            .max_connections(settings.max_connections)
+            .acquire_timeout(Duration::from_secs(settings.acquire_timeout_secs))
+            .connect(&settings.url)
+    };
+
+    match connect_with_retry(settings, connect).await {
+        Ok(pool) => {
+            log::info!("Connected to the database successfully");
+            Ok(pool)
         }
         Err(e) => {
-            println!("Failed to connect to the database: {}", e);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+            log::error!("Failed to connect to the database: {}", e);
+            Err(std::io::Error::other(e))
         }
-    };
+    }
+}
+
+// Refuses to hand out a pool when the schema is ahead of this build or a
+// migration has drifted, so the server never runs against a schema it does
+// not understand
+pub async fn create_pool_and_run_migrations(
+    settings: &DatabaseSettings,
+) -> Result<PgPool, std::io::Error> {
+    let pool = create_pool(settings).await?;
+
+    match run_migrations(&pool).await {
+        Ok(applied) => log::info!(
+            "Migrations ran successfully, applied {} and now at version {}",
+            applied.len(),
+            latest_version()
+        ),
+        Err(e) => {
+            log::error!("Failed to run migrations: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    }
 
     Ok(pool)
 }
+
+// Picks the storage backend from the scheme of the database URL
+pub async fn create_state(settings: Settings) -> Result<AppState, std::io::Error> {
+    if settings.database.url.starts_with("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return sqlite::create_pool_and_run_migrations(&settings.database)
+            .await
+            .map(|pool| AppState::sqlite(pool, settings));
+
+        #[cfg(not(feature = "sqlite"))]
+        return Err(std::io::Error::other(
+            "DATABASE_URL points at SQLite but this build lacks the `sqlite` feature",
+        ));
+    }
+
+    create_pool_and_run_migrations(&settings.database)
+        .await
+        .map(|pool| AppState::postgres(pool, settings))
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::connect_with_retry;
+    use crate::config::settings::DatabaseSettings;
+    use crate::db::migrations::{run_sqlite_migrations, SQLITE_MIGRATIONS};
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+    use sqlx::SqlitePool;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    pub async fn create_pool_and_run_migrations(
+        settings: &DatabaseSettings,
+    ) -> Result<SqlitePool, std::io::Error> {
+        let options = SqliteConnectOptions::from_str(&settings.url)
+            .map_err(std::io::Error::other)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .foreign_keys(true);
+
+        let connect = || {
+            SqlitePoolOptions::new()
+                .max_connections(settings.max_connections)
+                .acquire_timeout(Duration::from_secs(settings.acquire_timeout_secs))
+                .connect_with(options.clone())
+        };
+
+        let pool = match connect_with_retry(settings, connect).await {
+            Ok(pool) => {
+                log::info!("Connected to the database successfully");
+                pool
+            }
+            Err(e) => {
+                log::error!("Failed to connect to the database: {}", e);
+                return Err(std::io::Error::other(e));
+            }
+        };
+
+        match run_sqlite_migrations(&pool).await {
+            Ok(applied) => log::info!(
+                "Migrations ran successfully, applied {} and now at version {}",
+                applied.len(),
+                SQLITE_MIGRATIONS.last().map_or(0, |m| m.version)
+            ),
+            Err(e) => {
+                log::error!("Failed to run migrations: {}", e);
+                return Err(std::io::Error::other(e));
+            }
+        }
+
+        Ok(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn settings(connect_attempts: u32) -> DatabaseSettings {
+        DatabaseSettings {
+            connect_attempts,
+            ..Default::default()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_connect_retries_transient_errors_only() {
+        let calls = Cell::new(0);
+        let result: Result<(), _> = connect_with_retry(&settings(3), || {
+            calls.set(calls.get() + 1);
+            async { Err(sqlx::Error::PoolTimedOut) }
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert_eq!(calls.get(), 3);
+
+        let calls = Cell::new(0);
+        let result = connect_with_retry(&settings(3), || {
+            calls.set(calls.get() + 1);
+            async {
+                match calls.get() {
+                    1 => Err(sqlx::Error::PoolTimedOut),
+                    _ => Ok(calls.get()),
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+
+        let calls = Cell::new(0);
+        let result: Result<(), _> = connect_with_retry(&settings(3), || {
+            calls.set(calls.get() + 1);
+            async { Err(sqlx::Error::RowNotFound) }
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(calls.get(), 1);
+    }
+}
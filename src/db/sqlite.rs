@@ -0,0 +1,1246 @@
+use crate::db::repository::{
+    ChecklistRepository, DependencyRepository, HealthRepository, RefreshTokenRepository,
+    TagRepository, TaskRepository, UserRepository,
+};
+use crate::metrics::metrics::PoolStatus;
+use crate::model::models::{
+    ChecklistItem, ChecklistItemInfo, ChecklistItemPatch, DependencyInfo, Info, InvalidParent,
+    Placement, RefreshToken, Role, SortOrder, Tag, TagInfo, Task, TaskCursor, TaskInput, TaskPatch,
+    TaskQuery, TaskSort, TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite, User, UserInput,
+    UserQuery,
+};
+use crate::model::position::{position_after, position_between};
+use async_trait::async_trait;
+use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{Error, QueryBuilder, Sqlite, SqliteConnection, SqlitePool, Transaction};
+use uuid::Uuid;
+
+// The SQLite backend. Queries are checked at runtime since the compile-time
+// macros are tied to the Postgres schema; otherwise they mirror `db::db`.
+// Timestamps are stored as text in a format that sorts chronologically.
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        SqliteRepository { pool }
+    }
+
+    // SQLite starts transactions as readers, and a reader that goes on to
+    // write fails with SQLITE_BUSY when another connection wrote in between.
+    // Writing first takes the write lock up front, waiting for it like any
+    // other write, so that the reads that follow see the latest state and no
+    // one else writes until the commit.
+    async fn begin_write(&self) -> Result<Transaction<'_, Sqlite>, Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE tasks SET position = position WHERE 0")
+            .execute(&mut *tx)
+            .await?;
+        Ok(tx)
+    }
+}
+
+// Fills in the tags, checklist, progress and blockers of every task, with one
+// query for each
+async fn load_task_details(conn: &mut SqliteConnection, tasks: &mut [Task]) -> Result<(), Error> {
+    if tasks.is_empty() {
+        return Ok(());
+    }
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT task_tags.task_id, tags.id, tags.name \
+         FROM task_tags JOIN tags ON tags.id = task_tags.tag_id \
+         WHERE task_tags.task_id IN (",
+    );
+    push_task_ids(&mut query, tasks);
+    query.push(") ORDER BY tags.name, tags.id");
+
+    let rows: Vec<(i32, i32, String)> = query.build_query_as().fetch_all(&mut *conn).await?;
+    for (task_id, id, name) in rows {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == task_id) {
+            task.tags.push(Tag { id, name });
+        }
+    }
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT task_id, id, title, done FROM checklist_items WHERE task_id IN (",
+    );
+    push_task_ids(&mut query, tasks);
+    query.push(") ORDER BY id");
+
+    let items: Vec<(i32, i32, String, bool)> = query.build_query_as().fetch_all(&mut *conn).await?;
+    for (task_id, id, title, done) in items {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == task_id) {
+            task.progress.total += 1;
+            task.progress.done += i64::from(done);
+            task.checklist.push(ChecklistItem { id, title, done });
+        }
+    }
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT parent_id, COUNT(*), SUM(status = 'done') FROM tasks \
+         WHERE status <> 'cancelled' AND parent_id IN (",
+    );
+    push_task_ids(&mut query, tasks);
+    query.push(") GROUP BY parent_id");
+
+    let subtasks: Vec<(i32, i64, i64)> = query.build_query_as().fetch_all(&mut *conn).await?;
+    for (parent_id, total, done) in subtasks {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == parent_id) {
+            task.progress.total += total;
+            task.progress.done += done;
+        }
+    }
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT task_dependencies.task_id, task_dependencies.blocker_id, \
+         tasks.status NOT IN ('done', 'cancelled') \
+         FROM task_dependencies JOIN tasks ON tasks.id = task_dependencies.blocker_id \
+         WHERE task_dependencies.task_id IN (",
+    );
+    push_task_ids(&mut query, tasks);
+    query.push(") ORDER BY task_dependencies.blocker_id");
+
+    let blockers: Vec<(i32, i32, bool)> = query.build_query_as().fetch_all(&mut *conn).await?;
+    for (task_id, blocker_id, open) in blockers {
+        if let Some(task) = tasks.iter_mut().find(|task| task.id == task_id) {
+            task.blocked_by.push(blocker_id);
+            task.blocked |= open;
+        }
+    }
+
+    Ok(())
+}
+
+fn push_task_ids(query: &mut QueryBuilder<Sqlite>, tasks: &[Task]) {
+    let mut task_ids = query.separated(", ");
+    for task in tasks {
+        task_ids.push_bind(task.id);
+    }
+}
+
+// Like `neighbor_position_db`
+async fn neighbor_position(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    status: TaskStatus,
+    from: Option<&str>,
+    order: SortOrder,
+    exclude: i32,
+) -> Result<Option<String>, Error> {
+    let (comparison, direction) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    let mut query: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT position FROM tasks WHERE user_id = ");
+    query
+        .push_bind(user_id)
+        .push(" AND status = ")
+        .push_bind(status)
+        .push(" AND id <> ")
+        .push_bind(exclude);
+    if let Some(from) = from {
+        query
+            .push(format!(" AND position {} ", comparison))
+            .push_bind(from.to_owned());
+    }
+    query.push(format!(" ORDER BY position {} LIMIT 1", direction));
+
+    query.build_query_scalar().fetch_optional(conn).await
+}
+
+// Like `end_of_column_db`
+async fn end_of_column(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    status: TaskStatus,
+    exclude: i32,
+) -> Result<String, Error> {
+    let last = neighbor_position(conn, user_id, status, None, SortOrder::Desc, exclude).await?;
+    Ok(position_after(last.as_deref()))
+}
+
+// Like `insert_task_db`
+async fn insert_task(
+    conn: &mut SqliteConnection,
+    new_task: &TaskInput,
+    user_id: i32,
+) -> Result<Task, Error> {
+    // New tasks go to the end of their column
+    let position = end_of_column(conn, user_id, new_task.status, 0).await?;
+
+    sqlx::query_as(&format!(
+        "INSERT INTO tasks \
+         (title, description, due_date, status, completed_at, user_id, priority, position, \
+         parent_id, recurrence) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING {}",
+        TASK_COLUMNS
+    ))
+    .bind(&new_task.title)
+    .bind(&new_task.description)
+    .bind(new_task.due_date)
+    .bind(new_task.status)
+    .bind((new_task.status == TaskStatus::Done).then(now))
+    .bind(user_id)
+    .bind(new_task.priority)
+    .bind(position)
+    .bind(new_task.parent_id)
+    .bind(&new_task.recurrence)
+    .fetch_one(conn)
+    .await
+}
+
+// Like `schedule_next_occurrence_db`
+async fn schedule_next_occurrence(
+    conn: &mut SqliteConnection,
+    task: &mut Task,
+) -> Result<(), Error> {
+    if task.recurrence.is_none() || task.due_date.is_none() {
+        return Ok(());
+    }
+
+    if let Some(next) = task.next_occurrence().map_err(Error::protocol)? {
+        let copy = insert_task(conn, &next, task.user_id).await?;
+        sqlx::query(
+            "INSERT INTO task_tags (task_id, tag_id) \
+             SELECT $1, tag_id FROM task_tags WHERE task_id = $2",
+        )
+        .bind(copy.id)
+        .bind(task.id)
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query(
+            "INSERT INTO checklist_items (task_id, title) \
+             SELECT $1, title FROM checklist_items WHERE task_id = $2 ORDER BY id",
+        )
+        .bind(copy.id)
+        .bind(task.id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query("UPDATE tasks SET recurrence = NULL WHERE id = $1")
+        .bind(task.id)
+        .execute(conn)
+        .await?;
+    task.recurrence = None;
+
+    Ok(())
+}
+
+// Like `task_ancestors_db`
+async fn task_ancestors(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    task_id: i32,
+) -> Result<Vec<i32>, Error> {
+    sqlx::query_scalar(
+        "WITH RECURSIVE ancestors (id, depth) AS ( \
+             SELECT parent_id, 1 FROM tasks \
+             WHERE id = $1 AND user_id = $2 AND parent_id IS NOT NULL \
+             UNION \
+             SELECT tasks.parent_id, ancestors.depth + 1 \
+             FROM tasks JOIN ancestors ON tasks.id = ancestors.id \
+             WHERE tasks.parent_id IS NOT NULL AND ancestors.depth < 100) \
+         SELECT id FROM ancestors GROUP BY id ORDER BY MIN(depth)",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_all(conn)
+    .await
+}
+
+// Like `subtask_depth_db`
+async fn subtask_depth(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    task_id: i32,
+) -> Result<usize, Error> {
+    let depth: Option<i32> = sqlx::query_scalar(
+        "WITH RECURSIVE descendants (id, depth) AS ( \
+             SELECT id, 0 FROM tasks WHERE id = $1 AND user_id = $2 \
+             UNION \
+             SELECT tasks.id, descendants.depth + 1 \
+             FROM tasks JOIN descendants ON tasks.parent_id = descendants.id \
+             WHERE descendants.depth < 100) \
+         SELECT MAX(depth) FROM descendants",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(depth.unwrap_or(0) as usize)
+}
+
+// Like `check_parent_db`
+async fn check_parent(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    task_id: Option<i32>,
+    parent_id: i32,
+) -> Result<Option<InvalidParent>, Error> {
+    let exists: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM tasks WHERE id = $1 AND user_id = $2)")
+            .bind(parent_id)
+            .bind(user_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    if !exists {
+        return Ok(Some(InvalidParent::Missing));
+    }
+
+    let ancestors = task_ancestors(conn, user_id, parent_id).await?;
+    let below = match task_id {
+        Some(task_id) => subtask_depth(conn, user_id, task_id).await?,
+        None => 0,
+    };
+    Ok(InvalidParent::check(task_id, parent_id, &ancestors, below))
+}
+
+// Like `open_blockers_db`
+async fn open_blockers(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    task_id: i32,
+) -> Result<Vec<i32>, Error> {
+    sqlx::query_scalar(
+        "WITH RECURSIVE family (id, status) AS ( \
+             SELECT id, status FROM tasks WHERE id = $1 AND user_id = $2 \
+             UNION \
+             SELECT tasks.id, tasks.status FROM tasks \
+             JOIN family ON tasks.parent_id = family.id), \
+         completing (id) AS ( \
+             SELECT id FROM family WHERE id = $1 OR status IN ('todo', 'in_progress')) \
+         SELECT DISTINCT blockers.id \
+         FROM task_dependencies \
+         JOIN tasks AS blockers ON blockers.id = task_dependencies.blocker_id \
+         WHERE task_dependencies.task_id IN (SELECT id FROM completing) \
+           AND blockers.status NOT IN ('done', 'cancelled') \
+           AND blockers.id NOT IN (SELECT id FROM completing) \
+         ORDER BY blockers.id",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_all(conn)
+    .await
+}
+
+// Like `complete_subtasks_db`
+async fn complete_subtasks(conn: &mut SqliteConnection, info: &Info) -> Result<(), Error> {
+    let ids: Vec<i32> = sqlx::query_scalar(
+        "WITH RECURSIVE descendants (id) AS ( \
+             SELECT id FROM tasks WHERE parent_id = $1 AND user_id = $2 \
+             UNION \
+             SELECT tasks.id FROM tasks JOIN descendants ON tasks.parent_id = descendants.id) \
+         SELECT id FROM tasks \
+         WHERE id IN (SELECT id FROM descendants) AND status IN ('todo', 'in_progress') \
+         ORDER BY position, id",
+    )
+    .bind(info.task_id)
+    .bind(info.user_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut position = end_of_column(conn, info.user_id, TaskStatus::Done, 0).await?;
+    for id in &ids {
+        let mut task: Task = sqlx::query_as(&format!(
+            "UPDATE tasks SET status = 'done', position = $1, \
+             completed_at = COALESCE(completed_at, $2) WHERE id = $3 RETURNING {}",
+            TASK_COLUMNS
+        ))
+        .bind(&position)
+        .bind(now())
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+        schedule_next_occurrence(conn, &mut task).await?;
+        position = position_after(Some(&position));
+    }
+
+    Ok(())
+}
+
+// Like `placement_position_db`
+async fn placement_position(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    task_id: i32,
+    status: TaskStatus,
+    placement: Placement,
+) -> Result<String, Error> {
+    let anchor_id = match placement {
+        Placement::Before(anchor_id) | Placement::After(anchor_id) => anchor_id,
+        Placement::End => return end_of_column(conn, user_id, status, task_id).await,
+    };
+    let anchor: String = sqlx::query_scalar(
+        "SELECT position FROM tasks WHERE user_id = $1 AND id = $2 AND status = $3",
+    )
+    .bind(user_id)
+    .bind(anchor_id)
+    .bind(status)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (lower, upper) = match placement {
+        Placement::Before(_) => (
+            neighbor_position(
+                conn,
+                user_id,
+                status,
+                Some(&anchor),
+                SortOrder::Desc,
+                task_id,
+            )
+            .await?,
+            Some(anchor),
+        ),
+        _ => {
+            let upper = neighbor_position(
+                conn,
+                user_id,
+                status,
+                Some(&anchor),
+                SortOrder::Asc,
+                task_id,
+            )
+            .await?;
+            (Some(anchor), upper)
+        }
+    };
+    position_between(lower.as_deref(), upper.as_deref())
+        .ok_or_else(|| Error::protocol("Task positions are out of order"))
+}
+
+const TASK_COLUMNS: &str = "id, title, COALESCE(description, '') AS description, due_date, \
+     status, completed_at, user_id, priority, position, parent_id, recurrence";
+
+// `fetch_one` hands back the first row a write returns before SQLite has
+// committed it, so a read on another connection could still miss the write.
+// Writes outside a transaction read every row instead, which waits for the
+// commit.
+fn returned_row<T>(rows: Vec<T>) -> Result<T, Error> {
+    rows.into_iter().next().ok_or(Error::RowNotFound)
+}
+
+fn hash_password(password: &str) -> Result<String, Error> {
+    hash(password, DEFAULT_COST).map_err(|e| Error::protocol(format!("Bcrypt error: {}", e)))
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn escape_like(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn push_user_filter(query: &mut QueryBuilder<Sqlite>, filter: &UserQuery) {
+    match filter.q.as_deref().filter(|q| !q.is_empty()) {
+        Some(q) => {
+            let prefix = format!("{}%", escape_like(&q.to_lowercase()));
+            query
+                .push("(lower(username) LIKE ")
+                .push_bind(prefix.clone())
+                .push(" ESCAPE '\\' OR lower(email) LIKE ")
+                .push_bind(prefix)
+                .push(" ESCAPE '\\')");
+        }
+        None => {
+            query.push("1 = 1");
+        }
+    }
+}
+
+// Tasks without a due date sort last, as 'infinity' sorts after any date
+fn task_sort_key(sort: TaskSort) -> &'static str {
+    match sort {
+        TaskSort::Id => "id",
+        TaskSort::DueDate => "COALESCE(due_date, 'infinity')",
+        TaskSort::Title => "title",
+        // Priorities are stored as text, so rank them as the enum orders them.
+        // tasks_user_id_priority_rank_idx indexes this exact expression.
+        TaskSort::Priority => {
+            "CASE priority WHEN 'low' THEN 0 WHEN 'medium' THEN 1 WHEN 'high' THEN 2 ELSE 3 END"
+        }
+    }
+}
+
+// Value of `task_sort_key` for the task a cursor points after
+fn push_cursor_key(query: &mut QueryBuilder<Sqlite>, sort: TaskSort, after: &TaskCursor) {
+    match sort {
+        TaskSort::Id => {
+            query.push_bind(after.id);
+        }
+        TaskSort::DueDate => {
+            query
+                .push("COALESCE(")
+                .push_bind(after.due_date)
+                .push(", 'infinity')");
+        }
+        TaskSort::Title => {
+            query.push_bind(after.title.clone().unwrap_or_default());
+        }
+        TaskSort::Priority => {
+            query.push_bind(after.priority.unwrap_or_default() as i32);
+        }
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteRepository {
+    async fn create_user(&self, new_user: &UserInput) -> Result<User, Error> {
+        let password_hash = hash_password(&new_user.password)?;
+
+        sqlx::query_as(
+            "INSERT INTO users (username, password_hash, email) VALUES ($1, $2, $3) \
+             RETURNING id, username, password_hash, email, role",
+        )
+        .bind(&new_user.username)
+        .bind(&password_hash)
+        .bind(&new_user.email)
+        .fetch_all(&self.pool)
+        .await
+        .and_then(returned_row)
+    }
+
+    async fn get_users(
+        &self,
+        filter: &UserQuery,
+        after_id: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<User>, Error> {
+        let mut query: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT id, username, password_hash, email, role FROM users WHERE ");
+        push_user_filter(&mut query, filter);
+        if let Some(after_id) = after_id {
+            query.push(" AND id > ").push_bind(after_id);
+        }
+        query.push(" ORDER BY id LIMIT ").push_bind(limit);
+
+        query.build_query_as::<User>().fetch_all(&self.pool).await
+    }
+
+    async fn count_users(&self, filter: &UserQuery) -> Result<i64, Error> {
+        let mut query: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT COUNT(*) FROM users WHERE ");
+        push_user_filter(&mut query, filter);
+
+        let (total,): (i64,) = query.build_query_as().fetch_one(&self.pool).await?;
+
+        Ok(total)
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<User, Error> {
+        sqlx::query_as(
+            "SELECT id, username, password_hash, email, role FROM users WHERE username = $1",
+        )
+        .bind(username)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn get_user_role(&self, user_id: i32) -> Result<Role, Error> {
+        let (role,): (Role,) = sqlx::query_as("SELECT role FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(role)
+    }
+
+    async fn set_user_role(&self, user_id: i32, role: Role) -> Result<u64, Error> {
+        let result = sqlx::query("UPDATE users SET role = $1 WHERE id = $2")
+            .bind(role)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn reset_user_password(&self, user_id: i32, password: &str) -> Result<u64, Error> {
+        let password_hash = hash_password(password)?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(&password_hash)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = $1 WHERE user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(Utc::now())
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn authenticate_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<User>, Error> {
+        let user = match self.get_user_by_username(username).await {
+            Ok(user) => user,
+            Err(Error::RowNotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        if verify(password, &user.password_hash)
+            .map_err(|_| Error::protocol("Password verification failed"))?
+        {
+            Ok(Some(User {
+                password_hash: String::new(),
+                ..user
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[async_trait]
+impl TaskRepository for SqliteRepository {
+    async fn create_task(&self, new_task: &TaskInput, user_id: i32) -> Result<TaskWrite, Error> {
+        let mut tx = self.begin_write().await?;
+        if let Some(parent_id) = new_task.parent_id {
+            if let Some(invalid) = check_parent(&mut tx, user_id, None, parent_id).await? {
+                return Ok(TaskWrite::InvalidParent(invalid));
+            }
+        }
+        let task = insert_task(&mut tx, new_task, user_id).await?;
+        tx.commit().await?;
+
+        Ok(TaskWrite::Written(task))
+    }
+
+    async fn get_user_tasks(
+        &self,
+        user_id: i32,
+        filter: &TaskQuery,
+        after: Option<&TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<Task>, Error> {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+            "SELECT {} FROM tasks WHERE user_id = ",
+            TASK_COLUMNS
+        ));
+        query.push_bind(user_id);
+
+        if let Some(status) = filter.status {
+            query.push(" AND status = ").push_bind(status);
+        }
+        if let Some(priority) = filter.priority {
+            query.push(" AND priority = ").push_bind(priority);
+        }
+        if let Some(parent_id) = filter.parent_id {
+            query.push(" AND parent_id = ").push_bind(parent_id);
+        }
+        if let Some(due_before) = filter.due_before {
+            query.push(" AND due_date < ").push_bind(due_before);
+        }
+        if let Some(due_after) = filter.due_after {
+            query.push(" AND due_date > ").push_bind(due_after);
+        }
+        if let Some(overdue) = filter.overdue {
+            query
+                .push(if overdue { " AND " } else { " AND NOT " })
+                .push("COALESCE(due_date < ")
+                .push_bind(now())
+                .push(" AND status NOT IN ('done', 'cancelled'), 0)");
+        }
+        if let Some(tag) = &filter.tag {
+            query
+                .push(
+                    " AND EXISTS (SELECT 1 FROM task_tags JOIN tags ON tags.id = task_tags.tag_id \
+                     WHERE task_tags.task_id = tasks.id AND tags.name = ",
+                )
+                .push_bind(tag.clone())
+                .push(")");
+        }
+        if let Some(text) = filter.q.as_deref().filter(|text| !text.is_empty()) {
+            let pattern = format!("%{}%", escape_like(text));
+            query
+                .push(" AND (title LIKE ")
+                .push_bind(pattern.clone())
+                .push(" ESCAPE '\\' OR description LIKE ")
+                .push_bind(pattern)
+                .push(" ESCAPE '\\')");
+        }
+
+        let key = task_sort_key(filter.sort);
+        let (comparison, direction) = match filter.order {
+            SortOrder::Asc => (">", "ASC"),
+            SortOrder::Desc => ("<", "DESC"),
+        };
+
+        if let Some(after) = after {
+            query.push(format!(" AND ({}, id) {} (", key, comparison));
+            push_cursor_key(&mut query, filter.sort, after);
+            query.push(", ").push_bind(after.id).push(")");
+        }
+
+        query.push(format!(
+            " ORDER BY {} {}, id {} LIMIT ",
+            key, direction, direction
+        ));
+        query.push_bind(limit);
+
+        let mut tasks = query.build_query_as::<Task>().fetch_all(&self.pool).await?;
+        load_task_details(&mut *self.pool.acquire().await?, &mut tasks).await?;
+
+        Ok(tasks)
+    }
+
+    async fn get_user_task(&self, user_id: i32, task_id: i32) -> Result<Task, Error> {
+        let mut task: Task = sqlx::query_as(&format!(
+            "SELECT {} FROM tasks WHERE user_id = $1 AND id = $2",
+            TASK_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(task_id)
+        .fetch_one(&self.pool)
+        .await?;
+        load_task_details(
+            &mut *self.pool.acquire().await?,
+            std::slice::from_mut(&mut task),
+        )
+        .await?;
+
+        Ok(task)
+    }
+
+    async fn update_user_task(
+        &self,
+        info: Info,
+        task_update: TaskUpdate,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error> {
+        let mut tx = self.begin_write().await?;
+
+        if task_update.status == TaskStatus::Done && current_status != TaskStatus::Done {
+            let blockers = open_blockers(&mut tx, info.user_id, info.task_id).await?;
+            if !blockers.is_empty() {
+                return Ok(TaskWrite::Blocked(blockers));
+            }
+        }
+
+        // A task changing status goes to the end of its new column
+        let position = match task_update.status != current_status {
+            true => Some(end_of_column(&mut tx, info.user_id, task_update.status, 0).await?),
+            false => None,
+        };
+
+        let mut task: Task = sqlx::query_as(&format!(
+            "UPDATE tasks SET title = $1, description = $2, due_date = $3, status = $4, \
+             completed_at = CASE WHEN $4 = 'done' THEN COALESCE(completed_at, $5) END, \
+             priority = $9, recurrence = $10, position = COALESCE($11, position) \
+             WHERE id = $6 AND user_id = $7 AND status = $8 RETURNING {}",
+            TASK_COLUMNS
+        ))
+        .bind(task_update.title)
+        .bind(task_update.description)
+        .bind(task_update.due_date)
+        .bind(task_update.status)
+        .bind(now())
+        .bind(info.task_id)
+        .bind(info.user_id)
+        .bind(current_status)
+        .bind(task_update.priority)
+        .bind(task_update.recurrence)
+        .bind(position)
+        .fetch_one(&mut *tx)
+        .await?;
+        if task.status == TaskStatus::Done && current_status != TaskStatus::Done {
+            complete_subtasks(&mut tx, &info).await?;
+            schedule_next_occurrence(&mut tx, &mut task).await?;
+        }
+        load_task_details(&mut tx, std::slice::from_mut(&mut task)).await?;
+
+        tx.commit().await?;
+
+        Ok(TaskWrite::Written(task))
+    }
+
+    async fn patch_user_task(
+        &self,
+        info: Info,
+        patch: TaskPatch,
+        current_status: Option<TaskStatus>,
+    ) -> Result<TaskWrite, Error> {
+        if patch.is_empty() {
+            return self
+                .get_user_task(info.user_id, info.task_id)
+                .await
+                .map(TaskWrite::Written);
+        }
+
+        let mut tx = self.begin_write().await?;
+        if let Some(Some(parent_id)) = patch.parent_id {
+            let invalid =
+                check_parent(&mut tx, info.user_id, Some(info.task_id), parent_id).await?;
+            if let Some(invalid) = invalid {
+                return Ok(TaskWrite::InvalidParent(invalid));
+            }
+        }
+        if patch.status == Some(TaskStatus::Done) && current_status != Some(TaskStatus::Done) {
+            let blockers = open_blockers(&mut tx, info.user_id, info.task_id).await?;
+            if !blockers.is_empty() {
+                return Ok(TaskWrite::Blocked(blockers));
+            }
+        }
+
+        let position = match patch
+            .status
+            .filter(|status| Some(*status) != current_status)
+        {
+            Some(status) => Some(end_of_column(&mut tx, info.user_id, status, 0).await?),
+            None => None,
+        };
+
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("UPDATE tasks SET ");
+        let mut columns = query.separated(", ");
+        if let Some(title) = patch.title {
+            columns.push("title = ").push_bind_unseparated(title);
+        }
+        if let Some(description) = patch.description {
+            columns
+                .push("description = ")
+                .push_bind_unseparated(description);
+        }
+        if let Some(due_date) = patch.due_date {
+            columns.push("due_date = ").push_bind_unseparated(due_date);
+        }
+        if let Some(status) = patch.status {
+            columns.push("status = ").push_bind_unseparated(status);
+            columns
+                .push("completed_at = CASE WHEN ")
+                .push_bind_unseparated(status)
+                .push_unseparated(" = 'done' THEN COALESCE(completed_at, ")
+                .push_bind_unseparated(now())
+                .push_unseparated(") END");
+        }
+        if let Some(position) = position {
+            columns.push("position = ").push_bind_unseparated(position);
+        }
+        if let Some(priority) = patch.priority {
+            columns.push("priority = ").push_bind_unseparated(priority);
+        }
+        if let Some(parent_id) = patch.parent_id {
+            columns
+                .push("parent_id = ")
+                .push_bind_unseparated(parent_id);
+        }
+        if let Some(recurrence) = patch.recurrence {
+            columns
+                .push("recurrence = ")
+                .push_bind_unseparated(recurrence);
+        }
+
+        query
+            .push(" WHERE id = ")
+            .push_bind(info.task_id)
+            .push(" AND user_id = ")
+            .push_bind(info.user_id);
+        if let Some(current_status) = current_status {
+            query.push(" AND status = ").push_bind(current_status);
+        }
+        query.push(format!(" RETURNING {}", TASK_COLUMNS));
+
+        let mut task = query.build_query_as::<Task>().fetch_one(&mut *tx).await?;
+        if patch.status == Some(TaskStatus::Done) && current_status != Some(TaskStatus::Done) {
+            complete_subtasks(&mut tx, &info).await?;
+            schedule_next_occurrence(&mut tx, &mut task).await?;
+        }
+        load_task_details(&mut tx, std::slice::from_mut(&mut task)).await?;
+
+        tx.commit().await?;
+
+        Ok(TaskWrite::Written(task))
+    }
+
+    // `parent_id` has no foreign key in SQLite (see its migration 0009), so
+    // the subtasks are deleted here first
+    async fn delete_user_task(&self, info: Info) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM tasks WHERE user_id = $1 AND id = $2")
+            .bind(info.user_id)
+            .bind(info.task_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn move_user_task(
+        &self,
+        info: Info,
+        status: TaskStatus,
+        placement: Placement,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, Error> {
+        let mut tx = self.begin_write().await?;
+
+        if status == TaskStatus::Done && current_status != TaskStatus::Done {
+            let blockers = open_blockers(&mut tx, info.user_id, info.task_id).await?;
+            if !blockers.is_empty() {
+                return Ok(TaskWrite::Blocked(blockers));
+            }
+        }
+
+        let position =
+            placement_position(&mut tx, info.user_id, info.task_id, status, placement).await?;
+        let mut task: Task = sqlx::query_as(&format!(
+            "UPDATE tasks SET status = $1, position = $2, \
+             completed_at = CASE WHEN $1 = 'done' THEN COALESCE(completed_at, $3) END \
+             WHERE id = $4 AND user_id = $5 AND status = $6 RETURNING {}",
+            TASK_COLUMNS
+        ))
+        .bind(status)
+        .bind(position)
+        .bind(now())
+        .bind(info.task_id)
+        .bind(info.user_id)
+        .bind(current_status)
+        .fetch_one(&mut *tx)
+        .await?;
+        if status == TaskStatus::Done && current_status != TaskStatus::Done {
+            complete_subtasks(&mut tx, &info).await?;
+            schedule_next_occurrence(&mut tx, &mut task).await?;
+        }
+        load_task_details(&mut tx, std::slice::from_mut(&mut task)).await?;
+
+        tx.commit().await?;
+
+        Ok(TaskWrite::Written(task))
+    }
+
+    async fn get_user_board(&self, user_id: i32) -> Result<Vec<Task>, Error> {
+        let mut tasks: Vec<Task> = sqlx::query_as(&format!(
+            "SELECT {} FROM tasks WHERE user_id = $1 ORDER BY position, id",
+            TASK_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        load_task_details(&mut *self.pool.acquire().await?, &mut tasks).await?;
+
+        Ok(tasks)
+    }
+}
+
+#[async_trait]
+impl TagRepository for SqliteRepository {
+    async fn create_tag(&self, user_id: i32, name: &str) -> Result<Tag, Error> {
+        sqlx::query_as("INSERT INTO tags (user_id, name) VALUES ($1, $2) RETURNING id, name")
+            .bind(user_id)
+            .bind(name)
+            .fetch_all(&self.pool)
+            .await
+            .and_then(returned_row)
+    }
+
+    async fn get_user_tags(&self, user_id: i32) -> Result<Vec<Tag>, Error> {
+        sqlx::query_as("SELECT id, name FROM tags WHERE user_id = $1 ORDER BY name, id")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn rename_tag(&self, info: TagInfo, name: &str) -> Result<Tag, Error> {
+        sqlx::query_as(
+            "UPDATE tags SET name = $1 WHERE id = $2 AND user_id = $3 RETURNING id, name",
+        )
+        .bind(name)
+        .bind(info.tag_id)
+        .bind(info.user_id)
+        .fetch_all(&self.pool)
+        .await
+        .and_then(returned_row)
+    }
+
+    async fn delete_tag(&self, info: TagInfo) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM tags WHERE id = $1 AND user_id = $2")
+            .bind(info.tag_id)
+            .bind(info.user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn attach_tag(&self, info: TaskTagInfo) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO task_tags (task_id, tag_id) \
+             SELECT tasks.id, tags.id FROM tasks, tags \
+             WHERE tasks.id = $1 AND tasks.user_id = $3 AND tags.id = $2 AND tags.user_id = $3 \
+             ON CONFLICT (task_id, tag_id) DO UPDATE SET task_id = excluded.task_id \
+             RETURNING task_id",
+        )
+        .bind(info.task_id)
+        .bind(info.tag_id)
+        .bind(info.user_id)
+        .fetch_all(&self.pool)
+        .await
+        .and_then(returned_row)?;
+
+        Ok(())
+    }
+
+    async fn detach_tag(&self, info: TaskTagInfo) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "DELETE FROM task_tags WHERE task_id = $1 AND tag_id = $2 \
+             AND tag_id IN (SELECT id FROM tags WHERE user_id = $3)",
+        )
+        .bind(info.task_id)
+        .bind(info.tag_id)
+        .bind(info.user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl ChecklistRepository for SqliteRepository {
+    async fn create_checklist_item(&self, info: Info, title: &str) -> Result<ChecklistItem, Error> {
+        sqlx::query_as(
+            "INSERT INTO checklist_items (task_id, title) \
+             SELECT id, $1 FROM tasks WHERE id = $2 AND user_id = $3 \
+             RETURNING id, title, done",
+        )
+        .bind(title)
+        .bind(info.task_id)
+        .bind(info.user_id)
+        .fetch_all(&self.pool)
+        .await
+        .and_then(returned_row)
+    }
+
+    async fn update_checklist_item(
+        &self,
+        info: ChecklistItemInfo,
+        patch: ChecklistItemPatch,
+    ) -> Result<ChecklistItem, Error> {
+        sqlx::query_as(
+            "UPDATE checklist_items SET title = COALESCE($1, title), done = COALESCE($2, done) \
+             WHERE id = $3 AND task_id = $4 \
+               AND task_id IN (SELECT id FROM tasks WHERE user_id = $5) \
+             RETURNING id, title, done",
+        )
+        .bind(patch.title)
+        .bind(patch.done)
+        .bind(info.item_id)
+        .bind(info.task_id)
+        .bind(info.user_id)
+        .fetch_all(&self.pool)
+        .await
+        .and_then(returned_row)
+    }
+
+    async fn delete_checklist_item(&self, info: ChecklistItemInfo) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "DELETE FROM checklist_items WHERE id = $1 AND task_id = $2 \
+               AND task_id IN (SELECT id FROM tasks WHERE user_id = $3)",
+        )
+        .bind(info.item_id)
+        .bind(info.task_id)
+        .bind(info.user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl DependencyRepository for SqliteRepository {
+    async fn add_dependency(&self, info: DependencyInfo) -> Result<bool, Error> {
+        let mut tx = self.begin_write().await?;
+
+        // Like `add_dependency_db`
+        let cycle: bool = sqlx::query_scalar(
+            "WITH RECURSIVE blockers (id) AS ( \
+                 SELECT blocker_id FROM task_dependencies \
+                 WHERE task_id = $1 AND task_id IN (SELECT id FROM tasks WHERE user_id = $2) \
+                 UNION \
+                 SELECT task_dependencies.blocker_id \
+                 FROM task_dependencies JOIN blockers ON task_dependencies.task_id = blockers.id) \
+             SELECT EXISTS (SELECT 1 FROM blockers WHERE id = $3)",
+        )
+        .bind(info.blocker_id)
+        .bind(info.user_id)
+        .bind(info.task_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if cycle {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            "INSERT INTO task_dependencies (task_id, blocker_id) \
+             SELECT tasks.id, blockers.id FROM tasks, tasks AS blockers \
+             WHERE tasks.id = $1 AND tasks.user_id = $3 \
+               AND blockers.id = $2 AND blockers.user_id = $3 \
+             ON CONFLICT (task_id, blocker_id) DO UPDATE SET task_id = excluded.task_id \
+             RETURNING task_id",
+        )
+        .bind(info.task_id)
+        .bind(info.blocker_id)
+        .bind(info.user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn remove_dependency(&self, info: DependencyInfo) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "DELETE FROM task_dependencies WHERE task_id = $1 AND blocker_id = $2 \
+               AND task_id IN (SELECT id FROM tasks WHERE user_id = $3)",
+        )
+        .bind(info.task_id)
+        .bind(info.blocker_id)
+        .bind(info.user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for SqliteRepository {
+    async fn create_refresh_token(
+        &self,
+        user_id: i32,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, Error> {
+        sqlx::query_as(
+            "INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at) \
+             VALUES ($1, $2, $3, $4) \
+             RETURNING id, user_id, family_id, expires_at, revoked_at",
+        )
+        .bind(user_id)
+        .bind(family_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .fetch_all(&self.pool)
+        .await
+        .and_then(returned_row)
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error> {
+        sqlx::query_as(
+            "SELECT id, user_id, family_id, expires_at, revoked_at \
+             FROM refresh_tokens WHERE token_hash = $1",
+        )
+        .bind(token_hash)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        current: &RefreshToken,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<RefreshToken>, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let revoked = sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL",
+        )
+        .bind(Utc::now())
+        .bind(current.id)
+        .execute(&mut *tx)
+        .await?;
+
+        if revoked.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+
+        let next: RefreshToken = sqlx::query_as(
+            "INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at) \
+             VALUES ($1, $2, $3, $4) \
+             RETURNING id, user_id, family_id, expires_at, revoked_at",
+        )
+        .bind(current.user_id)
+        .bind(current.family_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE refresh_tokens SET replaced_by = $1 WHERE id = $2")
+            .bind(next.id)
+            .bind(current.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(next))
+    }
+
+    async fn revoke_token_family(&self, user_id: i32, family_id: Uuid) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = $1 \
+             WHERE user_id = $2 AND family_id = $3 AND revoked_at IS NULL",
+        )
+        .bind(Utc::now())
+        .bind(user_id)
+        .bind(family_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl HealthRepository for SqliteRepository {
+    async fn ping(&self) -> Result<(), Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> Result<i64, Error> {
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_migrations")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(version.unwrap_or(0))
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        Some(PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+        })
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
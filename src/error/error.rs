@@ -0,0 +1,167 @@
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::{http::StatusCode, HttpRequest, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use utoipa::ToSchema;
+
+// The error type returned by every handler. It always renders as an
+// `ErrorBody`, so clients can rely on `code` instead of parsing messages.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Validation(Value),
+    Internal(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            ApiError::Validation(details) => Some(details.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message) => write!(f, "{}", message),
+            ApiError::Validation(_) => write!(f, "Request validation failed"),
+            // The cause is logged when the response is rendered, never returned
+            ApiError::Internal(_) => write!(f, "Internal server error"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(cause) = self {
+            log::error!("Internal error: {}", cause);
+        }
+
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code().to_owned(),
+            message: self.to_string(),
+            details: self.details(),
+        })
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Resource not found".to_owned()),
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                ApiError::Conflict("Resource already exists".to_owned())
+            }
+            e => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+}
+
+impl From<validator::ValidationErrors> for ApiError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut violations: Vec<Value> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| {
+                    json!({
+                        "field": field,
+                        "code": error.code,
+                        "message": error.message,
+                    })
+                })
+            })
+            .collect();
+        violations.sort_by_key(|violation| violation["field"].to_string());
+
+        ApiError::Validation(Value::Array(violations))
+    }
+}
+
+// Registered through `web::JsonConfig` so malformed bodies get the same envelope
+pub fn json_error_handler(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest(error.to_string()).into()
+}
+
+// Registered through `web::QueryConfig`, e.g. for an unknown `status` filter
+pub fn query_error_handler(error: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest(error.to_string()).into()
+}
+
+// Registered through `web::PathConfig`, e.g. for a non-numeric `{task_id}`
+pub fn path_error_handler(error: PathError, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::NotFound(error.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    #[actix_rt::test]
+    async fn test_row_not_found_renders_404_envelope() {
+        let error = ApiError::from(sqlx::Error::RowNotFound);
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+
+        let body = to_bytes(error.error_response().into_body()).await.unwrap();
+        let body: ErrorBody = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.code, "not_found");
+        assert!(body.details.is_none());
+    }
+
+    #[test]
+    fn test_internal_errors_hide_their_cause() {
+        let error = ApiError::from(sqlx::Error::PoolTimedOut);
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error.to_string(), "Internal server error");
+    }
+}
@@ -0,0 +1,44 @@
+use crate::error::error::ApiError;
+use actix_web::{dev::Payload, web, Error, FromRequest, HttpRequest};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use validator::Validate;
+
+// Like `web::Json`, but also runs the `validator` rules of `T` and rejects
+// the request with a 422 listing every violated field.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(req, payload);
+
+        Box::pin(async move {
+            let value = json.await?.into_inner();
+            value.validate().map_err(|e| {
+                log::warn!("Rejected invalid request body: {}", e);
+                ApiError::from(e)
+            })?;
+            Ok(ValidatedJson(value))
+        })
+    }
+}
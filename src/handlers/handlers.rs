@@ -1,119 +1,305 @@
-use crate::db::db::{
-    authenticate_user, create_task_db, create_user_db, delete_user_task_db, get_all_users_db,
-    get_user_task_db, get_user_tasks_db, update_user_task_db,
+use crate::error::error::{ApiError, ErrorBody};
+use crate::handlers::extractors::ValidatedJson;
+use crate::jwt::auth::{AdminOnly, AuthenticatedUser, Authorized};
+use crate::jwt::session::{end_session, refresh_session, start_session};
+use crate::model::models::{
+    Board, BoardColumn, ChecklistItem, ChecklistItemInfo, ChecklistItemInput, ChecklistItemPatch,
+    DependencyInfo, Info, InvalidParent, OccurrenceQuery, Occurrences, Placement, PublicUser,
+    RefreshInput, RoleInput, SignInInput, Tag, TagInfo, TagInput, Task, TaskCursor, TaskInput,
+    TaskMove, TaskPage, TaskPatch, TaskQuery, TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite,
+    TokenResponse, UserCursor, UserInput, UserPage, UserQuery, UserResponse, MAX_SUBTASK_DEPTH,
 };
-use crate::jwt::jwt::generate_jwt;
-use crate::model::models::{Info, SignInInput, TaskInput, TaskUpdate, UserInput, UserResponse};
+use crate::model::recurrence::Recurrence;
 use crate::model::state::AppState;
-use actix_web::error::InternalError;
-use actix_web::{http::StatusCode, web, Error, HttpResponse, Responder, ResponseError};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
 use log;
-use std::fmt;
-
-#[derive(Debug)]
-pub struct CustomError {
-    inner: sqlx::Error,
-}
-
-impl fmt::Display for CustomError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "CustomError: {}", self.inner)
-    }
-}
-
-impl From<sqlx::Error> for CustomError {
-    fn from(error: sqlx::Error) -> Self {
-        CustomError { inner: error }
-    }
-}
-
-impl ResponseError for CustomError {
-    fn error_response(&self) -> HttpResponse {
-        HttpResponse::InternalServerError().json(format!("{}", self))
-    }
-}
+use validator::{Validate, ValidationError, ValidationErrors};
 
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body = UserInput,
+    responses(
+        (status = 200, description = "User created and signed in", body = UserResponse),
+        (status = 409, description = "Username or email already taken", body = ErrorBody),
+        (status = 422, description = "Invalid fields", body = ErrorBody),
+    )
+)]
 pub async fn create_user(
     state: web::Data<AppState>,
-    new_user: web::Json<UserInput>,
-) -> Result<HttpResponse, Error> {
+    new_user: ValidatedJson<UserInput>,
+) -> Result<HttpResponse, ApiError> {
     log::info!(
         "Received request to create user with username {}",
         new_user.username,
     );
 
-    let user = create_user_db(&state.pool, &new_user.into_inner())
+    let user = state
+        .users
+        .create_user(&new_user.into_inner())
         .await
         .map_err(|e| {
             log::error!("Failed to create user: {}", e);
-            actix_web::Error::from(actix_web::error::InternalError::new(
-                e,
-                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-            ))
+            ApiError::from(e)
         })?;
 
     log::info!("Successfully created user with id {}", user.id);
 
-    let token = generate_jwt(&user.id.to_string()).await.map_err(|e| {
-        log::error!("Failed to generate JWT: {}", e);
-        actix_web::Error::from(actix_web::error::InternalError::new(
-            e,
-            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-        ))
+    let tokens = start_session(&state, user.id).await.map_err(|e| {
+        log::error!("Failed to start session: {:?}", e);
+        e
     })?;
 
     // Use the CreateUserResponse struct to construct the response
-    let response = UserResponse { user, token };
+    let response = UserResponse {
+        user: user.into(),
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+    };
 
     Ok(HttpResponse::Ok().json(response))
 }
 
 // Gets Users
-pub async fn get_users(state: web::Data<AppState>) -> Result<HttpResponse, Error> {
-    log::info!("Received request to get all users");
+#[utoipa::path(
+    get,
+    path = "/get_users",
+    tag = "users",
+    params(UserQuery),
+    responses(
+        (status = 200, description = "A page of users; a `Link` header points at the next one", body = UserPage),
+        (status = 400, description = "Invalid cursor", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Caller is not an admin", body = ErrorBody),
+        (status = 422, description = "Invalid query", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_users(
+    admin: Authorized<AdminOnly>,
+    req: HttpRequest,
+    filter: web::Query<UserQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let filter = filter.into_inner();
+    log::info!(
+        "Received request to get users from admin with id {}",
+        admin.user.user_id
+    );
 
-    let users = get_all_users_db(&state.pool).await.map_err(|e| {
-        log::error!("Failed to get users: {}", e);
-        InternalError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
-    })?;
+    filter.validate()?;
+    let after_id = match filter.cursor.as_deref() {
+        Some(cursor) => Some(
+            UserCursor::decode(cursor)
+                .ok_or_else(|| ApiError::BadRequest("Invalid cursor".to_owned()))?
+                .id,
+        ),
+        None => None,
+    };
+    let limit = filter.limit.unwrap_or(UserQuery::DEFAULT_LIMIT);
+
+    // One extra row tells whether there is a next page
+    let mut users = state
+        .users
+        .get_users(&filter, after_id, limit + 1)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get users: {}", e);
+            ApiError::from(e)
+        })?;
+
+    let next_cursor = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|last| UserCursor { id: last.id }.encode())
+    } else {
+        None
+    };
+
+    let total = if filter.include_total {
+        Some(state.users.count_users(&filter).await.map_err(|e| {
+            log::error!("Failed to count users: {}", e);
+            ApiError::from(e)
+        })?)
+    } else {
+        None
+    };
 
     log::info!("Successfully fetched {} users", users.len());
-    Ok(HttpResponse::Ok().json(users))
+
+    let mut response = HttpResponse::Ok();
+    if let Some(next_cursor) = &next_cursor {
+        response.insert_header((header::LINK, next_page_link(&req, next_cursor)));
+    }
+
+    Ok(response.json(UserPage {
+        users: users.into_iter().map(PublicUser::from).collect(),
+        next_cursor,
+        total,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{user_id}/role",
+    tag = "users",
+    params(("user_id" = i32, Path, description = "User to change")),
+    request_body = RoleInput,
+    responses(
+        (status = 204, description = "Role updated"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Caller is not an admin", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_user_role(
+    admin: Authorized<AdminOnly>,
+    user_id: web::Path<i32>,
+    state: web::Data<AppState>,
+    input: web::Json<RoleInput>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_id.into_inner();
+    log::info!(
+        "Admin with id {} requested role {:?} for user with id {}",
+        admin.user.user_id,
+        input.role,
+        user_id
+    );
+
+    let rows_affected = state
+        .users
+        .set_user_role(user_id, input.role)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update role of user with id {}: {}", user_id, e);
+            ApiError::from(e)
+        })?;
+
+    if rows_affected == 0 {
+        Err(ApiError::NotFound("No user found to update".to_owned()))
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
 }
 
 // Create Task
+#[utoipa::path(
+    post,
+    path = "/users/{user_id}/tasks",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks")),
+    request_body = TaskInput,
+    responses(
+        (status = 200, description = "Task created", body = Task),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such parent task", body = ErrorBody),
+        (status = 409, description = "The parent is already nested as deep as subtasks go", body = ErrorBody),
+        (status = 422, description = "Invalid fields, or a recurrence without a due date", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn create_task(
-    new_task: web::Json<TaskInput>,
+    _auth: AuthenticatedUser,
+    new_task: ValidatedJson<TaskInput>,
     user_id: web::Path<i32>,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
     log::info!(
         "Received request to create task for user with id {}",
         user_id
     );
 
-    let task = create_task_db(&state.pool, &new_task.into_inner(), user_id.into_inner())
+    let user_id = user_id.into_inner();
+    let mut new_task = new_task.into_inner();
+    check_recurrence(new_task.due_date, &mut new_task.recurrence)?;
+
+    let task = match state
+        .tasks
+        .create_task(&new_task, user_id)
         .await
         .map_err(|e| {
             log::error!("Failed to create task: {}", e);
-            CustomError::from(e)
-        })?;
+            ApiError::from(e)
+        })? {
+        TaskWrite::Written(task) => task,
+        TaskWrite::InvalidParent(invalid) => return Err(parent_error(invalid)),
+        TaskWrite::Blocked(_) => unreachable!("a new task waits for no task"),
+    };
 
     log::info!("Successfully created task with id {}", task.id);
     Ok(HttpResponse::Ok().json(task))
 }
 
+// Link to the page after `next_cursor`, keeping every other query parameter
+fn next_page_link(req: &HttpRequest, next_cursor: &str) -> String {
+    let mut params: Vec<&str> = req
+        .query_string()
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("cursor="))
+        .collect();
+    let cursor_param = format!("cursor={}", next_cursor);
+    params.push(&cursor_param);
+
+    format!("<{}?{}>; rel=\"next\"", req.path(), params.join("&"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/tasks",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks"), TaskQuery),
+    responses(
+        (status = 200, description = "A page of tasks; a `Link` header points at the next one", body = TaskPage),
+        (status = 400, description = "Invalid cursor", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 422, description = "Invalid query", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn get_user_tasks(
+    _auth: AuthenticatedUser,
+    req: HttpRequest,
     user_id: web::Path<i32>,
+    filter: web::Query<TaskQuery>,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
     let user_id = user_id.into_inner();
+    let filter = filter.into_inner();
     log::info!("Received request to get tasks for user with id {}", user_id);
 
-    let tasks = get_user_tasks_db(&state.pool, user_id).await.map_err(|e| {
-        log::error!("Failed to get tasks for user with id {}: {}", user_id, e);
-        InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR)
-    })?;
+    filter.validate()?;
+    let after = match filter.cursor.as_deref() {
+        Some(cursor) => Some(
+            TaskCursor::decode(cursor)
+                .filter(|after| after.sort == filter.sort && after.order == filter.order)
+                .ok_or_else(|| ApiError::BadRequest("Invalid cursor".to_owned()))?,
+        ),
+        None => None,
+    };
+    let limit = filter.limit.unwrap_or(TaskQuery::DEFAULT_LIMIT);
+
+    // One extra row tells whether there is a next page
+    let mut tasks = state
+        .tasks
+        .get_user_tasks(user_id, &filter, after.as_ref(), limit + 1)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get tasks for user with id {}: {}", user_id, e);
+            ApiError::from(e)
+        })?;
+
+    let next_cursor = if tasks.len() as i64 > limit {
+        tasks.truncate(limit as usize);
+        tasks
+            .last()
+            .map(|last| TaskCursor::after(last, filter.sort, filter.order).encode())
+    } else {
+        None
+    };
 
     log::info!(
         "Successfully fetched {} tasks for user with id {}",
@@ -121,27 +307,212 @@ pub async fn get_user_tasks(
         user_id
     );
 
-    Ok(HttpResponse::Ok().json(tasks))
+    let mut response = HttpResponse::Ok();
+    if let Some(next_cursor) = &next_cursor {
+        response.insert_header((header::LINK, next_page_link(&req, next_cursor)));
+    }
+
+    Ok(response.json(TaskPage { tasks, next_cursor }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/tasks/{task_id}",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks"), ("task_id" = i32, Path, description = "Task id")),
+    responses(
+        (status = 200, description = "The task", body = Task),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn get_user_task(
+    _auth: AuthenticatedUser,
     info: web::Path<Info>,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
     let info = info.into_inner();
 
-    let task = get_user_task_db(&state.pool, info.user_id, info.task_id)
+    let task = state
+        .tasks
+        .get_user_task(info.user_id, info.task_id)
         .await
-        .map_err(|e| InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR))?;
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Task not found".to_owned()),
+            e => {
+                log::error!(
+                    "Failed to get task with id {} for user with id {}: {}",
+                    info.task_id,
+                    info.user_id,
+                    e
+                );
+                ApiError::from(e)
+            }
+        })?;
 
     Ok(HttpResponse::Ok().json(task))
 }
 
+// Returns the task's current status if it may move to `next`
+async fn check_status_transition(
+    state: &AppState,
+    info: &Info,
+    next: TaskStatus,
+) -> Result<TaskStatus, ApiError> {
+    let current = state
+        .tasks
+        .get_user_task(info.user_id, info.task_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("No task found to update".to_owned()),
+            e => ApiError::from(e),
+        })?
+        .status;
+
+    check_transition(info, current, next)?;
+    Ok(current)
+}
+
+// Returns the written task, or the error for a write that was refused. A
+// task can only be completed once the tasks it waits for are done or
+// cancelled. Since completing it also completes its subtasks that are not
+// blocked, their blockers count too. The write checks this itself, so a
+// dependency added or a blocker reopened meanwhile is not missed.
+fn check_write(info: &Info, write: TaskWrite) -> Result<Task, ApiError> {
+    let blockers = match write {
+        TaskWrite::Written(task) => return Ok(task),
+        TaskWrite::Blocked(blockers) => blockers,
+        TaskWrite::InvalidParent(invalid) => return Err(parent_error(invalid)),
+    };
+
+    let ids: Vec<String> = blockers.iter().map(i32::to_string).collect();
+    log::warn!(
+        "Rejected completion of task with id {} blocked by tasks {}",
+        info.task_id,
+        ids.join(", ")
+    );
+    Err(ApiError::Conflict(format!(
+        "Task is blocked by open tasks {}",
+        ids.join(", ")
+    )))
+}
+
+// Writes that change a task's status only apply while it still has the status
+// it was checked against, so they find no row both when the task is gone and
+// when a concurrent request changed its status first. Tells the two apart.
+async fn missing_or_changed(state: &AppState, info: &Info, not_found: &str) -> ApiError {
+    match state.tasks.get_user_task(info.user_id, info.task_id).await {
+        Ok(task) => {
+            log::warn!(
+                "Lost a concurrent status change of task with id {}, now {}",
+                info.task_id,
+                task.status
+            );
+            ApiError::Conflict(format!(
+                "Task was changed to {} by another request, retry against its current state",
+                task.status
+            ))
+        }
+        Err(sqlx::Error::RowNotFound) => ApiError::NotFound(not_found.to_owned()),
+        Err(e) => ApiError::from(e),
+    }
+}
+
+fn check_transition(info: &Info, current: TaskStatus, next: TaskStatus) -> Result<(), ApiError> {
+    if current.can_transition_to(next) {
+        Ok(())
+    } else {
+        log::warn!(
+            "Rejected status change of task with id {} from {} to {}",
+            info.task_id,
+            current,
+            next
+        );
+        Err(ApiError::Conflict(format!(
+            "Cannot move task from {} to {}",
+            current, next
+        )))
+    }
+}
+
+// A parent must be a task of the user, must not be the task or one of its
+// subtasks, and the task's subtasks must still fit within `MAX_SUBTASK_DEPTH`
+// below it. The write checks this itself, so a task nested meanwhile is not
+// missed.
+fn parent_error(invalid: InvalidParent) -> ApiError {
+    match invalid {
+        InvalidParent::Missing => ApiError::NotFound("No parent task found".to_owned()),
+        InvalidParent::Cycle => {
+            ApiError::Conflict("A task cannot become a subtask of its own subtask".to_owned())
+        }
+        InvalidParent::TooDeep => ApiError::Conflict(format!(
+            "Subtasks cannot be nested more than {} levels deep",
+            MAX_SUBTASK_DEPTH
+        )),
+    }
+}
+
+// Occurrences count from the due date, so a recurring task needs one. Leaves
+// `recurrence` in canonical form.
+fn check_recurrence(
+    due_date: Option<NaiveDateTime>,
+    recurrence: &mut Option<String>,
+) -> Result<(), ApiError> {
+    let Some(rule) = recurrence.as_mut() else {
+        return Ok(());
+    };
+    if due_date.is_none() {
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            "recurrence",
+            ValidationError::new("due_date_required")
+                .with_message("a recurring task needs a due_date".into()),
+        );
+        return Err(errors.into());
+    }
+
+    // Validation has already parsed the rule
+    if let Ok(parsed) = rule.parse::<Recurrence>() {
+        *rule = parsed.to_string();
+    }
+    Ok(())
+}
+
+// Rules are validated before they are stored, so this only fails if the
+// database was edited by hand
+fn stored_recurrence(task_id: i32, rule: &str) -> Result<Recurrence, ApiError> {
+    rule.parse().map_err(|e| {
+        ApiError::Internal(format!(
+            "Stored recurrence of task with id {} is invalid: {}",
+            task_id, e
+        ))
+    })
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{user_id}/tasks/{task_id}",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks"), ("task_id" = i32, Path, description = "Task id")),
+    request_body = TaskUpdate,
+    responses(
+        (status = 200, description = "The updated task", body = Task),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task", body = ErrorBody),
+        (status = 409, description = "Status transition not allowed, a concurrent status change, or completing a task with open blockers", body = ErrorBody),
+        (status = 422, description = "Invalid fields, or a recurrence without a due date", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn update_user_task(
+    _auth: AuthenticatedUser,
     info: web::Path<Info>,
     state: web::Data<AppState>,
-    task_update: web::Json<TaskUpdate>,
-) -> Result<HttpResponse, Error> {
+    task_update: ValidatedJson<TaskUpdate>,
+) -> Result<HttpResponse, ApiError> {
     let info = info.into_inner();
     log::info!(
         "Received request to update task with id {} for user with id {}",
@@ -149,17 +520,29 @@ pub async fn update_user_task(
         info.user_id
     );
 
-    let task = update_user_task_db(&state.pool, info.clone(), task_update.into_inner())
+    let mut task_update = task_update.into_inner();
+    check_recurrence(task_update.due_date, &mut task_update.recurrence)?;
+    let current_status = check_status_transition(&state, &info, task_update.status).await?;
+
+    let task = match state
+        .tasks
+        .update_user_task(info.clone(), task_update, current_status)
         .await
-        .map_err(|e| {
+    {
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(missing_or_changed(&state, &info, "No task found to update").await);
+        }
+        result => result.map_err(|e| {
             log::error!(
                 "Failed to update task with id {} for user with id {}: {}",
                 info.task_id,
                 info.user_id,
                 e
             );
-            InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR)
-        })?;
+            ApiError::from(e)
+        })?,
+    };
+    let task = check_write(&info, task)?;
 
     log::info!(
         "Successfully updated task with id {} for user with id {}",
@@ -170,10 +553,111 @@ pub async fn update_user_task(
     Ok(HttpResponse::Ok().json(task))
 }
 
+#[utoipa::path(
+    patch,
+    path = "/users/{user_id}/tasks/{task_id}",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks"), ("task_id" = i32, Path, description = "Task id")),
+    request_body = TaskPatch,
+    responses(
+        (status = 200, description = "The updated task", body = Task),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task or parent task", body = ErrorBody),
+        (status = 409, description = "Status transition not allowed, a concurrent status change, completing a task with open blockers, or a parent that would create a cycle or nest subtasks too deep", body = ErrorBody),
+        (status = 422, description = "Invalid fields, or a recurrence without a due date", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn patch_user_task(
+    _auth: AuthenticatedUser,
+    info: web::Path<Info>,
+    state: web::Data<AppState>,
+    task_patch: ValidatedJson<TaskPatch>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to patch task with id {} for user with id {}",
+        info.task_id,
+        info.user_id
+    );
+
+    let mut task_patch = task_patch.into_inner();
+    if task_patch.due_date.is_some() || task_patch.recurrence.is_some() {
+        // The fields left out keep their current values
+        let current = state
+            .tasks
+            .get_user_task(info.user_id, info.task_id)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => {
+                    ApiError::NotFound("No task found to update".to_owned())
+                }
+                e => ApiError::from(e),
+            })?;
+        let due_date = task_patch.due_date.unwrap_or(current.due_date);
+        match task_patch.recurrence.as_mut() {
+            Some(recurrence) => check_recurrence(due_date, recurrence)?,
+            None => check_recurrence(due_date, &mut current.recurrence.clone())?,
+        }
+    }
+    let current_status = match task_patch.status {
+        Some(next) => Some(check_status_transition(&state, &info, next).await?),
+        None => None,
+    };
+    if task_patch.parent_id == Some(Some(info.task_id)) {
+        return Err(ApiError::Conflict(
+            "A task cannot be its own parent".to_owned(),
+        ));
+    }
+
+    let task = match state
+        .tasks
+        .patch_user_task(info.clone(), task_patch, current_status)
+        .await
+    {
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(missing_or_changed(&state, &info, "No task found to update").await);
+        }
+        result => result.map_err(|e| {
+            log::error!(
+                "Failed to patch task with id {} for user with id {}: {}",
+                info.task_id,
+                info.user_id,
+                e
+            );
+            ApiError::from(e)
+        })?,
+    };
+    let task = check_write(&info, task)?;
+
+    log::info!(
+        "Successfully patched task with id {} for user with id {}",
+        task.id,
+        task.user_id
+    );
+
+    Ok(HttpResponse::Ok().json(task))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}/tasks/{task_id}",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks"), ("task_id" = i32, Path, description = "Task id")),
+    responses(
+        (status = 204, description = "Task deleted along with its subtasks"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn delete_user_task(
+    _auth: AuthenticatedUser,
     info: web::Path<Info>,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
     let info = info.into_inner();
     log::info!(
         "Received request to delete task with id {} for user with id {}",
@@ -181,7 +665,9 @@ pub async fn delete_user_task(
         info.user_id
     );
 
-    let rows_affected = delete_user_task_db(&state.pool, info.clone())
+    let rows_affected = state
+        .tasks
+        .delete_user_task(info.clone())
         .await
         .map_err(|e| {
             log::error!(
@@ -190,11 +676,11 @@ pub async fn delete_user_task(
                 info.user_id,
                 e
             );
-            InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR)
+            ApiError::from(e)
         })?;
 
     if rows_affected == 0 {
-        Err(InternalError::new("No task found to delete", StatusCode::NOT_FOUND).into())
+        Err(ApiError::NotFound("No task found to delete".to_owned()))
     } else {
         log::info!(
             "Successfully deleted task with id {} for user with id {}",
@@ -205,31 +691,809 @@ pub async fn delete_user_task(
     }
 }
 
-pub async fn sign_in_handler(
+#[utoipa::path(
+    post,
+    path = "/users/{user_id}/tasks/{task_id}/move",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks"), ("task_id" = i32, Path, description = "Task id")),
+    request_body = TaskMove,
+    responses(
+        (status = 200, description = "The moved task", body = Task),
+        (status = 400, description = "Both `before_id` and `after_id`, a status other than that task's, or the task itself", body = ErrorBody),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task, or no task to move next to", body = ErrorBody),
+        (status = 409, description = "Status transition not allowed, a concurrent change of either task, or completing a task with open blockers", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn move_user_task(
+    _auth: AuthenticatedUser,
+    info: web::Path<Info>,
     state: web::Data<AppState>,
-    info: web::Json<SignInInput>,
-) -> impl Responder {
-    let username = &info.username;
-    let password = &info.password;
+    input: web::Json<TaskMove>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    let input = input.into_inner();
+    log::info!(
+        "Received request to move task with id {} for user with id {}",
+        info.task_id,
+        info.user_id
+    );
+
+    if input.before_id.is_some() && input.after_id.is_some() {
+        return Err(ApiError::BadRequest(
+            "Give either before_id or after_id, not both".to_owned(),
+        ));
+    }
+    let anchor_id = input.before_id.or(input.after_id);
+    if anchor_id == Some(info.task_id) {
+        return Err(ApiError::BadRequest(
+            "A task cannot be moved next to itself".to_owned(),
+        ));
+    }
+
+    let task = state
+        .tasks
+        .get_user_task(info.user_id, info.task_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("No task found to move".to_owned()),
+            e => ApiError::from(e),
+        })?;
+    let anchor = match anchor_id {
+        Some(anchor_id) => Some(
+            state
+                .tasks
+                .get_user_task(info.user_id, anchor_id)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::RowNotFound => {
+                        ApiError::NotFound("No task found to move next to".to_owned())
+                    }
+                    e => ApiError::from(e),
+                })?,
+        ),
+        None => None,
+    };
+
+    let status = match (&anchor, input.status) {
+        (Some(anchor), Some(status)) if anchor.status != status => {
+            return Err(ApiError::BadRequest(format!(
+                "Task {} is not in status {}",
+                anchor.id, status
+            )));
+        }
+        (Some(anchor), _) => anchor.status,
+        (None, status) => status.unwrap_or(task.status),
+    };
+    check_transition(&info, task.status, status)?;
+
+    let placement = match (input.before_id, input.after_id) {
+        (Some(before_id), _) => Placement::Before(before_id),
+        (_, Some(after_id)) => Placement::After(after_id),
+        (None, None) => Placement::End,
+    };
+
+    let current_status = task.status;
+    let task = match state
+        .tasks
+        .move_user_task(info.clone(), status, placement, current_status)
+        .await
+    {
+        Err(sqlx::Error::RowNotFound) => {
+            // With the task unchanged, it was the task to move next to that
+            // left the column
+            return Err(
+                match (
+                    anchor_id,
+                    state.tasks.get_user_task(info.user_id, info.task_id).await,
+                ) {
+                    (Some(anchor_id), Ok(task)) if task.status == current_status => {
+                        ApiError::Conflict(format!(
+                            "Task {} was moved or deleted by another request, retry against its current state",
+                            anchor_id
+                        ))
+                    }
+                    _ => missing_or_changed(&state, &info, "No task found to move").await,
+                },
+            );
+        }
+        result => result.map_err(|e| {
+            log::error!(
+                "Failed to move task with id {} for user with id {}: {}",
+                info.task_id,
+                info.user_id,
+                e
+            );
+            ApiError::from(e)
+        })?,
+    };
+    let task = check_write(&info, task)?;
+
+    log::info!(
+        "Successfully moved task with id {} to {} for user with id {}",
+        task.id,
+        task.status,
+        task.user_id
+    );
+
+    Ok(HttpResponse::Ok().json(task))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/tasks/{task_id}/occurrences",
+    tag = "tasks",
+    params(
+        ("user_id" = i32, Path, description = "Owner of the tasks"),
+        ("task_id" = i32, Path, description = "Task id"),
+        OccurrenceQuery
+    ),
+    responses(
+        (status = 200, description = "The task's next due dates, its own first", body = Occurrences),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task", body = ErrorBody),
+        (status = 422, description = "Invalid query", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_task_occurrences(
+    _auth: AuthenticatedUser,
+    info: web::Path<Info>,
+    query: web::Query<OccurrenceQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    let query = query.into_inner();
+    query.validate()?;
+
+    let task = state
+        .tasks
+        .get_user_task(info.user_id, info.task_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Task not found".to_owned()),
+            e => ApiError::from(e),
+        })?;
+
+    let limit = query.limit.unwrap_or(OccurrenceQuery::DEFAULT_LIMIT);
+    let occurrences = match (task.recurrence.as_deref(), task.due_date) {
+        (Some(rule), Some(due_date)) => stored_recurrence(task.id, rule)?
+            .occurrences(due_date)
+            .take(limit)
+            .collect(),
+        (_, due_date) => due_date.into_iter().collect(),
+    };
+
+    Ok(HttpResponse::Ok().json(Occurrences {
+        recurrence: task.recurrence,
+        occurrences,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/board",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks")),
+    responses(
+        (status = 200, description = "Every task of the user, one column per status", body = Board),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_user_board(
+    _auth: AuthenticatedUser,
+    user_id: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_id.into_inner();
+    log::info!("Received request to get board for user with id {}", user_id);
+
+    let tasks = state.tasks.get_user_board(user_id).await.map_err(|e| {
+        log::error!("Failed to get board for user with id {}: {}", user_id, e);
+        ApiError::from(e)
+    })?;
+
+    let mut columns: Vec<BoardColumn> = TaskStatus::ALL
+        .into_iter()
+        .map(|status| BoardColumn {
+            status,
+            tasks: Vec::new(),
+        })
+        .collect();
+    for task in tasks {
+        if let Some(column) = columns
+            .iter_mut()
+            .find(|column| column.status == task.status)
+        {
+            column.tasks.push(task);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(Board { columns }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{user_id}/tasks/{task_id}/checklist",
+    tag = "tasks",
+    params(("user_id" = i32, Path, description = "Owner of the tasks"), ("task_id" = i32, Path, description = "Task id")),
+    request_body = ChecklistItemInput,
+    responses(
+        (status = 200, description = "Item added to the end of the checklist", body = ChecklistItem),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task", body = ErrorBody),
+        (status = 422, description = "Invalid fields", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_checklist_item(
+    _auth: AuthenticatedUser,
+    info: web::Path<Info>,
+    state: web::Data<AppState>,
+    input: ValidatedJson<ChecklistItemInput>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to add checklist item to task with id {} for user with id {}",
+        info.task_id,
+        info.user_id
+    );
 
-    match authenticate_user(&state.pool, username, password).await {
-        Ok(user) => match generate_jwt(&user.id.to_string()).await {
-            Ok(token) => {
-                let response = UserResponse { user, token };
-                HttpResponse::Ok().json(response)
+    let item = state
+        .checklists
+        .create_checklist_item(info, &input.title)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                ApiError::NotFound("No task found to add the item to".to_owned())
             }
-            Err(e) => {
-                log::error!("JWT generation failed: {}", e);
-                HttpResponse::InternalServerError().body("Failed to generate token")
+            e => {
+                log::error!("Failed to add checklist item: {}", e);
+                ApiError::from(e)
             }
-        },
-        Err(e) => {
-            log::error!("Authentication failed: {}", e);
-            if e.to_string().contains("Invalid username or password") {
-                HttpResponse::Unauthorized().body("Invalid credentials")
-            } else {
-                HttpResponse::InternalServerError().body("Internal server error")
+        })?;
+
+    log::info!("Successfully added checklist item with id {}", item.id);
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/users/{user_id}/tasks/{task_id}/checklist/{item_id}",
+    tag = "tasks",
+    params(
+        ("user_id" = i32, Path, description = "Owner of the tasks"),
+        ("task_id" = i32, Path, description = "Task id"),
+        ("item_id" = i32, Path, description = "Checklist item id"),
+    ),
+    request_body = ChecklistItemPatch,
+    responses(
+        (status = 200, description = "The updated item", body = ChecklistItem),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such item on the task", body = ErrorBody),
+        (status = 422, description = "Invalid fields", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_checklist_item(
+    _auth: AuthenticatedUser,
+    info: web::Path<ChecklistItemInfo>,
+    state: web::Data<AppState>,
+    patch: ValidatedJson<ChecklistItemPatch>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to update checklist item with id {} of task with id {} for user with id {}",
+        info.item_id,
+        info.task_id,
+        info.user_id
+    );
+
+    let item = state
+        .checklists
+        .update_checklist_item(info, patch.into_inner())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                ApiError::NotFound("No checklist item found to update".to_owned())
+            }
+            e => {
+                log::error!("Failed to update checklist item: {}", e);
+                ApiError::from(e)
+            }
+        })?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}/tasks/{task_id}/checklist/{item_id}",
+    tag = "tasks",
+    params(
+        ("user_id" = i32, Path, description = "Owner of the tasks"),
+        ("task_id" = i32, Path, description = "Task id"),
+        ("item_id" = i32, Path, description = "Checklist item id"),
+    ),
+    responses(
+        (status = 204, description = "Item removed from the checklist"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such item on the task", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_checklist_item(
+    _auth: AuthenticatedUser,
+    info: web::Path<ChecklistItemInfo>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to delete checklist item with id {} of task with id {} for user with id {}",
+        info.item_id,
+        info.task_id,
+        info.user_id
+    );
+
+    let rows_affected = state
+        .checklists
+        .delete_checklist_item(info)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to delete checklist item: {}", e);
+            ApiError::from(e)
+        })?;
+
+    if rows_affected == 0 {
+        Err(ApiError::NotFound(
+            "No checklist item found to delete".to_owned(),
+        ))
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{user_id}/tasks/{task_id}/dependencies/{blocker_id}",
+    tag = "tasks",
+    params(
+        ("user_id" = i32, Path, description = "Owner of the tasks"),
+        ("task_id" = i32, Path, description = "Task that waits"),
+        ("blocker_id" = i32, Path, description = "Task it waits for"),
+    ),
+    responses(
+        (status = 204, description = "Dependency added; adding it again changes nothing"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task or blocker", body = ErrorBody),
+        (status = 409, description = "The blocker already waits for the task, directly or not", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn add_dependency(
+    _auth: AuthenticatedUser,
+    info: web::Path<DependencyInfo>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to make task with id {} wait for task with id {} for user with id {}",
+        info.task_id,
+        info.blocker_id,
+        info.user_id
+    );
+
+    if info.task_id == info.blocker_id {
+        return Err(ApiError::Conflict(
+            "A task cannot wait for itself".to_owned(),
+        ));
+    }
+    let added = state
+        .dependencies
+        .add_dependency(info.clone())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                ApiError::NotFound("No task or blocker found to link".to_owned())
             }
+            e => {
+                log::error!("Failed to add dependency: {}", e);
+                ApiError::from(e)
+            }
+        })?;
+    if !added {
+        return Err(ApiError::Conflict(format!(
+            "Task {} already waits for task {}",
+            info.blocker_id, info.task_id
+        )));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}/tasks/{task_id}/dependencies/{blocker_id}",
+    tag = "tasks",
+    params(
+        ("user_id" = i32, Path, description = "Owner of the tasks"),
+        ("task_id" = i32, Path, description = "Task that waits"),
+        ("blocker_id" = i32, Path, description = "Task it waits for"),
+    ),
+    responses(
+        (status = 204, description = "Dependency removed"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "The task does not wait for the blocker", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn remove_dependency(
+    _auth: AuthenticatedUser,
+    info: web::Path<DependencyInfo>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to stop task with id {} waiting for task with id {} for user with id {}",
+        info.task_id,
+        info.blocker_id,
+        info.user_id
+    );
+
+    let rows_affected = state
+        .dependencies
+        .remove_dependency(info)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to remove dependency: {}", e);
+            ApiError::from(e)
+        })?;
+
+    if rows_affected == 0 {
+        Err(ApiError::NotFound(
+            "Task does not wait for that task".to_owned(),
+        ))
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+// Tag names are unique per user, so a unique violation is always a duplicate name
+fn tag_error(e: sqlx::Error, action: &str) -> ApiError {
+    match e {
+        sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+            ApiError::Conflict("A tag with that name already exists".to_owned())
+        }
+        sqlx::Error::RowNotFound => ApiError::NotFound(format!("No tag found to {}", action)),
+        e => {
+            log::error!("Failed to {} tag: {}", action, e);
+            ApiError::from(e)
         }
     }
 }
+
+#[utoipa::path(
+    post,
+    path = "/users/{user_id}/tags",
+    tag = "tags",
+    params(("user_id" = i32, Path, description = "Owner of the tags")),
+    request_body = TagInput,
+    responses(
+        (status = 200, description = "Tag created", body = Tag),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 409, description = "The user already has a tag with that name", body = ErrorBody),
+        (status = 422, description = "Invalid fields", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_tag(
+    _auth: AuthenticatedUser,
+    user_id: web::Path<i32>,
+    state: web::Data<AppState>,
+    input: ValidatedJson<TagInput>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_id.into_inner();
+    log::info!(
+        "Received request to create tag for user with id {}",
+        user_id
+    );
+
+    let tag = state
+        .tags
+        .create_tag(user_id, &input.name)
+        .await
+        .map_err(|e| tag_error(e, "create"))?;
+
+    log::info!("Successfully created tag with id {}", tag.id);
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/tags",
+    tag = "tags",
+    params(("user_id" = i32, Path, description = "Owner of the tags")),
+    responses(
+        (status = 200, description = "The user's tags in name order", body = Vec<Tag>),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_user_tags(
+    _auth: AuthenticatedUser,
+    user_id: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_id.into_inner();
+    log::info!("Received request to get tags for user with id {}", user_id);
+
+    let tags = state.tags.get_user_tags(user_id).await.map_err(|e| {
+        log::error!("Failed to get tags for user with id {}: {}", user_id, e);
+        ApiError::from(e)
+    })?;
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{user_id}/tags/{tag_id}",
+    tag = "tags",
+    params(("user_id" = i32, Path, description = "Owner of the tags"), ("tag_id" = i32, Path, description = "Tag id")),
+    request_body = TagInput,
+    responses(
+        (status = 200, description = "The renamed tag", body = Tag),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such tag", body = ErrorBody),
+        (status = 409, description = "The user already has a tag with that name", body = ErrorBody),
+        (status = 422, description = "Invalid fields", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn rename_tag(
+    _auth: AuthenticatedUser,
+    info: web::Path<TagInfo>,
+    state: web::Data<AppState>,
+    input: ValidatedJson<TagInput>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to rename tag with id {} for user with id {}",
+        info.tag_id,
+        info.user_id
+    );
+
+    let tag = state
+        .tags
+        .rename_tag(info, &input.name)
+        .await
+        .map_err(|e| tag_error(e, "rename"))?;
+
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}/tags/{tag_id}",
+    tag = "tags",
+    params(("user_id" = i32, Path, description = "Owner of the tags"), ("tag_id" = i32, Path, description = "Tag id")),
+    responses(
+        (status = 204, description = "Tag deleted and removed from every task"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such tag", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_tag(
+    _auth: AuthenticatedUser,
+    info: web::Path<TagInfo>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to delete tag with id {} for user with id {}",
+        info.tag_id,
+        info.user_id
+    );
+
+    let rows_affected = state
+        .tags
+        .delete_tag(info)
+        .await
+        .map_err(|e| tag_error(e, "delete"))?;
+
+    if rows_affected == 0 {
+        Err(ApiError::NotFound("No tag found to delete".to_owned()))
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{user_id}/tasks/{task_id}/tags/{tag_id}",
+    tag = "tags",
+    params(
+        ("user_id" = i32, Path, description = "Owner of the task and tag"),
+        ("task_id" = i32, Path, description = "Task id"),
+        ("tag_id" = i32, Path, description = "Tag id"),
+    ),
+    responses(
+        (status = 204, description = "Tag attached; attaching it again changes nothing"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "No such task or tag", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn attach_tag(
+    _auth: AuthenticatedUser,
+    info: web::Path<TaskTagInfo>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to attach tag with id {} to task with id {} for user with id {}",
+        info.tag_id,
+        info.task_id,
+        info.user_id
+    );
+
+    state.tags.attach_tag(info).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => ApiError::NotFound("No task or tag found to attach".to_owned()),
+        e => {
+            log::error!("Failed to attach tag: {}", e);
+            ApiError::from(e)
+        }
+    })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}/tasks/{task_id}/tags/{tag_id}",
+    tag = "tags",
+    params(
+        ("user_id" = i32, Path, description = "Owner of the task and tag"),
+        ("task_id" = i32, Path, description = "Task id"),
+        ("tag_id" = i32, Path, description = "Tag id"),
+    ),
+    responses(
+        (status = 204, description = "Tag detached"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+        (status = 403, description = "Not the caller's resources", body = ErrorBody),
+        (status = 404, description = "The tag is not attached to the task", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn detach_tag(
+    _auth: AuthenticatedUser,
+    info: web::Path<TaskTagInfo>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let info = info.into_inner();
+    log::info!(
+        "Received request to detach tag with id {} from task with id {} for user with id {}",
+        info.tag_id,
+        info.task_id,
+        info.user_id
+    );
+
+    let rows_affected = state.tags.detach_tag(info).await.map_err(|e| {
+        log::error!("Failed to detach tag: {}", e);
+        ApiError::from(e)
+    })?;
+
+    if rows_affected == 0 {
+        Err(ApiError::NotFound(
+            "Tag is not attached to the task".to_owned(),
+        ))
+    } else {
+        Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/sign_in",
+    tag = "auth",
+    request_body = SignInInput,
+    responses(
+        (status = 200, description = "Signed in", body = UserResponse),
+        (status = 401, description = "Invalid credentials", body = ErrorBody),
+    )
+)]
+pub async fn sign_in_handler(
+    state: web::Data<AppState>,
+    info: web::Json<SignInInput>,
+) -> Result<HttpResponse, ApiError> {
+    let username = &info.username;
+    let password = &info.password;
+
+    let user = state
+        .users
+        .authenticate_user(username, password)
+        .await
+        .map_err(|e| {
+            log::error!("Authentication failed: {}", e);
+            ApiError::from(e)
+        })?
+        .ok_or_else(|| {
+            log::warn!("Invalid credentials for username {}", username);
+            state.metrics.sign_in_failures.inc();
+            ApiError::Unauthorized("Invalid credentials".to_owned())
+        })?;
+
+    let tokens = start_session(&state, user.id).await.map_err(|e| {
+        log::error!("Session creation failed: {:?}", e);
+        e
+    })?;
+
+    let response = UserResponse {
+        user: user.into(),
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/token/refresh",
+    tag = "auth",
+    request_body = RefreshInput,
+    responses(
+        (status = 200, description = "A new access and refresh token pair", body = TokenResponse),
+        (status = 401, description = "Refresh token invalid, expired or already used", body = ErrorBody),
+    )
+)]
+pub async fn refresh_token_handler(
+    state: web::Data<AppState>,
+    input: web::Json<RefreshInput>,
+) -> Result<HttpResponse, ApiError> {
+    let tokens = refresh_session(&state, &input.refresh_token)
+        .await
+        .map_err(|e| {
+            log::warn!("Failed to refresh session: {:?}", e);
+            e
+        })?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sign_out",
+    tag = "auth",
+    responses(
+        (status = 204, description = "Session ended"),
+        (status = 401, description = "Missing, invalid or expired bearer token", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn sign_out_handler(
+    auth: AuthenticatedUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    log::info!("Received request to sign out user with id {}", auth.user_id);
+
+    end_session(&state, auth.user_id, &auth.claims.sid).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,84 @@
+use crate::db::migrations::latest_version;
+use crate::model::models::{BuildInfo, Health, Readiness};
+use crate::model::state::AppState;
+use actix_web::{web, HttpResponse};
+
+// Liveness: answers as long as the process can serve requests, without
+// touching the database
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "operations",
+    responses((status = 200, description = "The process is up", body = Health))
+)]
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(Health {
+        status: "ok".to_owned(),
+    })
+}
+
+// Readiness: the database answers and its schema is the one this build
+// expects. Responds 503 otherwise so the instance is taken out of rotation.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = Readiness),
+        (status = 503, description = "Database unreachable or not migrated", body = Readiness),
+    )
+)]
+pub async fn readyz(state: web::Data<AppState>) -> HttpResponse {
+    let expected_schema_version = latest_version();
+    let not_ready = |reason: &str, schema_version| {
+        HttpResponse::ServiceUnavailable().json(Readiness {
+            status: "unavailable".to_owned(),
+            reason: Some(reason.to_owned()),
+            schema_version,
+            expected_schema_version,
+        })
+    };
+
+    if let Err(e) = state.health.ping().await {
+        log::error!("Readiness check failed to reach the database: {}", e);
+        return not_ready("database unreachable", None);
+    }
+
+    let schema_version = match state.health.schema_version().await {
+        Ok(version) => version,
+        Err(e) => {
+            log::error!("Readiness check failed to read the schema version: {}", e);
+            return not_ready("schema version unknown", None);
+        }
+    };
+
+    if schema_version != expected_schema_version {
+        log::warn!(
+            "Readiness check found schema version {}, expected {}",
+            schema_version,
+            expected_schema_version
+        );
+        return not_ready("migrations not current", Some(schema_version));
+    }
+
+    HttpResponse::Ok().json(Readiness {
+        status: "ready".to_owned(),
+        reason: None,
+        schema_version: Some(schema_version),
+        expected_schema_version,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "operations",
+    responses((status = 200, description = "Build information", body = BuildInfo))
+)]
+pub async fn version() -> HttpResponse {
+    HttpResponse::Ok().json(BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        git_sha: env!("GIT_SHA").to_owned(),
+        schema_version: latest_version(),
+    })
+}
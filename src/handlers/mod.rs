@@ -1,4 +1,9 @@
+pub mod extractors;
+#[allow(clippy::module_inception)]
 pub mod handlers;
+pub mod health;
+pub mod metrics;
+pub mod openapi;
 
 #[cfg(test)]
 pub mod test;
@@ -0,0 +1,193 @@
+use crate::error::error::ErrorBody;
+use crate::handlers::{handlers, health, metrics};
+use crate::model::models::{
+    Board, BoardColumn, BuildInfo, ChecklistItem, ChecklistItemInput, ChecklistItemPatch, Health,
+    Occurrences, Progress, PublicUser, Readiness, RefreshInput, Role, RoleInput, SignInInput,
+    SortOrder, Tag, TagInput, Task, TaskInput, TaskMove, TaskPage, TaskPatch, TaskPriority,
+    TaskSort, TaskStatus, TaskUpdate, TokenResponse, UserInput, UserPage, UserResponse,
+};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+// The OpenAPI document served at `/openapi.json`. Every route registered in
+// `main.rs` must be listed in `paths`, which a test enforces.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Task management API"),
+    paths(
+        handlers::create_user,
+        handlers::get_users,
+        handlers::update_user_role,
+        handlers::create_task,
+        handlers::get_user_tasks,
+        handlers::get_user_task,
+        handlers::update_user_task,
+        handlers::patch_user_task,
+        handlers::delete_user_task,
+        handlers::move_user_task,
+        handlers::create_checklist_item,
+        handlers::update_checklist_item,
+        handlers::delete_checklist_item,
+        handlers::add_dependency,
+        handlers::remove_dependency,
+        handlers::get_task_occurrences,
+        handlers::get_user_board,
+        handlers::create_tag,
+        handlers::get_user_tags,
+        handlers::rename_tag,
+        handlers::delete_tag,
+        handlers::attach_tag,
+        handlers::detach_tag,
+        handlers::sign_in_handler,
+        handlers::sign_out_handler,
+        handlers::refresh_token_handler,
+        health::healthz,
+        health::readyz,
+        health::version,
+        metrics::metrics,
+    ),
+    components(schemas(
+        Board,
+        BoardColumn,
+        BuildInfo,
+        ChecklistItem,
+        ChecklistItemInput,
+        ChecklistItemPatch,
+        ErrorBody,
+        Health,
+        Occurrences,
+        Progress,
+        PublicUser,
+        Readiness,
+        RefreshInput,
+        Role,
+        RoleInput,
+        SignInInput,
+        SortOrder,
+        Tag,
+        TagInput,
+        Task,
+        TaskInput,
+        TaskMove,
+        TaskPage,
+        TaskPatch,
+        TaskPriority,
+        TaskSort,
+        TaskStatus,
+        TaskUpdate,
+        TokenResponse,
+        UserInput,
+        UserPage,
+        UserResponse,
+    )),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "users", description = "Registration and user administration"),
+        (name = "tasks", description = "Tasks of a user"),
+        (name = "tags", description = "Labels a user attaches to their tasks"),
+        (name = "auth", description = "Sessions and tokens"),
+        (name = "operations", description = "Probes and monitoring"),
+    )
+)]
+pub struct ApiDoc;
+
+// Access tokens from sign up, sign in or refresh, sent as
+// `Authorization: Bearer <token>`
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utoipa::openapi::path::HttpMethod;
+
+    // Routes that are deliberately left out of the document
+    const UNDOCUMENTED: &[&str] = &["/"];
+
+    // Every `.route(path, web::method()...)` registered in `main.rs`
+    fn main_routes() -> Vec<(String, String)> {
+        include_str!("../main.rs")
+            .split(".route(")
+            .skip(1)
+            .map(|route| {
+                let path = route.split('"').nth(1).expect("route path literal");
+                let method = route
+                    .split("web::")
+                    .nth(1)
+                    .and_then(|rest| rest.split('(').next())
+                    .expect("route method");
+                (path.to_owned(), method.to_owned())
+            })
+            .collect()
+    }
+
+    fn http_method(method: &str) -> HttpMethod {
+        match method {
+            "get" => HttpMethod::Get,
+            "post" => HttpMethod::Post,
+            "put" => HttpMethod::Put,
+            "patch" => HttpMethod::Patch,
+            "delete" => HttpMethod::Delete,
+            other => panic!("unexpected method {}", other),
+        }
+    }
+
+    #[test]
+    fn test_every_route_is_documented() {
+        let spec = ApiDoc::openapi();
+        let routes = main_routes();
+        assert!(routes.len() > 10, "found only {:?}", routes);
+
+        for (path, method) in routes {
+            if UNDOCUMENTED.contains(&path.as_str()) {
+                continue;
+            }
+            let item = spec
+                .paths
+                .paths
+                .get(&path)
+                .unwrap_or_else(|| panic!("{} is missing from the OpenAPI document", path));
+            let operation = match http_method(&method) {
+                HttpMethod::Get => &item.get,
+                HttpMethod::Post => &item.post,
+                HttpMethod::Put => &item.put,
+                HttpMethod::Patch => &item.patch,
+                HttpMethod::Delete => &item.delete,
+                _ => unreachable!(),
+            };
+            assert!(
+                operation.is_some(),
+                "{} {} is missing from the OpenAPI document",
+                method.to_uppercase(),
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_schemas_are_resolvable() {
+        let json = ApiDoc::openapi().to_json().unwrap();
+        let spec: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+
+        for reference in json.split("\"$ref\":\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "{} has no schema", name);
+        }
+        assert!(spec["components"]["securitySchemes"]["bearer_auth"].is_object());
+    }
+}
@@ -1,181 +1,381 @@
+use crate::config::settings::{AuthSettings, Settings};
+use crate::db::migrations::latest_version;
+use crate::db::repository::{HealthRepository, TaskRepository};
+use crate::error::error::{json_error_handler, ErrorBody};
 use crate::handlers::handlers::{
-    create_task, create_user, get_user_task, get_user_tasks, get_users, update_user_task,
+    add_dependency, attach_tag, create_checklist_item, create_tag, create_task, create_user,
+    delete_checklist_item, delete_tag, delete_user_task, detach_tag, get_task_occurrences,
+    get_user_board, get_user_tags, get_user_task, get_user_tasks, get_users, move_user_task,
+    patch_user_task, refresh_token_handler, remove_dependency, rename_tag, sign_in_handler,
+    sign_out_handler, update_checklist_item, update_user_task,
+};
+use crate::handlers::health::{healthz, readyz, version};
+use crate::handlers::metrics::metrics;
+use crate::jwt::session::start_session;
+use crate::logging::middleware::{RequestIdentity, REQUEST_ID_HEADER};
+use crate::metrics::metrics::PoolStatus;
+use crate::metrics::middleware::RequestMetrics;
+use crate::model::models::{
+    Board, BuildInfo, ChecklistItem, ChecklistItemInfo, ChecklistItemInput, ChecklistItemPatch,
+    Health, Info, Occurrences, Placement, Progress, Readiness, RefreshInput, Role, SignInInput,
+    SortOrder, Tag, TagInput, Task, TaskCursor, TaskInput, TaskMove, TaskPage, TaskPatch,
+    TaskPriority, TaskQuery, TaskSort, TaskStatus, TaskTagInfo, TaskUpdate, TaskWrite,
+    TokenResponse, User, UserInput, UserPage, UserResponse, MAX_SUBTASK_DEPTH,
 };
-use crate::model::models::{Task, TaskInput, TaskUpdate, User, UserInput};
 use crate::model::state::AppState;
-use actix_web::{http::StatusCode, test, web, App};
-use dotenv::dotenv;
-use sqlx::{Pool, Postgres};
-use std::env;
+use actix_web::{http::header, http::StatusCode, test, web, App};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
-// Create User Test
-#[actix_rt::test]
-async fn test_create_user() {
-    dotenv().ok();
+// Every test gets its own empty in-memory store, so the suite needs no
+// database and leaves nothing behind
+fn test_state() -> AppState {
+    AppState::in_memory(Settings {
+        auth: AuthSettings {
+            secret_key: "testsecret".to_owned(),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+// Usernames and emails are unique, so every test registers its own user
+fn unique_suffix() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("{}{}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+async fn create_test_user(state: &AppState) -> (User, String) {
+    let (user, tokens) = create_test_session(state).await;
+    (user, tokens.token)
+}
+
+async fn create_test_session(state: &AppState) -> (User, TokenResponse) {
+    let suffix = unique_suffix();
+    let user = state
+        .users
+        .create_user(&UserInput {
+            username: format!("user{}", suffix),
+            password: "securepassword123".into(),
+            email: format!("user{}@example.com", suffix),
+        })
+        .await
+        .unwrap();
+    let tokens = start_session(state, user.id).await.unwrap();
+    (user, tokens)
+}
+
+async fn create_test_admin(state: &AppState) -> (User, String) {
+    let (user, _) = create_test_session(state).await;
+    state
+        .users
+        .set_user_role(user.id, Role::Admin)
+        .await
+        .unwrap();
+    let tokens = start_session(state, user.id).await.unwrap();
+    (user, tokens.token)
+}
+
+fn written(write: TaskWrite) -> Task {
+    match write {
+        TaskWrite::Written(task) => task,
+        other => panic!("expected the task to be written, got {:?}", other),
+    }
+}
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+async fn create_test_task(state: &AppState, user_id: i32) -> Task {
+    written(
+        state
+            .tasks
+            .create_task(
+                &TaskInput {
+                    title: "Test task".into(),
+                    description: "Test description".into(),
+                    due_date: None,
+                    status: TaskStatus::Todo,
+                    priority: TaskPriority::default(),
+                    parent_id: None,
+                    recurrence: None,
+                },
+                user_id,
+            )
+            .await
+            .unwrap(),
+    )
+}
 
-    let pool = Pool::<Postgres>::connect(&database_url).await.unwrap();
+fn bearer(token: &str) -> (header::HeaderName, String) {
+    (header::AUTHORIZATION, format!("Bearer {}", token))
+}
 
-    let data = AppState { pool };
+// Create User Test
+#[actix_rt::test]
+async fn test_create_user() {
+    let state = test_state();
 
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
-            .app_data(web::Data::new(data))
+            .app_data(web::Data::new(state))
             .route("/users", web::post().to(create_user)),
     )
     .await;
 
+    let username = format!("Huzaifatest{}", unique_suffix());
     let req = test::TestRequest::post()
         .uri("/users")
         .set_json(&UserInput {
-            username: "Huzaifatest".into(),
+            username: username.clone(),
             password: "securepassword123".into(),
-            email: "huzadsfsdfsdifa@example.com".into(),
+            email: format!("{}@example.com", username),
         })
         .to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), StatusCode::OK);
 
-    let user: User = test::read_body_json(resp).await;
-    assert_eq!(user.username, "Huzaifatest");
+    let response: UserResponse = test::read_body_json(resp).await;
+    assert_eq!(response.user.username, username);
+    assert_eq!(response.user.role, Role::Member);
+    assert!(!response.token.is_empty());
+    assert!(!response.refresh_token.is_empty());
 }
 
 // Get Users Test
 #[actix_rt::test]
 async fn test_get_users() {
-    dotenv().ok();
+    let state = test_state();
+    let (_, admin_token) = create_test_admin(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/get_users", web::get().to(get_users)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/get_users")
+        .insert_header(bearer(&admin_token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let page: UserPage = test::read_body_json(resp).await;
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    assert!(!page.users.is_empty());
+    assert_eq!(page.total, None);
+}
 
-    let pool = Pool::<Postgres>::connect(&database_url).await.unwrap();
+fn assert_no_password_hash(body: &[u8]) {
+    let body = std::str::from_utf8(body).unwrap();
+    assert!(
+        !body.contains("password"),
+        "Body leaks a password field: {}",
+        body
+    );
+    assert!(!body.contains("$2b$"), "Body leaks a bcrypt hash: {}", body);
+}
 
-    let data = AppState { pool };
+#[actix_rt::test]
+async fn test_user_endpoints_never_return_password_hash() {
+    let state = test_state();
+    let (_, admin_token) = create_test_admin(&state).await;
 
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
-            .app_data(web::Data::new(data))
-            .route("/get_users", web::get().to(get_users)),
+            .app_data(web::Data::new(state))
+            .route("/users", web::post().to(create_user))
+            .route("/get_users", web::get().to(get_users))
+            .route("/sign_in", web::post().to(sign_in_handler)),
     )
     .await;
 
-    let req = test::TestRequest::get().uri("/get_users").to_request();
-    let resp = test::call_service(&mut app, req).await;
+    let username = format!("hashcheck{}", unique_suffix());
+    let req = test::TestRequest::post()
+        .uri("/users")
+        .set_json(&UserInput {
+            username: username.clone(),
+            password: "securepassword123".into(),
+            email: format!("{}@example.com", username),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_no_password_hash(&test::read_body(resp).await);
+
+    let req = test::TestRequest::post()
+        .uri("/sign_in")
+        .set_json(&SignInInput {
+            username,
+            password: "securepassword123".into(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_no_password_hash(&test::read_body(resp).await);
 
+    let req = test::TestRequest::get()
+        .uri("/get_users")
+        .insert_header(bearer(&admin_token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::OK);
+    assert_no_password_hash(&test::read_body(resp).await);
+}
 
-    let users: Vec<User> = test::read_body_json(resp).await;
+#[actix_rt::test]
+async fn test_get_users_requires_admin() {
+    let state = test_state();
+    let (_, member_token) = create_test_user(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/get_users", web::get().to(get_users)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/get_users").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
 
-    assert!(!users.is_empty());
+    let req = test::TestRequest::get()
+        .uri("/get_users")
+        .insert_header(bearer(&member_token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
 }
 
-// Create Task Test
 #[actix_rt::test]
-async fn test_create_task() {
-    dotenv().ok();
+async fn test_admin_can_access_other_users_tasks() {
+    let state = test_state();
+    let (owner, _) = create_test_user(&state).await;
+    let (_, admin_token) = create_test_admin(&state).await;
+    let task = create_test_task(&state, owner.id).await;
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::get().to(get_user_task),
+    ))
+    .await;
 
-    let pool = Pool::<Postgres>::connect(&database_url).await.unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", owner.id, task.id))
+        .insert_header(bearer(&admin_token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
 
-    let data = AppState { pool };
+// Create Task Test
+#[actix_rt::test]
+async fn test_create_task() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
 
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
-            .app_data(web::Data::new(data))
+            .app_data(web::Data::new(state))
             .route("/users/{user_id}/tasks", web::post().to(create_task)),
     )
     .await;
 
     let req = test::TestRequest::post()
-        .uri("/users/1/tasks")
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
         .set_json(&TaskInput {
             title: "Test task".into(),
             description: "Test description".into(),
             due_date: None,
-            status: "todo".into(),
+            status: TaskStatus::Todo,
+            priority: TaskPriority::default(),
+            parent_id: None,
+            recurrence: None,
         })
         .to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), StatusCode::OK);
 
     let task: Task = test::read_body_json(resp).await;
     assert_eq!(task.title, "Test task");
     assert_eq!(task.description, "Test description");
-    assert_eq!(task.status, "todo");
+    assert_eq!(task.status, TaskStatus::Todo);
+    assert_eq!(task.completed_at, None);
+    assert_eq!(task.user_id, user.id);
 }
 
 #[actix_rt::test]
 async fn test_get_user_tasks() {
-    dotenv().ok();
-
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    create_test_task(&state, user.id).await;
 
-    let pool = Pool::<Postgres>::connect(&database_url).await.unwrap();
-
-    let data = AppState { pool };
-
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
-            .app_data(web::Data::new(data))
+            .app_data(web::Data::new(state))
             .route("/users/{user_id}/tasks", web::get().to(get_user_tasks)),
     )
     .await;
 
-    let req = test::TestRequest::get().uri("/users/2/tasks").to_request();
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), StatusCode::OK);
 
-    let tasks: Vec<Task> = test::read_body_json(resp).await;
-    assert!(!tasks.is_empty(), "Should return at least one task");
+    let page: TaskPage = test::read_body_json(resp).await;
+    assert!(!page.tasks.is_empty(), "Should return at least one task");
+    assert_eq!(page.next_cursor, None);
 }
 
 #[actix_rt::test]
 async fn test_get_user_task() {
-    dotenv().ok();
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let created = create_test_task(&state, user.id).await;
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-
-    let pool = Pool::<Postgres>::connect(&database_url).await.unwrap();
-
-    let data = AppState { pool };
-
-    let mut app = test::init_service(App::new().app_data(web::Data::new(data)).route(
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
         "/users/{user_id}/tasks/{task_id}",
         web::get().to(get_user_task),
     ))
     .await;
 
     let req = test::TestRequest::get()
-        .uri("/users/2/tasks/2")
+        .uri(&format!("/users/{}/tasks/{}", user.id, created.id))
+        .insert_header(bearer(&token))
         .to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), StatusCode::OK);
 
     let task: Task = test::read_body_json(resp).await;
-    assert_eq!(task.user_id, 2, "Should return the task for user 2");
-    assert_eq!(task.id, 1, "Should return the task with id 1");
+    assert_eq!(task.user_id, user.id, "Should return the task for the user");
+    assert_eq!(task.id, created.id, "Should return the requested task");
 }
 
 #[actix_rt::test]
 async fn test_update_user_task() {
-    dotenv().ok();
-
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let created = create_test_task(&state, user.id).await;
 
-    let pool = Pool::<Postgres>::connect(&database_url).await.unwrap();
-
-    let data = AppState { pool };
-
-    let mut app = test::init_service(App::new().app_data(web::Data::new(data)).route(
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
         "/users/{user_id}/tasks/{task_id}",
         web::put().to(update_user_task),
     ))
@@ -185,50 +385,2340 @@ async fn test_update_user_task() {
         title: "Updated Title".into(),
         description: "Updated Description".into(),
         due_date: None,
-        status: "completed".into(),
+        status: TaskStatus::Done,
+        priority: TaskPriority::default(),
+        recurrence: None,
     };
 
     let req = test::TestRequest::put()
-        .uri("/users/2/tasks/2")
+        .uri(&format!("/users/{}/tasks/{}", user.id, created.id))
+        .insert_header(bearer(&token))
         .set_json(&task_update)
         .to_request();
 
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), StatusCode::OK);
 
     let task: Task = test::read_body_json(resp).await;
-    assert_eq!(task.user_id, 2, "Should return the task for user 2");
-    assert_eq!(task.id, 1, "Should return the task with id 1");
+    assert_eq!(task.user_id, user.id, "Should return the task for the user");
+    assert_eq!(task.id, created.id, "Should return the updated task");
     assert_eq!(task.title, "Updated Title", "Title should be updated");
     assert_eq!(
         task.description, "Updated Description",
         "Description should be updated"
     );
-    assert_eq!(task.status, "completed", "Status should be updated");
+    assert_eq!(task.status, TaskStatus::Done, "Status should be updated");
+    assert!(task.completed_at.is_some(), "Completion should be recorded");
+}
+
+#[actix_rt::test]
+async fn test_delete_user_task() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let created = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::delete().to(delete_user_task),
+    ))
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/{}/tasks/{}", user.id, created.id))
+        .insert_header(bearer(&token))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+}
+
+#[actix_rt::test]
+async fn test_tasks_require_bearer_token() {
+    let state = test_state();
+    let (user, _) = create_test_user(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tasks", web::get().to(get_user_tasks)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer("not-a-jwt"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn test_tasks_of_other_user_are_forbidden() {
+    let state = test_state();
+    let (owner, _) = create_test_user(&state).await;
+    let (_, intruder_token) = create_test_user(&state).await;
+    let task = create_test_task(&state, owner.id).await;
+
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::delete().to(delete_user_task),
+    ))
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/{}/tasks/{}", owner.id, task.id))
+        .insert_header(bearer(&intruder_token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_rt::test]
+async fn test_refresh_token_rotation_and_reuse_detection() {
+    let state = test_state();
+    let (_, tokens) = create_test_session(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/token/refresh", web::post().to(refresh_token_handler)),
+    )
+    .await;
+
+    let refresh = |refresh_token: &str| {
+        test::TestRequest::post()
+            .uri("/token/refresh")
+            .set_json(&RefreshInput {
+                refresh_token: refresh_token.to_owned(),
+            })
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, refresh(&tokens.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let rotated: TokenResponse = test::read_body_json(resp).await;
+    assert_ne!(rotated.refresh_token, tokens.refresh_token);
+
+    // Replaying the rotated-out token kills the whole family...
+    let resp = test::call_service(&app, refresh(&tokens.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // ...including the token that replaced it
+    let resp = test::call_service(&app, refresh(&rotated.refresh_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app, refresh("unknown")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn test_sign_out_revokes_refresh_token() {
+    let state = test_state();
+    let (_, tokens) = create_test_session(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/sign_out", web::post().to(sign_out_handler))
+            .route("/token/refresh", web::post().to(refresh_token_handler)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/sign_out")
+        .insert_header(bearer(&tokens.token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let req = test::TestRequest::post()
+        .uri("/token/refresh")
+        .set_json(&RefreshInput {
+            refresh_token: tokens.refresh_token,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn test_reset_password_ends_existing_sessions() {
+    let state = test_state();
+    let (user, tokens) = create_test_session(&state).await;
+    state
+        .users
+        .reset_user_password(user.id, "resetpassword456")
+        .await
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/sign_in", web::post().to(sign_in_handler))
+            .route("/token/refresh", web::post().to(refresh_token_handler)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/token/refresh")
+        .set_json(&RefreshInput {
+            refresh_token: tokens.refresh_token,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    for (password, status) in [
+        ("securepassword123", StatusCode::UNAUTHORIZED),
+        ("resetpassword456", StatusCode::OK),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/sign_in")
+            .set_json(&SignInInput {
+                username: user.username.clone(),
+                password: password.into(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status);
+    }
+}
+
+#[actix_rt::test]
+async fn test_missing_task_returns_not_found_envelope() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::get().to(get_user_task),
+    ))
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", user.id, i32::MAX))
+        .insert_header(bearer(&token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.code, "not_found");
+}
+
+#[actix_rt::test]
+async fn test_duplicate_username_returns_conflict() {
+    let state = test_state();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users", web::post().to(create_user)),
+    )
+    .await;
+
+    let username = format!("duplicate{}", unique_suffix());
+    for (attempt, expected) in [StatusCode::OK, StatusCode::CONFLICT]
+        .into_iter()
+        .enumerate()
+    {
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(&UserInput {
+                username: username.clone(),
+                password: "securepassword123".into(),
+                email: format!("{}{}@example.com", username, attempt),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+    }
+}
+
+#[actix_rt::test]
+async fn test_sign_in_with_bad_credentials_returns_unauthorized_envelope() {
+    let state = test_state();
+    let (user, _) = create_test_user(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/sign_in", web::post().to(sign_in_handler)),
+    )
+    .await;
+
+    for username in [user.username, format!("unknown{}", unique_suffix())] {
+        let req = test::TestRequest::post()
+            .uri("/sign_in")
+            .set_json(&SignInInput {
+                username,
+                password: "wrongpassword".into(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert_eq!(body.code, "unauthorized");
+    }
+}
+
+// Returns the fields reported as invalid in a 422 response
+async fn violated_fields(resp: actix_web::dev::ServiceResponse) -> Vec<String> {
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.code, "validation_failed");
+    body.details
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|violation| violation["field"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[actix_rt::test]
+async fn test_create_user_validation() {
+    let state = test_state();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users", web::post().to(create_user)),
+    )
+    .await;
+
+    let valid = || UserInput {
+        username: format!("valid{}", unique_suffix()),
+        password: "securepassword123".into(),
+        email: "valid@example.com".into(),
+    };
+    let cases = [
+        (
+            UserInput {
+                username: "".into(),
+                ..valid()
+            },
+            vec!["username"],
+        ),
+        (
+            UserInput {
+                password: "short".into(),
+                ..valid()
+            },
+            vec!["password"],
+        ),
+        (
+            UserInput {
+                email: "not-an-email".into(),
+                ..valid()
+            },
+            vec!["email"],
+        ),
+        (
+            UserInput {
+                username: "".into(),
+                password: "".into(),
+                email: "".into(),
+            },
+            vec!["email", "password", "username"],
+        ),
+    ];
+
+    for (input, expected) in cases {
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(&input)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(violated_fields(resp).await, expected);
+    }
+}
+
+#[actix_rt::test]
+async fn test_create_task_validation() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tasks", web::post().to(create_task)),
+    )
+    .await;
+
+    let valid = || TaskInput {
+        title: "Test task".into(),
+        description: "Test description".into(),
+        due_date: None,
+        status: TaskStatus::Todo,
+        priority: TaskPriority::default(),
+        parent_id: None,
+        recurrence: None,
+    };
+    let cases = [
+        (
+            TaskInput {
+                title: "".into(),
+                ..valid()
+            },
+            vec!["title"],
+        ),
+        (
+            TaskInput {
+                description: "".into(),
+                ..valid()
+            },
+            vec!["description"],
+        ),
+    ];
+
+    for (input, expected) in cases {
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{}/tasks", user.id))
+            .insert_header(bearer(&token))
+            .set_json(&input)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(violated_fields(resp).await, expected);
+    }
+}
+
+#[actix_rt::test]
+async fn test_update_user_task_validation() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let task = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::put().to(update_user_task),
+    ))
+    .await;
+
+    let valid = || TaskUpdate {
+        title: "Updated Title".into(),
+        description: "Updated Description".into(),
+        due_date: None,
+        status: TaskStatus::Todo,
+        priority: TaskPriority::default(),
+        recurrence: None,
+    };
+    let cases = [
+        (
+            TaskUpdate {
+                title: "".into(),
+                ..valid()
+            },
+            vec!["title"],
+        ),
+        (
+            TaskUpdate {
+                description: "".into(),
+                ..valid()
+            },
+            vec!["description"],
+        ),
+    ];
+
+    for (input, expected) in cases {
+        let req = test::TestRequest::put()
+            .uri(&format!("/users/{}/tasks/{}", user.id, task.id))
+            .insert_header(bearer(&token))
+            .set_json(&input)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(violated_fields(resp).await, expected);
+    }
 }
 
-// #[actix_rt::test]
-// async fn test_delete_user_task() {
-//     dotenv().ok();
+#[actix_rt::test]
+async fn test_patch_user_task_updates_only_supplied_fields() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let due_date = chrono::NaiveDate::from_ymd_opt(2030, 1, 1)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    let created = written(
+        state
+            .tasks
+            .create_task(
+                &TaskInput {
+                    title: "Test task".into(),
+                    description: "Test description".into(),
+                    due_date: Some(due_date),
+                    status: TaskStatus::Todo,
+                    priority: TaskPriority::default(),
+                    parent_id: None,
+                    recurrence: None,
+                },
+                user.id,
+            )
+            .await
+            .unwrap(),
+    );
 
-//     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::patch().to(patch_user_task),
+    ))
+    .await;
+    let uri = format!("/users/{}/tasks/{}", user.id, created.id);
 
-//     let pool = Pool::<Postgres>::connect(&database_url).await.unwrap();
+    // Absent fields, including due_date, are left untouched
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(bearer(&token))
+        .set_json(&TaskPatch {
+            status: Some(TaskStatus::InProgress),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let task: Task = test::read_body_json(resp).await;
+    assert_eq!(task.status, TaskStatus::InProgress);
+    assert_eq!(task.title, "Test task");
+    assert_eq!(task.description, "Test description");
+    assert_eq!(task.due_date, Some(due_date));
 
-//     let data = AppState { pool };
+    // An explicit null clears due_date
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(bearer(&token))
+        .set_json(serde_json::json!({ "due_date": null }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let task: Task = test::read_body_json(resp).await;
+    assert_eq!(task.due_date, None);
+    assert_eq!(task.status, TaskStatus::InProgress);
 
-//     let mut app = test::init_service(App::new().app_data(web::Data::new(data)).route(
-//         "/users/{user_id}/tasks/{task_id}",
-//         web::delete().to(delete_user_task),
-//     ))
-//     .await;
+    // An empty patch returns the task unchanged
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(bearer(&token))
+        .set_json(serde_json::json!({}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let task: Task = test::read_body_json(resp).await;
+    assert_eq!(task.id, created.id);
 
-//     let req = test::TestRequest::delete()
-//         .uri("/users/2/tasks/1")
-//         .to_request();
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .insert_header(bearer(&token))
+        .set_json(&TaskPatch {
+            title: Some("".into()),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(violated_fields(resp).await, vec!["title"]);
+}
 
-//     let resp = test::call_service(&mut app, req).await;
+#[actix_rt::test]
+async fn test_patch_missing_task_returns_not_found() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
 
-//     assert_eq!(resp.status(), StatusCode::NO_CONTENT);
-// }
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::patch().to(patch_user_task),
+    ))
+    .await;
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/users/{}/tasks/{}", user.id, i32::MAX))
+        .insert_header(bearer(&token))
+        .set_json(&TaskPatch {
+            title: Some("Nothing to patch".into()),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+// Stores tasks in memory, but lets another request change the status of the
+// task right before each status-guarded write, and nest the parent right
+// before each write giving a task a parent
+struct RacingTasks {
+    inner: Arc<dyn TaskRepository>,
+}
+
+impl RacingTasks {
+    async fn race(&self, info: &Info, current_status: TaskStatus) {
+        let status = match current_status {
+            TaskStatus::Blocked => TaskStatus::Todo,
+            _ => TaskStatus::Blocked,
+        };
+        let concurrent = TaskPatch {
+            status: Some(status),
+            ..Default::default()
+        };
+        self.inner
+            .patch_user_task(info.clone(), concurrent, Some(current_status))
+            .await
+            .unwrap();
+    }
+
+    // Puts the parent under the task, or under as many levels of tasks as
+    // subtasks nest for a new task
+    async fn nest(&self, user_id: i32, task_id: Option<i32>, parent_id: i32) {
+        let above = match task_id {
+            Some(task_id) => task_id,
+            None => {
+                let mut above = None;
+                for _ in 0..MAX_SUBTASK_DEPTH {
+                    let input = TaskInput {
+                        title: "Racing task".into(),
+                        description: "Test description".into(),
+                        due_date: None,
+                        status: TaskStatus::Todo,
+                        priority: TaskPriority::default(),
+                        parent_id: above,
+                        recurrence: None,
+                    };
+                    let task = written(self.inner.create_task(&input, user_id).await.unwrap());
+                    above = Some(task.id);
+                }
+                above.unwrap()
+            }
+        };
+        let concurrent = TaskPatch {
+            parent_id: Some(Some(above)),
+            ..Default::default()
+        };
+        let info = Info {
+            user_id,
+            task_id: parent_id,
+        };
+        written(
+            self.inner
+                .patch_user_task(info, concurrent, None)
+                .await
+                .unwrap(),
+        );
+    }
+}
+
+#[async_trait]
+impl TaskRepository for RacingTasks {
+    async fn create_task(
+        &self,
+        new_task: &TaskInput,
+        user_id: i32,
+    ) -> Result<TaskWrite, sqlx::Error> {
+        if let Some(parent_id) = new_task.parent_id {
+            self.nest(user_id, None, parent_id).await;
+        }
+        self.inner.create_task(new_task, user_id).await
+    }
+
+    async fn get_user_tasks(
+        &self,
+        user_id: i32,
+        filter: &TaskQuery,
+        after: Option<&TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<Task>, sqlx::Error> {
+        self.inner
+            .get_user_tasks(user_id, filter, after, limit)
+            .await
+    }
+
+    async fn get_user_task(&self, user_id: i32, task_id: i32) -> Result<Task, sqlx::Error> {
+        self.inner.get_user_task(user_id, task_id).await
+    }
+
+    async fn update_user_task(
+        &self,
+        info: Info,
+        task_update: TaskUpdate,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, sqlx::Error> {
+        self.race(&info, current_status).await;
+        self.inner
+            .update_user_task(info, task_update, current_status)
+            .await
+    }
+
+    async fn patch_user_task(
+        &self,
+        info: Info,
+        patch: TaskPatch,
+        current_status: Option<TaskStatus>,
+    ) -> Result<TaskWrite, sqlx::Error> {
+        if let Some(current_status) = current_status {
+            self.race(&info, current_status).await;
+        }
+        if let Some(Some(parent_id)) = patch.parent_id {
+            self.nest(info.user_id, Some(info.task_id), parent_id).await;
+        }
+        self.inner
+            .patch_user_task(info, patch, current_status)
+            .await
+    }
+
+    async fn delete_user_task(&self, info: Info) -> Result<u64, sqlx::Error> {
+        self.inner.delete_user_task(info).await
+    }
+
+    async fn move_user_task(
+        &self,
+        info: Info,
+        status: TaskStatus,
+        placement: Placement,
+        current_status: TaskStatus,
+    ) -> Result<TaskWrite, sqlx::Error> {
+        self.race(&info, current_status).await;
+        self.inner
+            .move_user_task(info, status, placement, current_status)
+            .await
+    }
+
+    async fn get_user_board(&self, user_id: i32) -> Result<Vec<Task>, sqlx::Error> {
+        self.inner.get_user_board(user_id).await
+    }
+}
+
+#[actix_rt::test]
+async fn test_lost_status_race_returns_conflict() {
+    let state = test_state();
+    let state = AppState {
+        tasks: Arc::new(RacingTasks {
+            inner: state.tasks.clone(),
+        }),
+        ..state
+    };
+    let (user, token) = create_test_user(&state).await;
+    let task = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::put().to(update_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::patch().to(patch_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/move",
+                web::post().to(move_user_task),
+            ),
+    )
+    .await;
+
+    let uri = format!("/users/{}/tasks/{}", user.id, task.id);
+    let requests = [
+        test::TestRequest::put().uri(&uri).set_json(TaskUpdate {
+            title: "Updated Title".into(),
+            description: "Updated Description".into(),
+            due_date: None,
+            status: TaskStatus::InProgress,
+            priority: TaskPriority::default(),
+            recurrence: None,
+        }),
+        test::TestRequest::patch().uri(&uri).set_json(TaskPatch {
+            status: Some(TaskStatus::InProgress),
+            ..Default::default()
+        }),
+        test::TestRequest::post()
+            .uri(&format!("{}/move", uri))
+            .set_json(TaskMove {
+                status: Some(TaskStatus::InProgress),
+                ..Default::default()
+            }),
+    ];
+    for req in requests {
+        let resp = test::call_service(&app, req.insert_header(bearer(&token)).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert!(body.message.contains("another request"), "{}", body.message);
+    }
+}
+
+#[actix_rt::test]
+async fn test_lost_parent_race_returns_conflict() {
+    let state = test_state();
+    let state = AppState {
+        tasks: Arc::new(RacingTasks {
+            inner: state.tasks.clone(),
+        }),
+        ..state
+    };
+    let (user, token) = create_test_user(&state).await;
+    let parent = create_test_task(&state, user.id).await;
+    let task = create_test_task(&state, user.id).await;
+    let other = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/users/{user_id}/tasks", web::post().to(create_task))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::patch().to(patch_user_task),
+            ),
+    )
+    .await;
+
+    // The parent is nested as deep as subtasks go right before the write
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .set_json(TaskInput {
+            title: "Subtask".into(),
+            description: "Test description".into(),
+            due_date: None,
+            status: TaskStatus::Todo,
+            priority: TaskPriority::default(),
+            parent_id: Some(parent.id),
+            recurrence: None,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert!(body.message.contains("levels deep"), "{}", body.message);
+
+    // The new parent becomes a subtask of the task right before the write
+    let req = test::TestRequest::patch()
+        .uri(&format!("/users/{}/tasks/{}", user.id, task.id))
+        .insert_header(bearer(&token))
+        .set_json(TaskPatch {
+            parent_id: Some(Some(other.id)),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert!(body.message.contains("its own subtask"), "{}", body.message);
+    let task = state.tasks.get_user_task(user.id, task.id).await.unwrap();
+    assert_eq!(task.parent_id, None);
+}
+
+#[actix_rt::test]
+async fn test_status_workflow_transitions() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let created = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}",
+        web::patch().to(patch_user_task),
+    ))
+    .await;
+    let uri = format!("/users/{}/tasks/{}", user.id, created.id);
+    let move_to = |status: TaskStatus| {
+        test::TestRequest::patch()
+            .uri(&uri)
+            .insert_header(bearer(&token))
+            .set_json(&TaskPatch {
+                status: Some(status),
+                ..Default::default()
+            })
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, move_to(TaskStatus::Blocked)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // A blocked task has to be unblocked before it can be completed
+    let resp = test::call_service(&app, move_to(TaskStatus::Done)).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.message, "Cannot move task from blocked to done");
+
+    let resp = test::call_service(&app, move_to(TaskStatus::InProgress)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, move_to(TaskStatus::Done)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let done: Task = test::read_body_json(resp).await;
+    assert!(done.completed_at.is_some());
+
+    // Staying in done keeps the original completion time
+    let resp = test::call_service(&app, move_to(TaskStatus::Done)).await;
+    let still_done: Task = test::read_body_json(resp).await;
+    assert_eq!(still_done.completed_at, done.completed_at);
+
+    let resp = test::call_service(&app, move_to(TaskStatus::Cancelled)).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    // Reopening clears the completion time
+    let resp = test::call_service(&app, move_to(TaskStatus::Todo)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let reopened: Task = test::read_body_json(resp).await;
+    assert_eq!(reopened.completed_at, None);
+}
+
+#[actix_rt::test]
+async fn test_unknown_status_is_rejected() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+            .route("/users/{user_id}/tasks", web::post().to(create_task)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .set_json(serde_json::json!({
+            "title": "Test task",
+            "description": "Test description",
+            "status": "completed",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.code, "bad_request");
+}
+
+async fn create_task_with(
+    state: &AppState,
+    user_id: i32,
+    title: &str,
+    due_date: Option<chrono::NaiveDateTime>,
+    status: TaskStatus,
+) -> Task {
+    written(
+        state
+            .tasks
+            .create_task(
+                &TaskInput {
+                    title: title.into(),
+                    description: format!("Description of {}", title),
+                    due_date,
+                    status,
+                    priority: TaskPriority::default(),
+                    parent_id: None,
+                    recurrence: None,
+                },
+                user_id,
+            )
+            .await
+            .unwrap(),
+    )
+}
+
+fn days_from_now(days: i64) -> chrono::NaiveDateTime {
+    (chrono::Utc::now() + chrono::Duration::days(days)).naive_utc()
+}
+
+#[actix_rt::test]
+async fn test_get_user_tasks_filters() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let overdue = create_task_with(
+        &state,
+        user.id,
+        "Pay rent",
+        Some(days_from_now(-2)),
+        TaskStatus::Todo,
+    )
+    .await;
+    let finished = create_task_with(
+        &state,
+        user.id,
+        "File taxes",
+        Some(days_from_now(-5)),
+        TaskStatus::Done,
+    )
+    .await;
+    let upcoming = create_task_with(
+        &state,
+        user.id,
+        "Book 100% refund",
+        Some(days_from_now(3)),
+        TaskStatus::InProgress,
+    )
+    .await;
+    let undated = create_task_with(&state, user.id, "Read", None, TaskStatus::Todo).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tasks", web::get().to(get_user_tasks)),
+    )
+    .await;
+
+    let cases = [
+        ("status=todo", vec![overdue.id, undated.id]),
+        ("overdue=true", vec![overdue.id]),
+        ("overdue=false", vec![finished.id, upcoming.id, undated.id]),
+        ("q=RENT", vec![overdue.id]),
+        ("q=100%25", vec![upcoming.id]),
+        (
+            &format!(
+                "due_before={}",
+                days_from_now(0).format("%Y-%m-%dT%H:%M:%S")
+            ),
+            vec![overdue.id, finished.id],
+        ),
+        (
+            &format!("due_after={}", days_from_now(0).format("%Y-%m-%dT%H:%M:%S")),
+            vec![upcoming.id],
+        ),
+        (
+            "sort=due_date",
+            vec![finished.id, overdue.id, upcoming.id, undated.id],
+        ),
+        (
+            "sort=title&order=desc",
+            vec![undated.id, overdue.id, finished.id, upcoming.id],
+        ),
+    ];
+
+    for (query, expected) in cases {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users/{}/tasks?{}", user.id, query))
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", query);
+
+        let page: TaskPage = test::read_body_json(resp).await;
+        let ids: Vec<i32> = page.tasks.iter().map(|task| task.id).collect();
+        assert_eq!(ids, expected, "{}", query);
+    }
+}
+
+#[actix_rt::test]
+async fn test_get_user_tasks_keyset_pagination() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let mut created = Vec::new();
+    for (title, due_in) in [
+        ("a", Some(4)),
+        ("b", None),
+        ("c", Some(1)),
+        ("d", Some(1)),
+        ("e", None),
+    ] {
+        let task = create_task_with(
+            &state,
+            user.id,
+            title,
+            due_in.map(days_from_now),
+            TaskStatus::Todo,
+        )
+        .await;
+        created.push(task.id);
+    }
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tasks", web::get().to(get_user_tasks)),
+    )
+    .await;
+
+    let expected_by_query = [
+        ("limit=2", created.clone()),
+        (
+            "limit=2&order=desc",
+            created.iter().rev().copied().collect(),
+        ),
+        (
+            "limit=2&sort=due_date",
+            vec![created[2], created[3], created[0], created[1], created[4]],
+        ),
+        (
+            "limit=2&sort=due_date&order=desc",
+            vec![created[4], created[1], created[0], created[3], created[2]],
+        ),
+    ];
+
+    for (query, expected) in expected_by_query {
+        let mut uri = format!("/users/{}/tasks?{}", user.id, query);
+        let mut ids = Vec::new();
+        loop {
+            let req = test::TestRequest::get()
+                .uri(&uri)
+                .insert_header(bearer(&token))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+            let link = resp
+                .headers()
+                .get(header::LINK)
+                .map(|link| link.to_str().unwrap().to_owned());
+
+            let page: TaskPage = test::read_body_json(resp).await;
+            assert!(page.tasks.len() <= 2);
+            ids.extend(page.tasks.iter().map(|task| task.id));
+
+            match page.next_cursor {
+                Some(cursor) => {
+                    let link = link.expect("Link header for the next page");
+                    assert!(link.contains(&format!("cursor={}", cursor)), "{}", link);
+                    assert!(link.ends_with("rel=\"next\""), "{}", link);
+                    uri = link[1..link.find('>').unwrap()].to_owned();
+                }
+                None => {
+                    assert!(link.is_none());
+                    break;
+                }
+            }
+        }
+        assert_eq!(ids, expected, "{}", query);
+    }
+}
+
+#[actix_rt::test]
+async fn test_get_user_tasks_rejects_bad_paging_parameters() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tasks", web::get().to(get_user_tasks)),
+    )
+    .await;
+
+    let cursor = TaskCursor {
+        sort: TaskSort::Title,
+        order: SortOrder::Asc,
+        id: 1,
+        due_date: None,
+        title: Some("a".into()),
+        priority: None,
+    }
+    .encode();
+    let cases = [
+        ("limit=0".to_owned(), StatusCode::UNPROCESSABLE_ENTITY),
+        ("limit=101".to_owned(), StatusCode::UNPROCESSABLE_ENTITY),
+        ("cursor=garbage".to_owned(), StatusCode::BAD_REQUEST),
+        // A cursor only continues the ordering it was issued for
+        (
+            format!("sort=due_date&cursor={}", cursor),
+            StatusCode::BAD_REQUEST,
+        ),
+    ];
+
+    for (query, expected) in cases {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users/{}/tasks?{}", user.id, query))
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected, "{}", query);
+    }
+}
+
+#[actix_rt::test]
+async fn test_get_users_search_and_pagination() {
+    let state = test_state();
+    let (_, admin_token) = create_test_admin(&state).await;
+    let prefix = format!("Search{}", unique_suffix());
+    let mut created = Vec::new();
+    for n in 0..3 {
+        let user = state
+            .users
+            .create_user(&UserInput {
+                username: format!("{}_{}", prefix, n),
+                password: "securepassword123".into(),
+                email: format!("{}_{}@example.com", prefix.to_lowercase(), n),
+            })
+            .await
+            .unwrap();
+        created.push(user.id);
+    }
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/get_users", web::get().to(get_users)),
+    )
+    .await;
+
+    let mut uri = format!(
+        "/get_users?q={}&limit=2&include_total=true",
+        prefix.to_uppercase()
+    );
+    let mut ids = Vec::new();
+    loop {
+        let req = test::TestRequest::get()
+            .uri(&uri)
+            .insert_header(bearer(&admin_token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let page: UserPage = test::read_body_json(resp).await;
+        assert_eq!(page.total, Some(3));
+        ids.extend(page.users.iter().map(|user| user.id));
+
+        match page.next_cursor {
+            Some(cursor) => {
+                uri = format!(
+                    "/get_users?q={}&limit=2&include_total=true&cursor={}",
+                    prefix, cursor
+                )
+            }
+            None => break,
+        }
+    }
+    assert_eq!(ids, created);
+
+    // The match is on the start of the username or email only
+    let req = test::TestRequest::get()
+        .uri(&format!("/get_users?q={}&include_total=true", &prefix[1..]))
+        .insert_header(bearer(&admin_token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let page: UserPage = test::read_body_json(resp).await;
+    assert_eq!(page.total, Some(0));
+    assert!(page.users.is_empty());
+
+    let req = test::TestRequest::get()
+        .uri("/get_users?limit=500")
+        .insert_header(bearer(&admin_token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_rt::test]
+async fn test_priority_defaults_and_filters() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let low = create_test_task(&state, user.id).await;
+    let urgent = create_test_task(&state, user.id).await;
+    assert_eq!(low.priority, TaskPriority::Medium);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tasks", web::get().to(get_user_tasks))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::patch().to(patch_user_task),
+            ),
+    )
+    .await;
+
+    for (task, priority) in [(&low, TaskPriority::Low), (&urgent, TaskPriority::Urgent)] {
+        let req = test::TestRequest::patch()
+            .uri(&format!("/users/{}/tasks/{}", user.id, task.id))
+            .insert_header(bearer(&token))
+            .set_json(&TaskPatch {
+                priority: Some(priority),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    let cases = [
+        ("priority=urgent", vec![urgent.id]),
+        ("sort=priority&order=desc", vec![urgent.id, low.id]),
+    ];
+    for (query, expected) in cases {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users/{}/tasks?{}", user.id, query))
+            .insert_header(bearer(&token))
+            .to_request();
+        let page: TaskPage = test::call_and_read_body_json(&app, req).await;
+        let ids: Vec<i32> = page.tasks.iter().map(|task| task.id).collect();
+        assert_eq!(ids, expected, "{}", query);
+    }
+}
+
+// Task ids of each board column, in column order
+fn column_ids(board: &Board) -> Vec<Vec<i32>> {
+    board
+        .columns
+        .iter()
+        .map(|column| column.tasks.iter().map(|task| task.id).collect())
+        .collect()
+}
+
+#[actix_rt::test]
+async fn test_move_task_and_board() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let first = create_test_task(&state, user.id).await;
+    let second = create_test_task(&state, user.id).await;
+    let third = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/board", web::get().to(get_user_board))
+            .route(
+                "/users/{user_id}/tasks/{task_id}/move",
+                web::post().to(move_user_task),
+            ),
+    )
+    .await;
+    let board_uri = format!("/users/{}/board", user.id);
+
+    let req = test::TestRequest::get()
+        .uri(&board_uri)
+        .insert_header(bearer(&token))
+        .to_request();
+    let board: Board = test::call_and_read_body_json(&app, req).await;
+    let statuses: Vec<TaskStatus> = board.columns.iter().map(|column| column.status).collect();
+    assert_eq!(statuses, TaskStatus::ALL);
+    assert_eq!(column_ids(&board)[0], vec![first.id, second.id, third.id]);
+
+    // Each move with the expected todo and in progress columns after it
+    let moves = [
+        (
+            third.id,
+            TaskMove {
+                before_id: Some(first.id),
+                ..Default::default()
+            },
+            [vec![third.id, first.id, second.id], vec![]],
+        ),
+        (
+            first.id,
+            TaskMove {
+                after_id: Some(second.id),
+                ..Default::default()
+            },
+            [vec![third.id, second.id, first.id], vec![]],
+        ),
+        (
+            second.id,
+            TaskMove {
+                status: Some(TaskStatus::InProgress),
+                ..Default::default()
+            },
+            [vec![third.id, first.id], vec![second.id]],
+        ),
+        (
+            third.id,
+            TaskMove {
+                before_id: Some(second.id),
+                ..Default::default()
+            },
+            [vec![first.id], vec![third.id, second.id]],
+        ),
+    ];
+    for (task_id, body, expected) in moves {
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{}/tasks/{}/move", user.id, task_id))
+            .insert_header(bearer(&token))
+            .set_json(&body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{:?}", body);
+
+        let req = test::TestRequest::get()
+            .uri(&board_uri)
+            .insert_header(bearer(&token))
+            .to_request();
+        let board: Board = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(column_ids(&board)[..2], expected, "{:?}", body);
+    }
+
+    let cases = [
+        (
+            TaskMove {
+                before_id: Some(second.id),
+                after_id: Some(third.id),
+                ..Default::default()
+            },
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            TaskMove {
+                before_id: Some(first.id),
+                ..Default::default()
+            },
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            TaskMove {
+                status: Some(TaskStatus::Done),
+                after_id: Some(second.id),
+                ..Default::default()
+            },
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            TaskMove {
+                before_id: Some(i32::MAX),
+                ..Default::default()
+            },
+            StatusCode::NOT_FOUND,
+        ),
+        // Moves across columns follow the status workflow
+        (
+            TaskMove {
+                status: Some(TaskStatus::Done),
+                ..Default::default()
+            },
+            StatusCode::OK,
+        ),
+        (
+            TaskMove {
+                status: Some(TaskStatus::Blocked),
+                ..Default::default()
+            },
+            StatusCode::CONFLICT,
+        ),
+    ];
+    for (body, expected) in cases {
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{}/tasks/{}/move", user.id, first.id))
+            .insert_header(bearer(&token))
+            .set_json(&body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected, "{:?}", body);
+    }
+}
+
+#[actix_rt::test]
+async fn test_tag_lifecycle() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let tagged = create_test_task(&state, user.id).await;
+    let untagged = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tags", web::post().to(create_tag))
+            .route("/users/{user_id}/tags", web::get().to(get_user_tags))
+            .route("/users/{user_id}/tags/{tag_id}", web::put().to(rename_tag))
+            .route(
+                "/users/{user_id}/tags/{tag_id}",
+                web::delete().to(delete_tag),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/tags/{tag_id}",
+                web::put().to(attach_tag),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/tags/{tag_id}",
+                web::delete().to(detach_tag),
+            )
+            .route("/users/{user_id}/tasks", web::get().to(get_user_tasks))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::get().to(get_user_task),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tags", user.id))
+        .insert_header(bearer(&token))
+        .set_json(&TagInput {
+            name: "urgent".into(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tag: Tag = test::read_body_json(resp).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tags", user.id))
+        .insert_header(bearer(&token))
+        .set_json(&TagInput {
+            name: "urgent".into(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let task_tag_uri = format!("/users/{}/tasks/{}/tags/{}", user.id, tagged.id, tag.id);
+    for _ in 0..2 {
+        let req = test::TestRequest::put()
+            .uri(&task_tag_uri)
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks?tag=urgent", user.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let page: TaskPage = test::call_and_read_body_json(&app, req).await;
+    let ids: Vec<i32> = page.tasks.iter().map(|task| task.id).collect();
+    assert_eq!(ids, vec![tagged.id]);
+    assert_eq!(page.tasks[0].tags, vec![tag.clone()]);
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/users/{}/tags/{}", user.id, tag.id))
+        .insert_header(bearer(&token))
+        .set_json(&TagInput {
+            name: "someday".into(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", user.id, tagged.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<&str> = task.tags.iter().map(|tag| tag.name.as_str()).collect();
+    assert_eq!(names, ["someday"]);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", user.id, untagged.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    assert!(task.tags.is_empty());
+
+    for expected in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+        let req = test::TestRequest::delete()
+            .uri(&task_tag_uri)
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+    }
+
+    for expected in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/users/{}/tags/{}", user.id, tag.id))
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tags", user.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let tags: Vec<Tag> = test::call_and_read_body_json(&app, req).await;
+    assert!(tags.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_tags_of_other_user_cannot_be_attached() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let (other, _) = create_test_user(&state).await;
+    let task = create_test_task(&state, user.id).await;
+    let foreign = state.tags.create_tag(other.id, "theirs").await.unwrap();
+
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+        "/users/{user_id}/tasks/{task_id}/tags/{tag_id}",
+        web::put().to(attach_tag),
+    ))
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!(
+            "/users/{}/tasks/{}/tags/{}",
+            user.id, task.id, foreign.id
+        ))
+        .insert_header(bearer(&token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.code, "not_found");
+}
+
+#[actix_rt::test]
+async fn test_subtask_nesting_and_cascades() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let root = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/users/{user_id}/tasks", web::post().to(create_task))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::get().to(get_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::patch().to(patch_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::delete().to(delete_user_task),
+            ),
+    )
+    .await;
+
+    let subtask = |parent_id| TaskInput {
+        title: "Subtask".into(),
+        description: "Test description".into(),
+        due_date: None,
+        status: TaskStatus::Todo,
+        priority: TaskPriority::default(),
+        parent_id: Some(parent_id),
+        recurrence: None,
+    };
+
+    // Three levels below the root are allowed, a fourth is not
+    let mut chain = vec![root.id];
+    for expected in [
+        StatusCode::OK,
+        StatusCode::OK,
+        StatusCode::OK,
+        StatusCode::CONFLICT,
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{}/tasks", user.id))
+            .insert_header(bearer(&token))
+            .set_json(subtask(*chain.last().unwrap()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+        if expected == StatusCode::OK {
+            let task: Task = test::read_body_json(resp).await;
+            chain.push(task.id);
+        }
+    }
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .set_json(subtask(0))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // A task cannot go under itself or one of its own subtasks
+    for parent_id in [root.id, chain[2]] {
+        let req = test::TestRequest::patch()
+            .uri(&format!("/users/{}/tasks/{}", user.id, root.id))
+            .insert_header(bearer(&token))
+            .set_json(serde_json::json!({ "parent_id": parent_id }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    // Moving the first level under a sibling would push the deepest level
+    // past the limit; `null` makes a task top-level again
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .set_json(subtask(root.id))
+        .to_request();
+    let sibling: Task = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::patch()
+        .uri(&format!("/users/{}/tasks/{}", user.id, chain[1]))
+        .insert_header(bearer(&token))
+        .set_json(serde_json::json!({ "parent_id": sibling.id }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let req = test::TestRequest::patch()
+        .uri(&format!("/users/{}/tasks/{}", user.id, sibling.id))
+        .insert_header(bearer(&token))
+        .set_json(serde_json::json!({ "parent_id": null }))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(task.parent_id, None);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", user.id, root.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(task.progress, Progress { done: 0, total: 1 });
+
+    // Completing the root completes every level below it
+    let req = test::TestRequest::patch()
+        .uri(&format!("/users/{}/tasks/{}", user.id, root.id))
+        .insert_header(bearer(&token))
+        .set_json(&TaskPatch {
+            status: Some(TaskStatus::Done),
+            ..Default::default()
+        })
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(task.progress, Progress { done: 1, total: 1 });
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", user.id, chain[3]))
+        .insert_header(bearer(&token))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(task.status, TaskStatus::Done);
+
+    // Deleting the root deletes every level below it
+    let req = test::TestRequest::delete()
+        .uri(&format!("/users/{}/tasks/{}", user.id, root.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    for task_id in &chain[1..] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/users/{}/tasks/{}", user.id, task_id))
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[actix_rt::test]
+async fn test_checklist_lifecycle() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let task = create_test_task(&state, user.id).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::get().to(get_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/checklist",
+                web::post().to(create_checklist_item),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/checklist/{item_id}",
+                web::patch().to(update_checklist_item),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/checklist/{item_id}",
+                web::delete().to(delete_checklist_item),
+            ),
+    )
+    .await;
+
+    let checklist_uri = format!("/users/{}/tasks/{}/checklist", user.id, task.id);
+    let mut items = Vec::new();
+    for title in ["Pack", "Ship"] {
+        let req = test::TestRequest::post()
+            .uri(&checklist_uri)
+            .insert_header(bearer(&token))
+            .set_json(&ChecklistItemInput {
+                title: title.into(),
+            })
+            .to_request();
+        let item: ChecklistItem = test::call_and_read_body_json(&app, req).await;
+        items.push(item);
+    }
+
+    let req = test::TestRequest::post()
+        .uri(&checklist_uri)
+        .insert_header(bearer(&token))
+        .set_json(&ChecklistItemInput { title: "".into() })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks/0/checklist", user.id))
+        .insert_header(bearer(&token))
+        .set_json(&ChecklistItemInput {
+            title: "Pack".into(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("{}/{}", checklist_uri, items[0].id))
+        .insert_header(bearer(&token))
+        .set_json(&ChecklistItemPatch {
+            done: Some(true),
+            ..Default::default()
+        })
+        .to_request();
+    let item: ChecklistItem = test::call_and_read_body_json(&app, req).await;
+    assert!(item.done);
+    assert_eq!(item.title, "Pack");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", user.id, task.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    let titles: Vec<&str> = task
+        .checklist
+        .iter()
+        .map(|item| item.title.as_str())
+        .collect();
+    assert_eq!(titles, ["Pack", "Ship"]);
+    assert_eq!(task.progress, Progress { done: 1, total: 2 });
+
+    for expected in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("{}/{}", checklist_uri, items[1].id))
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+    }
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("{}/{}", checklist_uri, items[1].id))
+        .insert_header(bearer(&token))
+        .set_json(&ChecklistItemPatch {
+            done: Some(true),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn test_dependencies_block_completion() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let task = create_test_task(&state, user.id).await;
+    let blocker = create_test_task(&state, user.id).await;
+    let parent = create_test_task(&state, user.id).await;
+    let subtask = written(
+        state
+            .tasks
+            .create_task(
+                &TaskInput {
+                    title: "Subtask".into(),
+                    description: "Test description".into(),
+                    due_date: None,
+                    status: TaskStatus::Todo,
+                    priority: TaskPriority::default(),
+                    parent_id: Some(parent.id),
+                    recurrence: None,
+                },
+                user.id,
+            )
+            .await
+            .unwrap(),
+    );
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::get().to(get_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::patch().to(patch_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/move",
+                web::post().to(move_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/dependencies/{blocker_id}",
+                web::put().to(add_dependency),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/dependencies/{blocker_id}",
+                web::delete().to(remove_dependency),
+            ),
+    )
+    .await;
+
+    let dependency_uri = |task_id, blocker_id| {
+        format!(
+            "/users/{}/tasks/{}/dependencies/{}",
+            user.id, task_id, blocker_id
+        )
+    };
+    for (task_id, blocker_id, expected) in [
+        (task.id, blocker.id, StatusCode::NO_CONTENT),
+        (task.id, blocker.id, StatusCode::NO_CONTENT),
+        (subtask.id, blocker.id, StatusCode::NO_CONTENT),
+        (blocker.id, task.id, StatusCode::CONFLICT),
+        (task.id, task.id, StatusCode::CONFLICT),
+        (task.id, 0, StatusCode::NOT_FOUND),
+    ] {
+        let req = test::TestRequest::put()
+            .uri(&dependency_uri(task_id, blocker_id))
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks/{}", user.id, task.id))
+        .insert_header(bearer(&token))
+        .to_request();
+    let fetched: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(fetched.blocked_by, vec![blocker.id]);
+    assert!(fetched.blocked);
+
+    let complete = |task_id| {
+        test::TestRequest::patch()
+            .uri(&format!("/users/{}/tasks/{}", user.id, task_id))
+            .insert_header(bearer(&token))
+            .set_json(TaskPatch {
+                status: Some(TaskStatus::Done),
+                ..Default::default()
+            })
+            .to_request()
+    };
+
+    // Neither the task nor the parent of a blocked subtask can be completed
+    for task_id in [task.id, parent.id] {
+        let resp = test::call_service(&app, complete(task_id)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks/{}/move", user.id, task.id))
+        .insert_header(bearer(&token))
+        .set_json(TaskMove {
+            status: Some(TaskStatus::Done),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = test::call_service(&app, complete(blocker.id)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    for task_id in [task.id, parent.id] {
+        let completed: Task = test::call_and_read_body_json(&app, complete(task_id)).await;
+        assert_eq!(completed.status, TaskStatus::Done);
+        assert!(!completed.blocked);
+    }
+
+    for expected in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+        let req = test::TestRequest::delete()
+            .uri(&dependency_uri(task.id, blocker.id))
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+    }
+}
+
+#[actix_rt::test]
+async fn test_recurring_tasks() {
+    let state = test_state();
+    let (user, token) = create_test_user(&state).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/users/{user_id}/tasks", web::post().to(create_task))
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::patch().to(patch_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/occurrences",
+                web::get().to(get_task_occurrences),
+            ),
+    )
+    .await;
+
+    let at = |day, hour| {
+        chrono::NaiveDate::from_ymd_opt(2030, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    };
+    let input = |due_date, recurrence: &str| TaskInput {
+        title: "Water the plants".into(),
+        description: "Test description".into(),
+        due_date,
+        status: TaskStatus::Todo,
+        priority: TaskPriority::High,
+        parent_id: None,
+        recurrence: Some(recurrence.into()),
+    };
+
+    for (due_date, recurrence) in [
+        (None, "FREQ=WEEKLY"),
+        (Some(at(7, 9)), "FREQ=YEARLY"),
+        (Some(at(7, 9)), "FREQ=WEEKLY;COUNT=0"),
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{}/tasks", user.id))
+            .insert_header(bearer(&token))
+            .set_json(input(due_date, recurrence))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert_eq!(body.details.unwrap()[0]["field"], "recurrence");
+    }
+
+    // 2030-01-07 is a Monday
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .set_json(input(Some(at(7, 9)), "freq=weekly;byday=mo,th;count=3"))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        task.recurrence.as_deref(),
+        Some("FREQ=WEEKLY;BYDAY=MO,TH;COUNT=3")
+    );
+
+    let preview = |task_id, limit: &str| {
+        test::TestRequest::get()
+            .uri(&format!(
+                "/users/{}/tasks/{}/occurrences{}",
+                user.id, task_id, limit
+            ))
+            .insert_header(bearer(&token))
+            .to_request()
+    };
+    let occurrences: Occurrences = test::call_and_read_body_json(&app, preview(task.id, "")).await;
+    assert_eq!(
+        occurrences.occurrences,
+        vec![at(7, 9), at(10, 9), at(14, 9)]
+    );
+    let occurrences: Occurrences =
+        test::call_and_read_body_json(&app, preview(task.id, "?limit=1")).await;
+    assert_eq!(occurrences.occurrences, vec![at(7, 9)]);
+    for limit in ["?limit=0", "?limit=101"] {
+        let resp = test::call_service(&app, preview(task.id, limit)).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let tag = state.tags.create_tag(user.id, "home").await.unwrap();
+    state
+        .tags
+        .attach_tag(TaskTagInfo {
+            user_id: user.id,
+            task_id: task.id,
+            tag_id: tag.id,
+        })
+        .await
+        .unwrap();
+    let info = Info {
+        user_id: user.id,
+        task_id: task.id,
+    };
+    let item = state
+        .checklists
+        .create_checklist_item(info.clone(), "Fill the can")
+        .await
+        .unwrap();
+    state
+        .checklists
+        .update_checklist_item(
+            ChecklistItemInfo {
+                user_id: user.id,
+                task_id: task.id,
+                item_id: item.id,
+            },
+            ChecklistItemPatch {
+                done: Some(true),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    let patch = |task_id, patch: TaskPatch| {
+        test::TestRequest::patch()
+            .uri(&format!("/users/{}/tasks/{}", user.id, task_id))
+            .insert_header(bearer(&token))
+            .set_json(patch)
+            .to_request()
+    };
+    let status = |status| TaskPatch {
+        status: Some(status),
+        ..Default::default()
+    };
+    let open_tasks = || async {
+        state
+            .tasks
+            .get_user_tasks(user.id, &TaskQuery::default(), None, 100)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|task| task.status == TaskStatus::Todo)
+            .collect::<Vec<Task>>()
+    };
+
+    // Completing an occurrence hands the rule over to the next one
+    let done: Task =
+        test::call_and_read_body_json(&app, patch(task.id, status(TaskStatus::Done))).await;
+    assert_eq!(done.status, TaskStatus::Done);
+    assert_eq!(done.recurrence, None);
+    let next = open_tasks().await;
+    assert_eq!(next.len(), 1);
+    let next = &next[0];
+    assert_eq!(next.title, "Water the plants");
+    assert_eq!(next.priority, TaskPriority::High);
+    assert_eq!(next.due_date, Some(at(10, 9)));
+    assert_eq!(
+        next.recurrence.as_deref(),
+        Some("FREQ=WEEKLY;BYDAY=MO,TH;COUNT=2")
+    );
+    assert_eq!(next.tags, vec![tag]);
+    assert_eq!(next.checklist.len(), 1);
+    assert!(!next.checklist[0].done);
+
+    // Reopening and completing the first one again creates nothing new
+    let resp = test::call_service(&app, patch(task.id, status(TaskStatus::Todo))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(open_tasks().await.len(), 2);
+    let resp = test::call_service(&app, patch(task.id, status(TaskStatus::Done))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(open_tasks().await.len(), 1);
+
+    // A recurring task cannot lose its due date
+    let resp = test::call_service(
+        &app,
+        patch(
+            next.id,
+            TaskPatch {
+                due_date: Some(None),
+                ..Default::default()
+            },
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // The last of the COUNT occurrences does not recur
+    let resp = test::call_service(&app, patch(next.id, status(TaskStatus::Done))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let last = open_tasks().await;
+    assert_eq!(last.len(), 1);
+    assert_eq!(last[0].due_date, Some(at(14, 9)));
+    assert_eq!(
+        last[0].recurrence.as_deref(),
+        Some("FREQ=WEEKLY;BYDAY=MO,TH;COUNT=1")
+    );
+    let resp = test::call_service(&app, patch(last[0].id, status(TaskStatus::Done))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(open_tasks().await.is_empty());
+
+    // Without a rule, only the task's own due date
+    let occurrences: Occurrences =
+        test::call_and_read_body_json(&app, preview(last[0].id, "")).await;
+    assert_eq!(occurrences.recurrence, None);
+    assert_eq!(occurrences.occurrences, vec![at(14, 9)]);
+
+    // A recurring subtask completed with its parent recurs under it as well
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .set_json(TaskInput {
+            title: "Chores".into(),
+            recurrence: None,
+            ..input(None, "")
+        })
+        .to_request();
+    let parent: Task = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .insert_header(bearer(&token))
+        .set_json(TaskInput {
+            parent_id: Some(parent.id),
+            ..input(Some(at(21, 9)), "FREQ=WEEKLY;COUNT=2")
+        })
+        .to_request();
+    let subtask: Task = test::call_and_read_body_json(&app, req).await;
+    let resp = test::call_service(&app, patch(parent.id, status(TaskStatus::Done))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let subtask = state
+        .tasks
+        .get_user_task(user.id, subtask.id)
+        .await
+        .unwrap();
+    assert_eq!(subtask.status, TaskStatus::Done);
+    assert_eq!(subtask.recurrence, None);
+    let next = open_tasks().await;
+    assert_eq!(next.len(), 1);
+    assert_eq!(next[0].parent_id, Some(parent.id));
+    assert_eq!(next[0].due_date, Some(at(28, 9)));
+    assert_eq!(next[0].recurrence.as_deref(), Some("FREQ=WEEKLY;COUNT=1"));
+}
+
+// A database that answers but has not been migrated to this build's schema
+struct BehindSchema;
+
+#[async_trait]
+impl HealthRepository for BehindSchema {
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> Result<i64, sqlx::Error> {
+        Ok(latest_version() - 1)
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        None
+    }
+
+    async fn close(&self) {}
+}
+
+#[actix_rt::test]
+async fn test_health_and_version_endpoints() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(test_state()))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .route("/version", web::get().to(version)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let health: Health = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(health.status, "ok");
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let readiness: Readiness = test::read_body_json(resp).await;
+    assert_eq!(readiness.status, "ready");
+    assert_eq!(readiness.schema_version, Some(latest_version()));
+
+    let req = test::TestRequest::get().uri("/version").to_request();
+    let info: BuildInfo = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert!(!info.git_sha.is_empty());
+    assert_eq!(info.schema_version, latest_version());
+}
+
+#[actix_rt::test]
+async fn test_readyz_fails_while_migrations_are_pending() {
+    let state = AppState {
+        health: Arc::new(BehindSchema),
+        ..test_state()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/readyz", web::get().to(readyz)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: Readiness = test::read_body_json(resp).await;
+    assert_eq!(readiness.reason.as_deref(), Some("migrations not current"));
+    assert_eq!(readiness.schema_version, Some(latest_version() - 1));
+}
+
+#[actix_rt::test]
+async fn test_metrics_record_requests_and_sign_in_failures() {
+    let state = test_state();
+    let (user, _) = create_test_user(&state).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .wrap(RequestMetrics)
+            .route("/users/{user_id}/tasks", web::get().to(get_user_tasks))
+            .route("/sign_in", web::post().to(sign_in_handler))
+            .route("/metrics", web::get().to(metrics)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/users/{}/tasks", user.id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri("/sign_in")
+        .set_json(&SignInInput {
+            username: user.username.clone(),
+            password: "wrongpassword".into(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+    // Labelled by route pattern rather than the concrete path
+    assert!(body.contains(
+        r#"http_requests_total{method="GET",route="/users/{user_id}/tasks",status="4xx"} 1"#
+    ));
+    assert!(
+        body.contains(r#"http_request_duration_seconds_count{method="POST",route="/sign_in"} 1"#)
+    );
+    assert!(body.contains("sign_in_failures_total 1"));
+}
+
+#[actix_rt::test]
+async fn test_request_id_is_echoed_or_generated() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(test_state()))
+            .wrap(RequestIdentity)
+            .route("/healthz", web::get().to(healthz)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/healthz")
+        .insert_header((REQUEST_ID_HEADER, "client-id-1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.headers().get(REQUEST_ID_HEADER).unwrap(),
+        "client-id-1"
+    );
+
+    let mut generated = Vec::new();
+    for header in [None, Some("not valid!"), Some(&*"x".repeat(200))] {
+        let mut req = test::TestRequest::get().uri("/healthz");
+        if let Some(header) = header {
+            req = req.insert_header((REQUEST_ID_HEADER, header));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        let id = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(Uuid::parse_str(id).is_ok(), "{} is not a generated id", id);
+        generated.push(id.to_owned());
+    }
+    generated.dedup();
+    assert_eq!(generated.len(), 3);
+
+    // Unmatched routes are tagged too
+    let req = test::TestRequest::get().uri("/missing").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(resp.headers().contains_key(REQUEST_ID_HEADER));
+}
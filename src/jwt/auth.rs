@@ -0,0 +1,115 @@
+use crate::error::error::ApiError;
+use crate::jwt::jwt::decode_jwt;
+use crate::model::models::{Claims, Role};
+use crate::model::state::AppState;
+use actix_web::{dev::Payload, http::header, web, FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+use std::marker::PhantomData;
+
+// The user behind a valid `Authorization: Bearer` token.
+//
+// Extracting it rejects the request with 401 when the token is missing,
+// malformed or expired, and with 403 when the route has a `{user_id}`
+// segment that does not belong to the token's subject. Admins may act on
+// any user's resources.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: i32,
+    pub claims: Claims,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+fn authenticate(req: &HttpRequest) -> Result<AuthenticatedUser, ApiError> {
+    let token = bearer_token(req)
+        .ok_or_else(|| ApiError::Unauthorized("Missing bearer token".to_owned()))?;
+
+    let state = req.app_data::<web::Data<AppState>>().ok_or_else(|| {
+        log::error!("AppState is not registered as app data");
+        ApiError::Internal("Authentication is not configured".to_owned())
+    })?;
+
+    let claims = decode_jwt(&state.settings.auth, token).map_err(|e| {
+        log::warn!("Rejected bearer token: {}", e);
+        ApiError::Unauthorized("Invalid or expired token".to_owned())
+    })?;
+
+    let user_id = claims
+        .sub
+        .parse::<i32>()
+        .map_err(|_| ApiError::Unauthorized("Invalid or expired token".to_owned()))?;
+
+    if let Some(path_user_id) = req.match_info().get("user_id") {
+        if claims.role != Role::Admin && path_user_id.parse::<i32>().ok() != Some(user_id) {
+            log::warn!(
+                "User with id {} attempted to access resources of user {}",
+                user_id,
+                path_user_id
+            );
+            return Err(ApiError::Forbidden(
+                "Access to this user's resources is forbidden".to_owned(),
+            ));
+        }
+    }
+
+    Ok(AuthenticatedUser { user_id, claims })
+}
+
+// A rule deciding which roles may call a handler, checked by `Authorized`
+pub trait Permission {
+    fn allows(role: Role) -> bool;
+}
+
+pub struct AdminOnly;
+
+impl Permission for AdminOnly {
+    fn allows(role: Role) -> bool {
+        role == Role::Admin
+    }
+}
+
+// An `AuthenticatedUser` whose role satisfies the permission `P`, e.g.
+// `Authorized<AdminOnly>`. Rejects other users with 403.
+pub struct Authorized<P: Permission> {
+    pub user: AuthenticatedUser,
+    permission: PhantomData<P>,
+}
+
+impl<P: Permission> FromRequest for Authorized<P> {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(authenticate(req).and_then(|user| {
+            if P::allows(user.claims.role) {
+                Ok(Authorized {
+                    user,
+                    permission: PhantomData,
+                })
+            } else {
+                log::warn!(
+                    "User with id {} lacks permission for {}",
+                    user.user_id,
+                    req.path()
+                );
+                Err(ApiError::Forbidden("Insufficient permissions".to_owned()))
+            }
+        }))
+    }
+}
@@ -1,27 +1,40 @@
-use crate::model::models::Claims;
-use dotenv::dotenv;
+use crate::config::settings::AuthSettings;
+use crate::model::models::{Claims, Role};
 use jsonwebtoken::errors::Error;
-use jsonwebtoken::{encode, EncodingKey, Header};
-use std::env;
-
-pub async fn generate_jwt(user_id: &str) -> Result<String, Error> {
-    dotenv().ok();
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 
+pub async fn generate_jwt(
+    settings: &AuthSettings,
+    user_id: &str,
+    session_id: &str,
+    role: Role,
+) -> Result<String, Error> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(chrono::Duration::minutes(settings.access_token_ttl_minutes))
         .expect("valid timestamp")
         .timestamp();
 
     let claims = Claims {
         sub: user_id.to_owned(),
+        sid: session_id.to_owned(),
+        role,
         exp: expiration as usize,
     };
 
-    let secret_key = env::var("SECRET_KEY").expect("SECRET_KEY must be set");
-
     encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(secret_key.as_ref()),
+        &EncodingKey::from_secret(settings.secret_key.as_ref()),
     )
 }
+
+// Verifies the signature and expiry of a token issued by `generate_jwt`
+pub fn decode_jwt(settings: &AuthSettings, token: &str) -> Result<Claims, Error> {
+    let data = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(settings.secret_key.as_ref()),
+        &Validation::default(),
+    )?;
+
+    Ok(data.claims)
+}
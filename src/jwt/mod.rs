@@ -1 +1,5 @@
+pub mod auth;
+#[allow(clippy::module_inception)]
 pub mod jwt;
+pub mod refresh;
+pub mod session;
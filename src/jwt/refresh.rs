@@ -0,0 +1,33 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// Opaque refresh token handed to the client; only its hash is stored
+pub fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_tokens_are_unique_and_hash_deterministically() {
+        let first = generate_refresh_token();
+        let second = generate_refresh_token();
+
+        assert_eq!(first.len(), 64);
+        assert_ne!(first, second);
+        assert_eq!(hash_refresh_token(&first), hash_refresh_token(&first));
+        assert_ne!(hash_refresh_token(&first), first);
+    }
+}
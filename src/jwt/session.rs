@@ -0,0 +1,120 @@
+use crate::error::error::ApiError;
+use crate::jwt::jwt::generate_jwt;
+use crate::jwt::refresh::{generate_refresh_token, hash_refresh_token};
+use crate::model::models::{RefreshToken, TokenResponse};
+use crate::model::state::AppState;
+use uuid::Uuid;
+
+fn invalid_refresh_token() -> ApiError {
+    ApiError::Unauthorized("Invalid or expired refresh token".to_owned())
+}
+
+fn refresh_token_expiry(state: &AppState) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc::now() + chrono::Duration::days(state.settings.auth.refresh_token_ttl_days)
+}
+
+// The role is read on every issue so that role changes apply from the next refresh
+async fn issue_tokens(
+    state: &AppState,
+    refresh_token: String,
+    stored: &RefreshToken,
+) -> Result<TokenResponse, ApiError> {
+    let role = state.users.get_user_role(stored.user_id).await?;
+    let token = generate_jwt(
+        &state.settings.auth,
+        &stored.user_id.to_string(),
+        &stored.family_id.to_string(),
+        role,
+    )
+    .await?;
+
+    Ok(TokenResponse {
+        token,
+        refresh_token,
+    })
+}
+
+// Starts a new refresh token family, e.g. on sign up or sign in
+pub async fn start_session(state: &AppState, user_id: i32) -> Result<TokenResponse, ApiError> {
+    let refresh_token = generate_refresh_token();
+    let stored = state
+        .refresh_tokens
+        .create_refresh_token(
+            user_id,
+            Uuid::new_v4(),
+            &hash_refresh_token(&refresh_token),
+            refresh_token_expiry(state),
+        )
+        .await?;
+
+    issue_tokens(state, refresh_token, &stored).await
+}
+
+// Exchanges a refresh token for a new access/refresh token pair. Presenting a
+// token that was already rotated revokes every token in its family.
+pub async fn refresh_session(
+    state: &AppState,
+    refresh_token: &str,
+) -> Result<TokenResponse, ApiError> {
+    let current = match state
+        .refresh_tokens
+        .get_refresh_token(&hash_refresh_token(refresh_token))
+        .await
+    {
+        Ok(current) => current,
+        Err(sqlx::Error::RowNotFound) => return Err(invalid_refresh_token()),
+        Err(e) => return Err(e.into()),
+    };
+
+    if current.revoked_at.is_some() {
+        return Err(revoke_reused_family(state, &current).await);
+    }
+
+    if current.expires_at <= chrono::Utc::now() {
+        return Err(invalid_refresh_token());
+    }
+
+    let next_token = generate_refresh_token();
+    let next = state
+        .refresh_tokens
+        .rotate_refresh_token(
+            &current,
+            &hash_refresh_token(&next_token),
+            refresh_token_expiry(state),
+        )
+        .await?;
+
+    match next {
+        Some(next) => issue_tokens(state, next_token, &next).await,
+        None => Err(revoke_reused_family(state, &current).await),
+    }
+}
+
+async fn revoke_reused_family(state: &AppState, reused: &RefreshToken) -> ApiError {
+    log::warn!(
+        "Refresh token reuse detected for user with id {}, revoking session {}",
+        reused.user_id,
+        reused.family_id
+    );
+
+    match state
+        .refresh_tokens
+        .revoke_token_family(reused.user_id, reused.family_id)
+        .await
+    {
+        Ok(_) => ApiError::Unauthorized("Refresh token has already been used".to_owned()),
+        Err(e) => e.into(),
+    }
+}
+
+// Revokes every refresh token of the session an access token belongs to
+pub async fn end_session(state: &AppState, user_id: i32, session_id: &str) -> Result<(), ApiError> {
+    let family_id = Uuid::parse_str(session_id).map_err(|_| invalid_refresh_token())?;
+
+    state
+        .refresh_tokens
+        .revoke_token_family(user_id, family_id)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,8 @@
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod handlers;
+pub mod jwt;
+pub mod logging;
+pub mod metrics;
+pub mod model;
@@ -0,0 +1,74 @@
+use chrono::{SecondsFormat, Utc};
+use env_logger::Env;
+use log::Record;
+use serde_json::{Map, Value};
+use std::future::Future;
+use std::io::Write;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// Runs `future` with `request_id` attached to every line it logs
+pub async fn with_request_id<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+// The id of the request the current task is handling, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+// Installs the global logger, writing one JSON object per line. `RUST_LOG`
+// selects what is logged, `info` and above by default.
+pub fn init() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+        .format(|buf, record| writeln!(buf, "{}", to_json(record)))
+        .init();
+}
+
+fn to_json(record: &Record) -> Value {
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_owned(),
+        Utc::now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into(),
+    );
+    line.insert("level".to_owned(), record.level().as_str().into());
+    line.insert("target".to_owned(), record.target().into());
+    line.insert("message".to_owned(), record.args().to_string().into());
+    if let Some(request_id) = current_request_id() {
+        line.insert("request_id".to_owned(), request_id.into());
+    }
+
+    Value::Object(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    fn log_line() -> Value {
+        to_json(
+            &Record::builder()
+                .args(format_args!("Created task {}", 7))
+                .level(Level::Warn)
+                .target("tasks")
+                .build(),
+        )
+    }
+
+    #[actix_rt::test]
+    async fn test_log_lines_carry_the_request_id() {
+        let line = log_line();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], "tasks");
+        assert_eq!(line["message"], "Created task 7");
+        assert!(line.get("request_id").is_none());
+
+        let line = with_request_id("abc-123".to_owned(), async { log_line() }).await;
+        assert_eq!(line["request_id"], "abc-123");
+    }
+}
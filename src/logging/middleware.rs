@@ -0,0 +1,88 @@
+use crate::logging::logging::with_request_id;
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Longer ids are replaced, so clients cannot flood the logs through it
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// The correlation id of a request, available to handlers via
+// `req.extensions()`
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+// Tags every request with an id, taken from its `X-Request-Id` header or
+// generated when missing or malformed. The id is attached to every log line
+// emitted while the request is handled and echoed in the response header.
+pub struct RequestIdentity;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdentity
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdentityMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdentityMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdentityMiddleware<S> {
+    service: Rc<S>,
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdentityMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        let request_id = req
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid_request_id(id))
+            .map(str::to_owned)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+
+        Box::pin(with_request_id(request_id.clone(), async move {
+            let mut res = service.call(req).await?;
+
+            let value = HeaderValue::from_str(&request_id).expect("request ids are valid headers");
+            res.headers_mut().insert(REQUEST_ID_HEADER, value);
+
+            Ok(res)
+        }))
+    }
+}
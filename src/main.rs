@@ -1,49 +1,88 @@
-mod db;
-mod handlers;
-mod jwt;
-mod model;
-
 use actix_cors::Cors;
 use actix_web::web::Data;
 use actix_web::{http::header, web, App, HttpServer, Responder};
-use db::server::create_pool_and_run_migrations;
 use dotenv::dotenv;
-use handlers::handlers::{
-    create_task, create_user, delete_user_task, get_user_task, get_user_tasks, get_users,
-    sign_in_handler, update_user_task,
+use task_management_actix_web::config::settings::Settings;
+use task_management_actix_web::db::server::create_state;
+use task_management_actix_web::error::error::{
+    json_error_handler, path_error_handler, query_error_handler,
+};
+use task_management_actix_web::handlers::handlers::{
+    add_dependency, attach_tag, create_checklist_item, create_tag, create_task, create_user,
+    delete_checklist_item, delete_tag, delete_user_task, detach_tag, get_task_occurrences,
+    get_user_board, get_user_tags, get_user_task, get_user_tasks, get_users, move_user_task,
+    patch_user_task, refresh_token_handler, remove_dependency, rename_tag, sign_in_handler,
+    sign_out_handler, update_checklist_item, update_user_role, update_user_task,
 };
-use model::state::AppState;
+use task_management_actix_web::handlers::health::{healthz, readyz, version};
+use task_management_actix_web::handlers::metrics::metrics;
+use task_management_actix_web::handlers::openapi::ApiDoc;
+use task_management_actix_web::logging::logging;
+use task_management_actix_web::logging::middleware::{RequestIdentity, REQUEST_ID_HEADER};
+use task_management_actix_web::metrics::middleware::RequestMetrics;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
 async fn index() -> impl Responder {
-    format!("Hello, world!")
+    "Hello, world!"
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init();
+    logging::init();
     dotenv().ok();
 
-    let pool = create_pool_and_run_migrations().await?;
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let bind_address = settings.server.bind_address.clone();
+    let shutdown_timeout = settings.server.shutdown_timeout_secs;
 
-    println!("Starting server at http://127.0.0.1:8080");
+    let state = create_state(settings).await?;
 
+    log::info!("Starting server at http://{}", bind_address);
+
+    let openapi = ApiDoc::openapi();
+    let app_state = state.clone();
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:3000")
-            .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
+        let state = &app_state;
+        let cors = state
+            .settings
+            .server
+            .cors_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
             .allowed_headers(vec![
                 header::CONTENT_TYPE,
                 header::AUTHORIZATION,
                 header::ACCEPT,
+                REQUEST_ID_HEADER,
             ])
+            .expose_headers(vec![header::LINK, REQUEST_ID_HEADER])
             .supports_credentials();
 
         App::new()
-            .app_data(Data::new(AppState { pool: pool.clone() }))
+            .app_data(Data::new(state.clone()))
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+            .app_data(web::PathConfig::default().error_handler(path_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
             .wrap(cors)
+            .wrap(RequestMetrics)
+            .wrap(RequestIdentity)
+            .service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", openapi.clone()))
             .route("/", web::get().to(index))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .route("/version", web::get().to(version))
+            .route("/metrics", web::get().to(metrics))
             .route("/users", web::post().to(create_user))
             .route("/get_users", web::get().to(get_users))
+            .route("/users/{user_id}/role", web::put().to(update_user_role))
             .route("/users/{user_id}/tasks", web::post().to(create_task))
             .route("/users/{user_id}/tasks", web::get().to(get_user_tasks))
             .route(
@@ -54,13 +93,71 @@ async fn main() -> std::io::Result<()> {
                 "/users/{user_id}/tasks/{task_id}",
                 web::put().to(update_user_task),
             )
+            .route(
+                "/users/{user_id}/tasks/{task_id}",
+                web::patch().to(patch_user_task),
+            )
             .route(
                 "/users/{user_id}/tasks/{task_id}",
                 web::delete().to(delete_user_task),
             )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/move",
+                web::post().to(move_user_task),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/checklist",
+                web::post().to(create_checklist_item),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/checklist/{item_id}",
+                web::patch().to(update_checklist_item),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/checklist/{item_id}",
+                web::delete().to(delete_checklist_item),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/dependencies/{blocker_id}",
+                web::put().to(add_dependency),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/dependencies/{blocker_id}",
+                web::delete().to(remove_dependency),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/occurrences",
+                web::get().to(get_task_occurrences),
+            )
+            .route("/users/{user_id}/board", web::get().to(get_user_board))
+            .route("/users/{user_id}/tags", web::post().to(create_tag))
+            .route("/users/{user_id}/tags", web::get().to(get_user_tags))
+            .route("/users/{user_id}/tags/{tag_id}", web::put().to(rename_tag))
+            .route(
+                "/users/{user_id}/tags/{tag_id}",
+                web::delete().to(delete_tag),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/tags/{tag_id}",
+                web::put().to(attach_tag),
+            )
+            .route(
+                "/users/{user_id}/tasks/{task_id}/tags/{tag_id}",
+                web::delete().to(detach_tag),
+            )
             .route("/sign_in", web::post().to(sign_in_handler))
+            .route("/sign_out", web::post().to(sign_out_handler))
+            .route("/token/refresh", web::post().to(refresh_token_handler))
     })
-    .bind("127.0.0.1:8080")?
+    .bind(bind_address)?
+    .shutdown_timeout(shutdown_timeout)
     .run()
-    .await
+    .await?;
+
+    // The server stops on SIGTERM or SIGINT once in-flight requests are
+    // done or the shutdown timeout has passed
+    log::info!("Server stopped, closing database connections");
+    state.health.close().await;
+
+    Ok(())
 }
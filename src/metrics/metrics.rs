@@ -0,0 +1,94 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+// Connection counts of a database pool at one point in time
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStatus {
+    pub size: u32,
+    pub idle: usize,
+}
+
+// Every metric the service exports, registered in a registry of its own so
+// that separate app instances, e.g. in tests, never share counters
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub sign_in_failures: IntCounter,
+    db_pool_connections: IntGauge,
+    db_pool_idle_connections: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests",
+            ),
+            &["method", "route"],
+        )
+        .expect("valid metric");
+        let sign_in_failures = IntCounter::new(
+            "sign_in_failures_total",
+            "Sign in attempts rejected for invalid credentials",
+        )
+        .expect("valid metric");
+        let db_pool_connections = IntGauge::new("db_pool_connections", "Open database connections")
+            .expect("valid metric");
+        let db_pool_idle_connections =
+            IntGauge::new("db_pool_idle_connections", "Idle database connections")
+                .expect("valid metric");
+
+        let registry = Registry::new();
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(sign_in_failures.clone()),
+            Box::new(db_pool_connections.clone()),
+            Box::new(db_pool_idle_connections.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            sign_in_failures,
+            db_pool_connections,
+            db_pool_idle_connections,
+        }
+    }
+
+    // Renders every metric in the Prometheus text format. Pool gauges are
+    // sampled here rather than kept up to date, since they are only read on
+    // scrape.
+    pub fn render(&self, pool: Option<PoolStatus>) -> String {
+        if let Some(pool) = pool {
+            self.db_pool_connections.set(pool.size.into());
+            self.db_pool_idle_connections.set(pool.idle as i64);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding cannot fail");
+        String::from_utf8(buffer).expect("metrics are valid UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
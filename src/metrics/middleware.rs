@@ -0,0 +1,84 @@
+use crate::model::state::AppState;
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Instant;
+
+// Records the count, status class and latency of every request handled by
+// the wrapped app in the `Metrics` of its `AppState`.
+//
+// Requests are labelled with the route pattern, e.g.
+// `/users/{user_id}/tasks`, so that ids do not create a series each.
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let state = req.app_data::<web::Data<AppState>>().cloned();
+            let method = req.method().to_string();
+            let route = req
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".to_owned());
+            let start = Instant::now();
+
+            let result = service.call(req).await;
+
+            if let Some(state) = state {
+                let status = match &result {
+                    Ok(res) => res.status(),
+                    Err(e) => e.as_response_error().status_code(),
+                };
+                let status_class = format!("{}xx", status.as_u16() / 100);
+
+                let metrics = &state.metrics;
+                metrics
+                    .http_requests
+                    .with_label_values(&[&method, &route, &status_class])
+                    .inc();
+                metrics
+                    .http_request_duration
+                    .with_label_values(&[&method, &route])
+                    .observe(start.elapsed().as_secs_f64());
+            }
+
+            result
+        })
+    }
+}
@@ -1,2 +1,4 @@
 pub mod models;
+pub mod position;
+pub mod recurrence;
 pub mod state;
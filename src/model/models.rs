@@ -1,44 +1,429 @@
-use serde::{Deserialize, Serialize};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::types::chrono;
-use validator::Validate;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::model::recurrence::Recurrence;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Member,
+}
+
+// Never serialized: responses expose users through `PublicUser`
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct User {
     pub id: i32,
     pub username: String,
     pub password_hash: String,
     pub email: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PublicUser {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub role: Role,
+}
+
+impl From<User> for PublicUser {
+    fn from(user: User) -> Self {
+        PublicUser {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            role: user.role,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct UserInput {
-    #[validate(length(min = 1))]
+    #[validate(length(min = 1, message = "username must not be empty"))]
     pub username: String,
-    #[validate(length(min = 8))]
+    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub password: String,
-    #[validate(email)]
+    #[validate(email(message = "email must be a valid email address"))]
     pub email: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "task_status", rename_all = "snake_case")]
+pub enum TaskStatus {
+    #[default]
+    Todo,
+    InProgress,
+    Blocked,
+    Done,
+    Cancelled,
+}
+
+impl TaskStatus {
+    // In workflow order, which is also the column order of the board
+    pub const ALL: [TaskStatus; 5] = [
+        TaskStatus::Todo,
+        TaskStatus::InProgress,
+        TaskStatus::Blocked,
+        TaskStatus::Done,
+        TaskStatus::Cancelled,
+    ];
+
+    // The workflow a task follows. Staying in the same status is always
+    // allowed; finished tasks can only be reopened.
+    pub fn can_transition_to(self, next: TaskStatus) -> bool {
+        use TaskStatus::*;
+
+        self == next
+            || matches!(
+                (self, next),
+                (Todo, InProgress | Blocked | Done | Cancelled)
+                    | (InProgress, Todo | Blocked | Done | Cancelled)
+                    | (Blocked, Todo | InProgress | Cancelled)
+                    | (Done, Todo | InProgress)
+                    | (Cancelled, Todo)
+            )
+    }
+}
+
+impl std::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            TaskStatus::Todo => "todo",
+            TaskStatus::InProgress => "in_progress",
+            TaskStatus::Blocked => "blocked",
+            TaskStatus::Done => "done",
+            TaskStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Declared from lowest to highest, which is the order priorities sort in
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    sqlx::Type,
+    ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "task_priority", rename_all = "lowercase")]
+pub enum TaskPriority {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Urgent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Task {
     pub id: i32,
     pub title: String,
     pub description: String,
     pub due_date: Option<chrono::NaiveDateTime>,
-    pub status: String,
+    pub status: TaskStatus,
+    // Set when the task reaches `done`, cleared when it is reopened
+    pub completed_at: Option<chrono::NaiveDateTime>,
     pub user_id: i32,
+    pub priority: TaskPriority,
+    // Rank within the task's status column, compared byte by byte. See
+    // `model::position`.
+    pub position: String,
+    // The task this is a subtask of
+    pub parent_id: Option<i32>,
+    // RRULE the task repeats by, in canonical form. See `model::recurrence`.
+    pub recurrence: Option<String>,
+    // The fields below are loaded separately from the task row.
+    // In name order.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    // In creation order
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub checklist: Vec<ChecklistItem>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub progress: Progress,
+    // Ids of the tasks this one waits for, in id order
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub blocked_by: Vec<i32>,
+    // Whether any of `blocked_by` is neither done nor cancelled. Unrelated to
+    // the `blocked` status, which is set by hand.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+impl Task {
+    // The task to create as the next occurrence of this one once it is
+    // completed: a `todo` copy due at the rule's next date, taking over the
+    // rest of the rule. `None` without a recurrence or once the rule has run
+    // out. Rules are validated before they are stored, so this only fails if
+    // the database was edited by hand.
+    pub fn next_occurrence(&self) -> Result<Option<TaskInput>, String> {
+        let (Some(rule), Some(due_date)) = (self.recurrence.as_deref(), self.due_date) else {
+            return Ok(None);
+        };
+        let rule: Recurrence = rule.parse().map_err(|e| {
+            format!(
+                "Stored recurrence of task with id {} is invalid: {}",
+                self.id, e
+            )
+        })?;
+
+        Ok(rule
+            .next_occurrence(due_date)
+            .map(|(due_date, rest)| TaskInput {
+                title: self.title.clone(),
+                description: self.description.clone(),
+                due_date: Some(due_date),
+                status: TaskStatus::Todo,
+                priority: self.priority,
+                parent_id: self.parent_id,
+                recurrence: Some(rest.to_string()),
+            }))
+    }
+}
+
+// Finished steps of a task: its checklist items plus its direct subtasks.
+// Cancelled subtasks are left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Progress {
+    pub done: i64,
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ChecklistItem {
+    pub id: i32,
+    pub title: String,
+    pub done: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ChecklistItemInput {
+    #[validate(length(min = 1, max = 200, message = "title must be 1 to 200 characters"))]
+    pub title: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ChecklistItemPatch {
+    #[validate(length(min = 1, max = 200, message = "title must be 1 to 200 characters"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub done: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct TagInput {
+    #[validate(length(min = 1, max = 50, message = "name must be 1 to 50 characters"))]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct TaskInput {
-    #[validate(length(min = 1))]
+    #[validate(length(min = 1, message = "title must not be empty"))]
     pub title: String,
-    #[validate(length(min = 1))]
+    #[validate(length(min = 1, message = "description must not be empty"))]
     pub description: String,
     pub due_date: Option<chrono::NaiveDateTime>,
-    #[validate(length(min = 1))]
-    pub status: String,
+    #[serde(default)]
+    pub status: TaskStatus,
+    #[serde(default)]
+    pub priority: TaskPriority,
+    // Makes the task a subtask of another task of the same user
+    pub parent_id: Option<i32>,
+    // An RRULE such as `FREQ=WEEKLY;BYDAY=MO`; needs a `due_date`
+    #[validate(custom(function = "validate_recurrence"))]
+    pub recurrence: Option<String>,
+}
+
+fn validate_recurrence(rule: &str) -> Result<(), ValidationError> {
+    rule.parse::<Recurrence>()
+        .map(|_| ())
+        .map_err(|message| ValidationError::new("recurrence").with_message(message.into()))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSort {
+    // Creation order
+    #[default]
+    Id,
+    DueDate,
+    Title,
+    Priority,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// Query string of GET /users/{user_id}/tasks
+#[derive(Debug, Default, Serialize, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TaskQuery {
+    pub status: Option<TaskStatus>,
+    pub priority: Option<TaskPriority>,
+    // Only direct subtasks of this task
+    pub parent_id: Option<i32>,
+    pub due_before: Option<chrono::NaiveDateTime>,
+    pub due_after: Option<chrono::NaiveDateTime>,
+    // Past due and neither done nor cancelled
+    pub overdue: Option<bool>,
+    // Case-insensitive match on title or description
+    pub q: Option<String>,
+    // Name of a tag the task must carry
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub sort: TaskSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    #[validate(range(min = 1, max = 100, message = "limit must be between 1 and 100"))]
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+impl TaskQuery {
+    pub const DEFAULT_LIMIT: i64 = 50;
+}
+
+// Query string of GET /get_users
+#[derive(Debug, Default, Serialize, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserQuery {
+    // Case-insensitive prefix of the username or email
+    pub q: Option<String>,
+    #[validate(range(min = 1, max = 100, message = "limit must be between 1 and 100"))]
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    // Also count every matching user, ignoring paging
+    #[serde(default)]
+    pub include_total: bool,
+}
+
+impl UserQuery {
+    pub const DEFAULT_LIMIT: i64 = 50;
+}
+
+// Users are paged in id order, so the last id is all a cursor needs
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct UserCursor {
+    pub id: i32,
+}
+
+impl UserCursor {
+    pub fn encode(&self) -> String {
+        encode_cursor(self)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        decode_cursor(cursor)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UserPage {
+    pub users: Vec<PublicUser>,
+    pub next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+// Position after the last task of a page. Encoded as opaque base64 so clients
+// only ever pass it back through `cursor`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TaskCursor {
+    pub sort: TaskSort,
+    pub order: SortOrder,
+    pub id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<chrono::NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<TaskPriority>,
+}
+
+impl TaskCursor {
+    pub fn after(task: &Task, sort: TaskSort, order: SortOrder) -> Self {
+        TaskCursor {
+            sort,
+            order,
+            id: task.id,
+            due_date: if sort == TaskSort::DueDate {
+                task.due_date
+            } else {
+                None
+            },
+            title: if sort == TaskSort::Title {
+                Some(task.title.clone())
+            } else {
+                None
+            },
+            priority: if sort == TaskSort::Priority {
+                Some(task.priority)
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        encode_cursor(self)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        decode_cursor(cursor)
+    }
+}
+
+fn encode_cursor<T: Serialize>(cursor: &T) -> String {
+    let json = serde_json::to_vec(cursor).expect("cursor serializes");
+    URL_SAFE_NO_PAD.encode(json)
+}
+
+fn decode_cursor<T: serde::de::DeserializeOwned>(cursor: &str) -> Option<T> {
+    let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TaskPage {
+    pub tasks: Vec<Task>,
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -47,31 +432,270 @@ pub struct Info {
     pub task_id: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagInfo {
+    pub user_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChecklistItemInfo {
+    pub user_id: i32,
+    pub task_id: i32,
+    pub item_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DependencyInfo {
+    pub user_id: i32,
+    pub task_id: i32,
+    pub blocker_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaskTagInfo {
+    pub user_id: i32,
+    pub task_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct TaskUpdate {
-    #[validate(length(min = 1))]
+    #[validate(length(min = 1, message = "title must not be empty"))]
     pub title: String,
-    #[validate(length(min = 1))]
+    #[validate(length(min = 1, message = "description must not be empty"))]
     pub description: String,
     pub due_date: Option<chrono::NaiveDateTime>,
-    #[validate(length(min = 1))]
-    pub status: String,
+    pub status: TaskStatus,
+    #[serde(default)]
+    pub priority: TaskPriority,
+    // Left out, the task stops repeating
+    #[validate(custom(function = "validate_recurrence"))]
+    pub recurrence: Option<String>,
+}
+
+// Body of a PATCH: only the supplied fields are updated. `due_date` tells an
+// absent field (`None`) apart from an explicit `null` (`Some(None)`).
+#[derive(Debug, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct TaskPatch {
+    #[validate(length(min = 1, message = "title must not be empty"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[validate(length(min = 1, message = "description must not be empty"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub due_date: Option<Option<chrono::NaiveDateTime>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<TaskStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<TaskPriority>,
+    // `null` makes the task a top-level task again
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub parent_id: Option<Option<i32>>,
+    // `null` stops the task from repeating
+    #[validate(custom(function = "validate_recurrence"))]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recurrence: Option<Option<String>>,
+}
+
+impl TaskPatch {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.due_date.is_none()
+            && self.status.is_none()
+            && self.priority.is_none()
+            && self.parent_id.is_none()
+            && self.recurrence.is_none()
+    }
+}
+
+// Body of POST /users/{user_id}/tasks/{task_id}/move. The task goes right
+// before `before_id` or right after `after_id`, in that task's column; with
+// neither, to the end of the `status` column, or of its own column.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct TaskMove {
+    pub status: Option<TaskStatus>,
+    pub before_id: Option<i32>,
+    pub after_id: Option<i32>,
+}
+
+// Where a move puts a task within its new column: right before or right
+// after another task of that column, or at its end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Before(i32),
+    After(i32),
+    End,
+}
+
+// Subtasks nest at most this many levels below a top-level task
+pub const MAX_SUBTASK_DEPTH: usize = 3;
+
+// Why a task cannot go under the parent it was given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidParent {
+    // No task of the user has the parent's id
+    Missing,
+    // The parent is the task itself or one of its subtasks
+    Cycle,
+    // The task's subtasks would nest deeper than `MAX_SUBTASK_DEPTH`
+    TooDeep,
 }
 
-#[derive(Deserialize)]
+impl InvalidParent {
+    // Checks a task with `below` levels of subtasks going under the existing
+    // task `parent_id`, whose parent, grandparent and so on are `ancestors`.
+    // `task_id` is `None` for a new task.
+    pub fn check(
+        task_id: Option<i32>,
+        parent_id: i32,
+        ancestors: &[i32],
+        below: usize,
+    ) -> Option<Self> {
+        if task_id.is_some_and(|task_id| task_id == parent_id || ancestors.contains(&task_id)) {
+            return Some(Self::Cycle);
+        }
+        // The task would sit one level below its parent
+        (ancestors.len() + 1 + below > MAX_SUBTASK_DEPTH).then_some(Self::TooDeep)
+    }
+}
+
+// Outcome of a write that may complete a task or give it a parent, returned
+// and matched right away
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum TaskWrite {
+    Written(Task),
+    // Nothing was written: the task, or a subtask it would complete, waits
+    // for these open tasks, in id order
+    Blocked(Vec<i32>),
+    // Nothing was written: the task cannot go under its new parent
+    InvalidParent(InvalidParent),
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BoardColumn {
+    pub status: TaskStatus,
+    // In position order
+    pub tasks: Vec<Task>,
+}
+
+// Every task of a user grouped by status, one column per status in workflow
+// order, including empty ones
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Board {
+    pub columns: Vec<BoardColumn>,
+}
+
+// Query string of GET /users/{user_id}/tasks/{task_id}/occurrences
+#[derive(Debug, Default, Serialize, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OccurrenceQuery {
+    #[validate(range(min = 1, max = 100, message = "limit must be between 1 and 100"))]
+    pub limit: Option<usize>,
+}
+
+impl OccurrenceQuery {
+    pub const DEFAULT_LIMIT: usize = 10;
+}
+
+// Upcoming due dates of a task, starting with its own. A task without a
+// recurrence has only its own, or none without a due date.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Occurrences {
+    pub recurrence: Option<String>,
+    pub occurrences: Vec<chrono::NaiveDateTime>,
+}
+
+// Wraps any present value, including `null`, in `Some`; absent fields fall
+// back to `None` through `#[serde(default)]`
+fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RoleInput {
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct SignInInput {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String,
+    // Refresh token family the access token was issued for
+    pub sid: String,
+    pub role: Role,
     pub exp: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct UserResponse {
-    pub user: User,
+    pub user: PublicUser,
+    pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub family_id: Uuid,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RefreshInput {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TokenResponse {
     pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Health {
+    pub status: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Readiness {
+    pub status: String,
+    // Why the service is not ready, absent when it is
+    pub reason: Option<String>,
+    pub schema_version: Option<i64>,
+    pub expected_schema_version: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_sha: String,
+    // Latest migration this build ships
+    pub schema_version: i64,
 }
@@ -0,0 +1,105 @@
+// Fractional positions for ordering tasks within a board column. Positions
+// are strings compared byte by byte, and there is always room for a new one
+// between any two, so moving a task never renumbers its neighbours.
+//
+// Digits are base 62 in ASCII order. No generated position ends in the
+// lowest digit, which leaves room before every position.
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+fn digit_value(digit: u8) -> usize {
+    DIGITS.iter().position(|&d| d == digit).unwrap_or(0)
+}
+
+// A position strictly between `lower` and `upper`, where `None` stands for the
+// start or the end of the column. `None` when `upper` does not sort after
+// `lower`, as no position fits between them.
+pub fn position_between(lower: Option<&str>, upper: Option<&str>) -> Option<String> {
+    let lower = lower.unwrap_or("");
+    let upper = match upper {
+        Some(upper) if upper <= lower => return None,
+        upper => upper,
+    };
+
+    let position = midpoint(lower.as_bytes(), upper.map(str::as_bytes));
+    Some(String::from_utf8(position).expect("positions are ASCII"))
+}
+
+// A position after `lower`, or the first one of an empty column
+pub fn position_after(lower: Option<&str>) -> String {
+    position_between(lower, None).expect("there is always room at the end")
+}
+
+fn midpoint(lower: &[u8], upper: Option<&[u8]>) -> Vec<u8> {
+    if let Some(upper) = upper {
+        // Keep the common prefix, reading missing digits of `lower` as zeros
+        let common = upper
+            .iter()
+            .enumerate()
+            .take_while(|(i, &digit)| lower.get(*i).copied().unwrap_or(DIGITS[0]) == digit)
+            .count();
+        if common > 0 {
+            let mut position = upper[..common].to_vec();
+            position.extend(midpoint(
+                lower.get(common..).unwrap_or_default(),
+                Some(&upper[common..]),
+            ));
+            return position;
+        }
+    }
+
+    let low = lower.first().map_or(0, |&digit| digit_value(digit));
+    let high = upper.map_or(DIGITS.len(), |upper| digit_value(upper[0]));
+    if high - low > 1 {
+        vec![DIGITS[(low + high) / 2]]
+    } else if let Some(upper) = upper.filter(|upper| upper.len() > 1) {
+        upper[..1].to_vec()
+    } else {
+        let mut position = vec![DIGITS[low]];
+        position.extend(midpoint(lower.get(1..).unwrap_or_default(), None));
+        position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{position_after, position_between};
+
+    fn assert_between(lower: Option<&str>, upper: Option<&str>) -> String {
+        let position = position_between(lower, upper).unwrap();
+        assert!(lower.is_none_or(|lower| lower < position.as_str()));
+        assert!(upper.is_none_or(|upper| position.as_str() < upper));
+        assert!(!position.ends_with('0'), "{}", position);
+        position
+    }
+
+    #[test]
+    fn test_positions_stay_ordered() {
+        assert_eq!(position_between(None, None).as_deref(), Some("V"));
+        assert_eq!(
+            position_between(Some("V"), Some("W")).as_deref(),
+            Some("VV")
+        );
+        assert_between(Some("0000000012V"), None);
+        assert_between(Some("0000000012V"), Some("0000000013V"));
+        assert_between(Some("z"), None);
+        assert_between(None, Some("01"));
+
+        // Appending, prepending and always inserting at the same spot
+        let start = assert_between(None, None);
+        let mut next = assert_between(Some(&start), None);
+        let (mut first, mut last) = (start.clone(), next.clone());
+        for _ in 0..200 {
+            last = assert_between(Some(&last), None);
+            first = assert_between(None, Some(&first));
+            next = assert_between(Some(&start), Some(&next));
+        }
+    }
+
+    #[test]
+    fn test_bounds_out_of_order_have_no_position() {
+        assert_eq!(position_between(Some("W"), Some("V")), None);
+        assert_eq!(position_between(Some("V"), Some("V")), None);
+        assert_eq!(position_between(None, Some("")), None);
+        assert!(position_after(Some("z")).as_str() > "z");
+    }
+}
@@ -0,0 +1,460 @@
+// Recurrence rules for tasks, written as RFC 5545 RRULEs such as
+// `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;COUNT=10`. The supported subset is FREQ
+// (DAILY, WEEKLY or MONTHLY), INTERVAL, BYDAY, and COUNT or UNTIL. Weeks start
+// on Monday, and an ordinal in BYDAY (`1MO`, `-1FR`) is only allowed with
+// MONTHLY.
+//
+// The task's due date plays the part of DTSTART: it is always the first
+// occurrence, and later ones keep its time of day. Times are UTC like every
+// other timestamp of the API.
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+// Periods looked at before giving up on finding another occurrence, so a
+// rule that can never match (`FREQ=DAILY;INTERVAL=7;BYDAY=TU` from a Monday)
+// ends instead of spinning
+const MAX_PERIODS: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+// One BYDAY entry: a weekday, with the ordinal picking one of its days in the
+// month (`2TU` is the second Tuesday, `-1FR` the last Friday)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeekdayNum {
+    pub ordinal: Option<i8>,
+    pub weekday: Weekday,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<WeekdayNum>,
+    // Occurrences left, counting the first one
+    pub count: Option<u32>,
+    // Last moment an occurrence may fall on, inclusive
+    pub until: Option<NaiveDateTime>,
+}
+
+impl Recurrence {
+    // Occurrences in order, starting with `start` itself
+    pub fn occurrences(&self, start: NaiveDateTime) -> impl Iterator<Item = NaiveDateTime> + '_ {
+        let later = (0..MAX_PERIODS)
+            .map_while(move |period| self.period_dates(start.date(), period))
+            .flatten()
+            .map(move |date| date.and_time(start.time()))
+            .filter(move |occurrence| *occurrence > start);
+
+        std::iter::once(start)
+            .chain(later)
+            .take_while(|occurrence| self.until.is_none_or(|until| *occurrence <= until))
+            .take(self.count.map_or(usize::MAX, |count| count as usize))
+    }
+
+    // The occurrence after `start`, with the rule the task due then carries:
+    // this one, less the occurrence at `start` when it has a COUNT
+    pub fn next_occurrence(&self, start: NaiveDateTime) -> Option<(NaiveDateTime, Recurrence)> {
+        let next = self.occurrences(start).nth(1)?;
+        let rest = Recurrence {
+            count: self.count.map(|count| count - 1),
+            ..self.clone()
+        };
+        Some((next, rest))
+    }
+
+    // Candidate dates of the `period`th period after the one holding `start`,
+    // in order. `None` once the dates leave chrono's range.
+    fn period_dates(&self, start: NaiveDate, period: u32) -> Option<Vec<NaiveDate>> {
+        let steps = period.checked_mul(self.interval)?;
+        let mut dates: Vec<NaiveDate> = match self.frequency {
+            Frequency::Daily => {
+                let date = start.checked_add_days(Days::new(steps.into()))?;
+                vec![date]
+                    .into_iter()
+                    .filter(|date| self.by_day.is_empty() || self.has_weekday(date.weekday()))
+                    .collect()
+            }
+            Frequency::Weekly => {
+                let monday = start.week(Weekday::Mon).first_day();
+                let monday = monday.checked_add_days(Days::new(u64::from(steps) * 7))?;
+                let weekdays = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|day| day.weekday).collect()
+                };
+                weekdays
+                    .into_iter()
+                    .filter_map(|weekday| {
+                        monday.checked_add_days(Days::new(weekday.num_days_from_monday().into()))
+                    })
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let month =
+                    i64::from(start.year()) * 12 + i64::from(start.month0()) + i64::from(steps);
+                let year = i32::try_from(month.div_euclid(12)).ok()?;
+                let first = NaiveDate::from_ymd_opt(year, month.rem_euclid(12) as u32 + 1, 1)?;
+                if self.by_day.is_empty() {
+                    // Months too short for the start's day are skipped
+                    first.with_day(start.day()).into_iter().collect()
+                } else {
+                    self.by_day
+                        .iter()
+                        .flat_map(|day| month_days(first, *day))
+                        .collect()
+                }
+            }
+        };
+        dates.sort();
+        dates.dedup();
+        Some(dates)
+    }
+
+    fn has_weekday(&self, weekday: Weekday) -> bool {
+        self.by_day.iter().any(|day| day.weekday == weekday)
+    }
+}
+
+// Days of the month starting on `first` matching `day`
+fn month_days(first: NaiveDate, day: WeekdayNum) -> Vec<NaiveDate> {
+    let offset =
+        (7 + day.weekday.num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+    let days: Vec<NaiveDate> = (0..5)
+        .filter_map(|week| first.checked_add_days(Days::new(u64::from(offset + week * 7))))
+        .filter(|date| date.month() == first.month())
+        .collect();
+
+    match day.ordinal {
+        None => days,
+        Some(ordinal) if ordinal > 0 => days
+            .get(ordinal as usize - 1)
+            .copied()
+            .into_iter()
+            .collect(),
+        Some(ordinal) => days
+            .len()
+            .checked_sub(ordinal.unsigned_abs() as usize)
+            .and_then(|i| days.get(i).copied())
+            .into_iter()
+            .collect(),
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim();
+        let rule = rule
+            .get(..6)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("RRULE:"))
+            .map_or(rule, |_| &rule[6..]);
+
+        let mut frequency = None;
+        let mut interval = None;
+        let mut by_day = None;
+        let mut count = None;
+        let mut until = None;
+        for part in rule.split(';') {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("`{part}` is not a NAME=VALUE pair"))?;
+            let name = name.trim().to_ascii_uppercase();
+            let value = value.trim().to_ascii_uppercase();
+            match name.as_str() {
+                "FREQ" => set(&mut frequency, &name, parse_frequency(&value)?)?,
+                "INTERVAL" => set(&mut interval, &name, parse_positive(&name, &value)?)?,
+                "BYDAY" => set(&mut by_day, &name, parse_by_day(&value)?)?,
+                "COUNT" => set(&mut count, &name, parse_positive(&name, &value)?)?,
+                "UNTIL" => set(&mut until, &name, parse_until(&value)?)?,
+                _ => return Err(format!("{name} is not supported")),
+            }
+        }
+
+        let frequency = frequency.ok_or("FREQ is required")?;
+        let by_day: Vec<WeekdayNum> = by_day.unwrap_or_default();
+        if count.is_some() && until.is_some() {
+            return Err("COUNT and UNTIL cannot both be given".to_string());
+        }
+        if frequency != Frequency::Monthly && by_day.iter().any(|day| day.ordinal.is_some()) {
+            return Err("BYDAY ordinals are only allowed with FREQ=MONTHLY".to_string());
+        }
+
+        Ok(Recurrence {
+            frequency,
+            interval: interval.unwrap_or(1),
+            by_day,
+            count,
+            until,
+        })
+    }
+}
+
+fn set<T>(slot: &mut Option<T>, name: &str, value: T) -> Result<(), String> {
+    if slot.replace(value).is_some() {
+        return Err(format!("{name} is given more than once"));
+    }
+    Ok(())
+}
+
+fn parse_frequency(value: &str) -> Result<Frequency, String> {
+    match value {
+        "DAILY" => Ok(Frequency::Daily),
+        "WEEKLY" => Ok(Frequency::Weekly),
+        "MONTHLY" => Ok(Frequency::Monthly),
+        _ => Err(format!(
+            "FREQ={value} is not supported, use DAILY, WEEKLY or MONTHLY"
+        )),
+    }
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|number| *number > 0)
+        .ok_or_else(|| format!("{name} must be a positive integer"))
+}
+
+fn parse_by_day(value: &str) -> Result<Vec<WeekdayNum>, String> {
+    let mut days: Vec<WeekdayNum> = Vec::new();
+    for entry in value.split(',') {
+        let invalid = || format!("`{entry}` is not a BYDAY entry like MO, 1MO or -1FR");
+        let split = entry.len().checked_sub(2).ok_or_else(invalid)?;
+        let (ordinal, weekday) = entry.split_at_checked(split).ok_or_else(invalid)?;
+        let weekday = match weekday {
+            "MO" => Weekday::Mon,
+            "TU" => Weekday::Tue,
+            "WE" => Weekday::Wed,
+            "TH" => Weekday::Thu,
+            "FR" => Weekday::Fri,
+            "SA" => Weekday::Sat,
+            "SU" => Weekday::Sun,
+            _ => return Err(invalid()),
+        };
+        let ordinal = match ordinal {
+            "" => None,
+            ordinal => Some(
+                ordinal
+                    .parse::<i8>()
+                    .ok()
+                    .filter(|ordinal| (1..=5).contains(&ordinal.abs()))
+                    .ok_or_else(|| {
+                        format!("`{entry}` needs an ordinal between -5 and 5, other than 0")
+                    })?,
+            ),
+        };
+        let day = WeekdayNum { ordinal, weekday };
+        if !days.contains(&day) {
+            days.push(day);
+        }
+    }
+    Ok(days)
+}
+
+// `YYYYMMDDTHHMMSS` with an optional `Z`, or a bare date standing for the
+// end of that day
+fn parse_until(value: &str) -> Result<NaiveDateTime, String> {
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .map(|date| date.and_time(NaiveTime::from_hms_opt(23, 59, 59).expect("valid time")))
+        })
+        .map_err(|_| "UNTIL must look like 20261231 or 20261231T235959Z".to_string())
+}
+
+// The canonical form stored with the task
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+        };
+        write!(f, "FREQ={frequency}")?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<String> = self
+                .by_day
+                .iter()
+                .map(|day| {
+                    let weekday = &day.weekday.to_string().to_ascii_uppercase()[..2];
+                    match day.ordinal {
+                        Some(ordinal) => format!("{ordinal}{weekday}"),
+                        None => weekday.to_string(),
+                    }
+                })
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Recurrence;
+    use chrono::NaiveDateTime;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn occurrences(rule: &str, start: &str, limit: usize) -> Vec<String> {
+        let rule: Recurrence = rule.parse().unwrap();
+        rule.occurrences(at(start))
+            .take(limit)
+            .map(|occurrence| occurrence.format("%Y-%m-%d %a %H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let canonical = |rule: &str| rule.parse::<Recurrence>().map(|rule| rule.to_string());
+
+        assert_eq!(canonical("FREQ=DAILY").unwrap(), "FREQ=DAILY");
+        assert_eq!(
+            canonical("rrule:freq=weekly;byday=mo,th,mo;interval=2;count=10").unwrap(),
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;COUNT=10"
+        );
+        assert_eq!(
+            canonical("FREQ=MONTHLY;BYDAY=-1FR;UNTIL=20261231").unwrap(),
+            "FREQ=MONTHLY;BYDAY=-1FR;UNTIL=20261231T235959Z"
+        );
+        assert_eq!(
+            canonical("FREQ=MONTHLY;INTERVAL=1").unwrap(),
+            "FREQ=MONTHLY"
+        );
+
+        for invalid in [
+            "",
+            "INTERVAL=2",
+            "FREQ=YEARLY",
+            "FREQ=DAILY;FREQ=WEEKLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;COUNT=-1",
+            "FREQ=DAILY;COUNT=2;UNTIL=20261231",
+            "FREQ=DAILY;UNTIL=tomorrow",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "FREQ=MONTHLY;BYDAY=6MO",
+            "FREQ=MONTHLY;BYDAY=0MO",
+            "FREQ=DAILY;BYHOUR=9",
+            "FREQ=DAILY;",
+        ] {
+            assert!(
+                invalid.parse::<Recurrence>().is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_daily_and_weekly_occurrences() {
+        assert_eq!(
+            occurrences("FREQ=DAILY;INTERVAL=2;COUNT=3", "2026-10-30 09:00", 10),
+            [
+                "2026-10-30 Fri 09:00",
+                "2026-11-01 Sun 09:00",
+                "2026-11-03 Tue 09:00"
+            ]
+        );
+        // Weekdays only
+        assert_eq!(
+            occurrences("FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR", "2026-10-16 08:30", 3),
+            [
+                "2026-10-16 Fri 08:30",
+                "2026-10-19 Mon 08:30",
+                "2026-10-20 Tue 08:30"
+            ]
+        );
+        assert_eq!(
+            occurrences("FREQ=WEEKLY;INTERVAL=2;BYDAY=TH,MO", "2026-10-14 18:00", 5),
+            [
+                "2026-10-14 Wed 18:00",
+                "2026-10-15 Thu 18:00",
+                "2026-10-26 Mon 18:00",
+                "2026-10-29 Thu 18:00",
+                "2026-11-09 Mon 18:00",
+            ]
+        );
+        assert_eq!(
+            occurrences("FREQ=WEEKLY;UNTIL=20261101", "2026-10-17 10:00", 10),
+            [
+                "2026-10-17 Sat 10:00",
+                "2026-10-24 Sat 10:00",
+                "2026-10-31 Sat 10:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monthly_occurrences() {
+        // Months without a 31st are skipped
+        assert_eq!(
+            occurrences("FREQ=MONTHLY", "2027-01-31 12:00", 3),
+            [
+                "2027-01-31 Sun 12:00",
+                "2027-03-31 Wed 12:00",
+                "2027-05-31 Mon 12:00"
+            ]
+        );
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;BYDAY=-1FR", "2026-10-01 17:00", 3),
+            [
+                "2026-10-01 Thu 17:00",
+                "2026-10-30 Fri 17:00",
+                "2026-11-27 Fri 17:00"
+            ]
+        );
+        assert_eq!(
+            occurrences(
+                "FREQ=MONTHLY;INTERVAL=3;BYDAY=1MO,3MO",
+                "2026-10-05 09:00",
+                4
+            ),
+            [
+                "2026-10-05 Mon 09:00",
+                "2026-10-19 Mon 09:00",
+                "2027-01-04 Mon 09:00",
+                "2027-01-18 Mon 09:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let rule: Recurrence = "FREQ=WEEKLY;BYDAY=MO,TH;COUNT=3".parse().unwrap();
+        let (next, rest) = rule.next_occurrence(at("2026-10-19 09:00")).unwrap();
+        assert_eq!(next, at("2026-10-22 09:00"));
+        assert_eq!(rest.to_string(), "FREQ=WEEKLY;BYDAY=MO,TH;COUNT=2");
+
+        let (next, rest) = rest.next_occurrence(next).unwrap();
+        assert_eq!(next, at("2026-10-26 09:00"));
+        assert_eq!(rest.count, Some(1));
+        assert!(rest.next_occurrence(next).is_none());
+
+        let rule: Recurrence = "FREQ=DAILY;UNTIL=20261020T090000Z".parse().unwrap();
+        assert!(rule.next_occurrence(at("2026-10-20 09:00")).is_none());
+
+        // Never matches again after the start, but still ends
+        let rule: Recurrence = "FREQ=DAILY;INTERVAL=7;BYDAY=TU".parse().unwrap();
+        assert!(rule.next_occurrence(at("2026-10-19 09:00")).is_none());
+    }
+}
@@ -1,5 +1,76 @@
+use crate::config::settings::Settings;
+use crate::db::memory::MemoryRepository;
+use crate::db::postgres::PgRepository;
+use crate::db::repository::{
+    ChecklistRepository, DependencyRepository, HealthRepository, RefreshTokenRepository,
+    TagRepository, TaskRepository, UserRepository,
+};
+#[cfg(feature = "sqlite")]
+use crate::db::sqlite::SqliteRepository;
+use crate::metrics::metrics::Metrics;
 use sqlx::PgPool;
+use std::sync::Arc;
 
+// Shared by every worker. Handlers only see the repository traits, so the
+// storage backend is chosen once, where the state is built, together with
+// the settings loaded at startup.
+#[derive(Clone)]
 pub struct AppState {
-    pub pool: PgPool,
+    pub users: Arc<dyn UserRepository>,
+    pub tasks: Arc<dyn TaskRepository>,
+    pub tags: Arc<dyn TagRepository>,
+    pub checklists: Arc<dyn ChecklistRepository>,
+    pub dependencies: Arc<dyn DependencyRepository>,
+    pub refresh_tokens: Arc<dyn RefreshTokenRepository>,
+    pub health: Arc<dyn HealthRepository>,
+    pub settings: Arc<Settings>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl AppState {
+    pub fn postgres(pool: PgPool, settings: Settings) -> Self {
+        let repository = Arc::new(PgRepository::new(pool));
+        AppState {
+            users: repository.clone(),
+            tasks: repository.clone(),
+            tags: repository.clone(),
+            checklists: repository.clone(),
+            dependencies: repository.clone(),
+            refresh_tokens: repository.clone(),
+            health: repository,
+            settings: Arc::new(settings),
+            metrics: Arc::new(Metrics::new()),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(pool: sqlx::SqlitePool, settings: Settings) -> Self {
+        let repository = Arc::new(SqliteRepository::new(pool));
+        AppState {
+            users: repository.clone(),
+            tasks: repository.clone(),
+            tags: repository.clone(),
+            checklists: repository.clone(),
+            dependencies: repository.clone(),
+            refresh_tokens: repository.clone(),
+            health: repository,
+            settings: Arc::new(settings),
+            metrics: Arc::new(Metrics::new()),
+        }
+    }
+
+    pub fn in_memory(settings: Settings) -> Self {
+        let repository = Arc::new(MemoryRepository::new());
+        AppState {
+            users: repository.clone(),
+            tasks: repository.clone(),
+            tags: repository.clone(),
+            checklists: repository.clone(),
+            dependencies: repository.clone(),
+            refresh_tokens: repository.clone(),
+            health: repository,
+            settings: Arc::new(settings),
+            metrics: Arc::new(Metrics::new()),
+        }
+    }
 }